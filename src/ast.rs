@@ -0,0 +1,202 @@
+use std::fmt;
+
+use crate::index::IndexDefinition;
+use crate::table::{Column, Value};
+
+//One parsed SQL statement. The parser builds these without touching the
+//database, the executor runs them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Select {
+        table: String,
+        columns: Vec<Expr>,
+        filter: Option<Expr>,
+    },
+    Insert {
+        table: String,
+        columns: Vec<String>,
+        values: Vec<Expr>,
+    },
+    Update {
+        table: String,
+        assignments: Vec<(String, Expr)>,
+        filter: Option<Expr>,
+    },
+    Delete {
+        table: String,
+        filter: Option<Expr>,
+    },
+    CreateTable {
+        name: String,
+        columns: Vec<Column>,
+        clustered_key: Option<String>,
+    },
+    CreateIndex {
+        table: String,
+        definition: IndexDefinition,
+    },
+    DropTable {
+        name: String,
+        if_exists: bool,
+    },
+    TruncateTable {
+        name: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum UnaryOperator {
+    NEGATE,
+    NOT,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum BinaryOperator {
+    OR,
+    AND,
+    EQUALS,
+    NOTEQUALS,
+    LESS,
+    LESSEQUALS,
+    GREATER,
+    GREATEREQUALS,
+    CONCAT,
+    PLUS,
+    MINUS,
+    MULTIPLY,
+    DIVIDE,
+    MODULO,
+}
+
+impl BinaryOperator {
+    //Binding strength, higher binds tighter. NOT sits between AND and the comparisons.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperator::OR => 1,
+            BinaryOperator::AND => 2,
+            BinaryOperator::EQUALS
+            | BinaryOperator::NOTEQUALS
+            | BinaryOperator::LESS
+            | BinaryOperator::LESSEQUALS
+            | BinaryOperator::GREATER
+            | BinaryOperator::GREATEREQUALS => COMPARISON_PRECEDENCE,
+            BinaryOperator::CONCAT => 5,
+            BinaryOperator::PLUS | BinaryOperator::MINUS => 6,
+            BinaryOperator::MULTIPLY | BinaryOperator::DIVIDE | BinaryOperator::MODULO => 7,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOperator::OR => "OR",
+            BinaryOperator::AND => "AND",
+            BinaryOperator::EQUALS => "=",
+            BinaryOperator::NOTEQUALS => "<>",
+            BinaryOperator::LESS => "<",
+            BinaryOperator::LESSEQUALS => "<=",
+            BinaryOperator::GREATER => ">",
+            BinaryOperator::GREATEREQUALS => ">=",
+            BinaryOperator::CONCAT => "||",
+            BinaryOperator::PLUS => "+",
+            BinaryOperator::MINUS => "-",
+            BinaryOperator::MULTIPLY => "*",
+            BinaryOperator::DIVIDE => "/",
+            BinaryOperator::MODULO => "%",
+        }
+    }
+}
+
+pub const NOT_PRECEDENCE: u8 = 3;
+//Also the precedence of IS NULL, IN, BETWEEN, LIKE and MATCH.
+pub const COMPARISON_PRECEDENCE: u8 = 4;
+pub const NEGATE_PRECEDENCE: u8 = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    Null,
+    //`column` or `table.column`, `*` selects every column.
+    Column { table: Option<String>, name: String },
+    //`?` or `$n`, numbered from 1.
+    Placeholder(usize),
+    Unary { operator: UnaryOperator, expr: Box<Expr> },
+    Binary { left: Box<Expr>, operator: BinaryOperator, right: Box<Expr> },
+    //Function names are kept uppercase.
+    Function { name: String, args: Vec<Expr> },
+    IsNull { expr: Box<Expr>, negated: bool },
+    InList { expr: Box<Expr>, list: Vec<Expr>, negated: bool },
+    Between { expr: Box<Expr>, low: Box<Expr>, high: Box<Expr>, negated: bool },
+    Like { expr: Box<Expr>, pattern: Box<Expr>, negated: bool },
+    //`column MATCH terms...`, the terms joined by spaces form the query.
+    Match { column: String, terms: Vec<Expr> },
+}
+
+impl Expr {
+    pub fn column(name: &str) -> Expr {
+        Expr::Column { table: None, name: name.to_string() }
+    }
+
+    //Name of a plain column reference, qualified or not.
+    pub fn column_name(&self) -> Option<&str> {
+        match self {
+            Expr::Column { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+}
+
+//Writes the expression back as SQL, nested operators are parenthesized.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let not = |negated: &bool| if *negated { "NOT " } else { "" };
+        let operand = |expr: &Expr| match expr {
+            Expr::Binary { .. }
+            | Expr::Unary { operator: UnaryOperator::NOT, .. }
+            | Expr::IsNull { .. }
+            | Expr::InList { .. }
+            | Expr::Between { .. }
+            | Expr::Like { .. }
+            | Expr::Match { .. } => format!("({})", expr),
+            _ => expr.to_string(),
+        };
+        match self {
+            Expr::Literal(Value::String(value)) => write!(f, "'{}'", value.replace('\'', "''")),
+            Expr::Literal(value) => write!(f, "{}", value),
+            Expr::Null => write!(f, "NULL"),
+            Expr::Column { table: Some(table), name } => write!(f, "{}.{}", table, name),
+            Expr::Column { table: None, name } => write!(f, "{}", name),
+            Expr::Placeholder(number) => write!(f, "${}", number),
+            Expr::Unary { operator: UnaryOperator::NEGATE, expr } => write!(f, "-{}", operand(expr)),
+            Expr::Unary { operator: UnaryOperator::NOT, expr } => write!(f, "NOT {}", operand(expr)),
+            Expr::Binary { left, operator, right } => {
+                write!(f, "{} {} {}", operand(left), operator.symbol(), operand(right))
+            }
+            Expr::Function { name, args } => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+            Expr::IsNull { expr, negated } => write!(f, "{} IS {}NULL", operand(expr), not(negated)),
+            Expr::InList { expr, list, negated } => {
+                let list: Vec<String> = list.iter().map(|item| item.to_string()).collect();
+                write!(f, "{} {}IN ({})", operand(expr), not(negated), list.join(", "))
+            }
+            Expr::Between { expr, low, high, negated } => write!(
+                f,
+                "{} {}BETWEEN {} AND {}",
+                operand(expr),
+                not(negated),
+                operand(low),
+                operand(high)
+            ),
+            Expr::Like { expr, pattern, negated } => {
+                write!(f, "{} {}LIKE {}", operand(expr), not(negated), operand(pattern))
+            }
+            Expr::Match { column, terms } => {
+                let terms: Vec<String> = terms.iter().map(|term| term.to_string()).collect();
+                write!(f, "{} MATCH {}", column, terms.join(" "))
+            }
+        }
+    }
+}
@@ -1,23 +1,820 @@
-
-use uuid::Uuid;
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::fmt;
+use std::ops::Bound;
 
+use crate::key::IndexKey;
 
-
-enum Node {
-    Leaf(LeafNode),
-    Internal(InternalNode),
+//Nodes are shared handles so that leaves can be linked through `next`
+//while still being owned by their parent.
+enum Node<K> {
+    Leaf(Rc<RefCell<LeafNode<K>>>),
+    Internal(Rc<RefCell<InternalNode<K>>>),
 }
 
 
-struct LeafNode{
-    keys: Vec<Uuid>,
+struct LeafNode<K>{
+    keys: Vec<K>,
     values: Vec<Vec<u8>>,
-    next: Option<Rc<RefCell<LeafNode>>>,
+    next: Option<Rc<RefCell<LeafNode<K>>>>,
+}
+
+struct InternalNode<K>{
+    keys: Vec<K>,
+    children: Vec<Node<K>>,
+}
+
+//Derived Clone would require K: Clone, cloning a handle never clones keys.
+impl<K> Clone for Node<K> {
+    fn clone(&self) -> Self {
+        match self {
+            Node::Leaf(leaf) => Node::Leaf(Rc::clone(leaf)),
+            Node::Internal(internal) => Node::Internal(Rc::clone(internal)),
+        }
+    }
+}
+
+impl<K> Node<K> {
+    fn new_leaf() -> Self {
+        Node::Leaf(Rc::new(RefCell::new(LeafNode {
+            keys: vec![],
+            values: vec![],
+            next: None,
+        })))
+    }
+
+    fn key_count(&self) -> usize {
+        match self {
+            Node::Leaf(leaf) => leaf.borrow().keys.len(),
+            Node::Internal(internal) => internal.borrow().keys.len(),
+        }
+    }
+}
+
+//Separator key and new right sibling produced by a split.
+type Split<K> = Option<(K, Node<K>)>;
+
+pub struct BPlusTree<K: IndexKey> {
+    root: Node<K>,
+    order: usize,
+    len: usize,
+}
+
+//order is the maximum number of children of an internal node,
+//leaves hold at most order - 1 keys.
+impl<K: IndexKey> BPlusTree<K> {
+    pub fn new(order: usize) -> Self {
+        assert!(order >= 3, "B+tree order must be at least 3");
+        BPlusTree {
+            root: Node::new_leaf(),
+            order,
+            len: 0,
+        }
+    }
+
+    //Builds the tree bottom up from entries sorted by key, packing every node full.
+    pub fn bulk_load(order: usize, entries: impl IntoIterator<Item = (K, Vec<u8>)>) -> Self {
+        let mut tree = BPlusTree::new(order);
+        let entries: Vec<(K, Vec<u8>)> = entries.into_iter().collect();
+        if entries.is_empty() {
+            return tree;
+        }
+        assert!(
+            entries.windows(2).all(|pair| pair[0].0 < pair[1].0),
+            "bulk load input must be sorted by unique keys"
+        );
+        tree.len = entries.len();
+
+        let mut entries = entries.into_iter();
+        let mut level: Vec<(K, Node<K>)> = vec![];
+        let mut previous: Option<Rc<RefCell<LeafNode<K>>>> = None;
+        for size in Self::chunk_sizes(tree.len, tree.max_keys(), tree.min_keys()) {
+            let (keys, values): (Vec<K>, Vec<Vec<u8>>) = entries.by_ref().take(size).unzip();
+            let first_key = keys[0].clone();
+            let leaf = Rc::new(RefCell::new(LeafNode { keys, values, next: None }));
+            if let Some(previous) = previous {
+                previous.borrow_mut().next = Some(Rc::clone(&leaf));
+            }
+            previous = Some(Rc::clone(&leaf));
+            level.push((first_key, Node::Leaf(leaf)));
+        }
+
+        while level.len() > 1 {
+            let mut nodes = level.into_iter();
+            let mut parents = vec![];
+            for size in Self::chunk_sizes(nodes.len(), order, tree.min_keys() + 1) {
+                let (mut keys, children): (Vec<K>, Vec<Node<K>>) = nodes.by_ref().take(size).unzip();
+                //The first child's smallest key moves up as this node's own smallest key.
+                let first_key = keys.remove(0);
+                let internal = InternalNode { keys, children };
+                parents.push((first_key, Node::Internal(Rc::new(RefCell::new(internal)))));
+            }
+            level = parents;
+        }
+        tree.root = level.pop().unwrap().1;
+        tree
+    }
+
+    //Splits `total` items into as few nodes of at most `max` items as possible,
+    //evening out the last two so that neither falls below `min`.
+    fn chunk_sizes(total: usize, max: usize, min: usize) -> Vec<usize> {
+        let mut sizes = vec![max; total / max];
+        if !total.is_multiple_of(max) {
+            sizes.push(total % max);
+        }
+        let count = sizes.len();
+        if count > 1 && sizes[count - 1] < min {
+            let combined = sizes[count - 2] + sizes[count - 1];
+            sizes[count - 2] = combined - combined / 2;
+            sizes[count - 1] = combined / 2;
+        }
+        sizes
+    }
+
+    pub fn height(&self) -> usize {
+        let mut height = 1;
+        let mut node = self.root.clone();
+        while let Node::Internal(internal) = node {
+            node = internal.borrow().children[0].clone();
+            height += 1;
+        }
+        height
+    }
+
+    fn leaf_for(&self, key: Option<&K>) -> Rc<RefCell<LeafNode<K>>> {
+        let mut node = self.root.clone();
+        loop {
+            let next = match &node {
+                Node::Internal(internal) => {
+                    let internal = internal.borrow();
+                    let index = match key {
+                        Some(key) => internal.keys.partition_point(|k| k < key),
+                        None => 0,
+                    };
+                    internal.children[index].clone()
+                }
+                Node::Leaf(leaf) => return Rc::clone(leaf),
+            };
+            node = next;
+        }
+    }
+
+    //Walks the leaves through their `next` links starting at the lower bound.
+    pub fn range(&self, start: Bound<&K>, end: Bound<&K>) -> Range<K> {
+        let start_key = match start {
+            Bound::Included(key) | Bound::Excluded(key) => Some(key),
+            Bound::Unbounded => None,
+        };
+        let leaf = self.leaf_for(start_key);
+        let position = match start {
+            Bound::Included(key) => leaf.borrow().keys.partition_point(|k| k < key),
+            Bound::Excluded(key) => leaf.borrow().keys.partition_point(|k| k <= key),
+            Bound::Unbounded => 0,
+        };
+        Range {
+            leaf: Some(leaf),
+            position,
+            start: start.cloned(),
+            end: end.cloned(),
+        }
+    }
+
+    pub fn iter(&self) -> Range<K> {
+        self.range(Bound::Unbounded, Bound::Unbounded)
+    }
+
+    fn max_keys(&self) -> usize {
+        self.order - 1
+    }
+
+    fn min_keys(&self) -> usize {
+        (self.order - 1) / 2
+    }
+
+    //Indexes only read the tree through `range`, point lookups are kept for checking it in tests.
+    #[cfg(test)]
+    pub fn get(&self, key: &K) -> Option<Vec<u8>> {
+        let mut node = self.root.clone();
+        loop {
+            let next = match &node {
+                Node::Internal(internal) => {
+                    let internal = internal.borrow();
+                    let index = internal.keys.partition_point(|k| k <= key);
+                    internal.children[index].clone()
+                }
+                Node::Leaf(leaf) => {
+                    let leaf = leaf.borrow();
+                    return match leaf.keys.binary_search(key) {
+                        Ok(index) => Some(leaf.values[index].clone()),
+                        Err(_) => None,
+                    };
+                }
+            };
+            node = next;
+        }
+    }
+
+    pub fn insert(&mut self, key: K, value: Vec<u8>) -> Option<Vec<u8>> {
+        let root = self.root.clone();
+        let (old_value, split) = self.insert_into(&root, key, value);
+        if let Some((separator, right)) = split {
+            self.root = Node::Internal(Rc::new(RefCell::new(InternalNode {
+                keys: vec![separator],
+                children: vec![root, right],
+            })));
+        }
+        if old_value.is_none() {
+            self.len += 1;
+        }
+        old_value
+    }
+
+    fn insert_into(
+        &self,
+        node: &Node<K>,
+        key: K,
+        value: Vec<u8>,
+    ) -> (Option<Vec<u8>>, Split<K>) {
+        match node {
+            Node::Leaf(leaf_ref) => {
+                let mut leaf = leaf_ref.borrow_mut();
+                match leaf.keys.binary_search(&key) {
+                    Ok(index) => {
+                        let old = std::mem::replace(&mut leaf.values[index], value);
+                        return (Some(old), None);
+                    }
+                    Err(index) => {
+                        leaf.keys.insert(index, key);
+                        leaf.values.insert(index, value);
+                    }
+                }
+                if leaf.keys.len() <= self.max_keys() {
+                    return (None, None);
+                }
+                let mid = leaf.keys.len() / 2;
+                let right = Rc::new(RefCell::new(LeafNode {
+                    keys: leaf.keys.split_off(mid),
+                    values: leaf.values.split_off(mid),
+                    next: leaf.next.take(),
+                }));
+                leaf.next = Some(Rc::clone(&right));
+                let separator = right.borrow().keys[0].clone();
+                (None, Some((separator, Node::Leaf(right))))
+            }
+            Node::Internal(internal_ref) => {
+                let (index, child) = {
+                    let internal = internal_ref.borrow();
+                    let index = internal.keys.partition_point(|k| *k <= key);
+                    (index, internal.children[index].clone())
+                };
+                let (old_value, split) = self.insert_into(&child, key, value);
+                let Some((separator, right)) = split else {
+                    return (old_value, None);
+                };
+                let mut internal = internal_ref.borrow_mut();
+                internal.keys.insert(index, separator);
+                internal.children.insert(index + 1, right);
+                if internal.keys.len() <= self.max_keys() {
+                    return (old_value, None);
+                }
+                let mid = internal.keys.len() / 2;
+                let right_keys = internal.keys.split_off(mid + 1);
+                let separator = internal.keys.pop().unwrap();
+                let right_children = internal.children.split_off(mid + 1);
+                let right = Node::Internal(Rc::new(RefCell::new(InternalNode {
+                    keys: right_keys,
+                    children: right_children,
+                })));
+                (old_value, Some((separator, right)))
+            }
+        }
+    }
+
+    pub fn delete(&mut self, key: &K) -> Option<Vec<u8>> {
+        let root = self.root.clone();
+        let removed = self.delete_from(&root, key);
+        if removed.is_some() {
+            self.len -= 1;
+        }
+        //Shrink the tree when the root is left with a single child.
+        let new_root = match &self.root {
+            Node::Internal(internal) if internal.borrow().keys.is_empty() => {
+                Some(internal.borrow().children[0].clone())
+            }
+            _ => None,
+        };
+        if let Some(new_root) = new_root {
+            self.root = new_root;
+        }
+        removed
+    }
+
+    fn delete_from(&self, node: &Node<K>, key: &K) -> Option<Vec<u8>> {
+        match node {
+            Node::Leaf(leaf_ref) => {
+                let mut leaf = leaf_ref.borrow_mut();
+                let index = leaf.keys.binary_search(key).ok()?;
+                leaf.keys.remove(index);
+                Some(leaf.values.remove(index))
+            }
+            Node::Internal(internal_ref) => {
+                let (index, child) = {
+                    let internal = internal_ref.borrow();
+                    let index = internal.keys.partition_point(|k| k <= key);
+                    (index, internal.children[index].clone())
+                };
+                let removed = self.delete_from(&child, key)?;
+                if child.key_count() < self.min_keys() {
+                    self.rebalance(&mut internal_ref.borrow_mut(), index);
+                }
+                Some(removed)
+            }
+        }
+    }
+
+    //Fix an underflowing child by borrowing from a sibling that can spare a key,
+    //otherwise merge it with a sibling and drop the separator from the parent.
+    fn rebalance(&self, parent: &mut InternalNode<K>, index: usize) {
+        if index > 0 && parent.children[index - 1].key_count() > self.min_keys() {
+            Self::borrow_from_left(parent, index);
+        } else if index + 1 < parent.children.len()
+            && parent.children[index + 1].key_count() > self.min_keys()
+        {
+            Self::borrow_from_right(parent, index);
+        } else if index > 0 {
+            Self::merge(parent, index - 1);
+        } else if index + 1 < parent.children.len() {
+            Self::merge(parent, index);
+        }
+    }
+
+    fn borrow_from_left(parent: &mut InternalNode<K>, index: usize) {
+        match (&parent.children[index - 1], &parent.children[index]) {
+            (Node::Leaf(left), Node::Leaf(child)) => {
+                let mut left = left.borrow_mut();
+                let mut child = child.borrow_mut();
+                let key = left.keys.pop().unwrap();
+                let value = left.values.pop().unwrap();
+                parent.keys[index - 1] = key.clone();
+                child.keys.insert(0, key);
+                child.values.insert(0, value);
+            }
+            (Node::Internal(left), Node::Internal(child)) => {
+                let mut left = left.borrow_mut();
+                let mut child = child.borrow_mut();
+                let separator = std::mem::replace(&mut parent.keys[index - 1], left.keys.pop().unwrap());
+                child.keys.insert(0, separator);
+                child.children.insert(0, left.children.pop().unwrap());
+            }
+            _ => unreachable!("siblings must be on the same level"),
+        }
+    }
+
+    fn borrow_from_right(parent: &mut InternalNode<K>, index: usize) {
+        match (&parent.children[index], &parent.children[index + 1]) {
+            (Node::Leaf(child), Node::Leaf(right)) => {
+                let mut child = child.borrow_mut();
+                let mut right = right.borrow_mut();
+                child.keys.push(right.keys.remove(0));
+                child.values.push(right.values.remove(0));
+                parent.keys[index] = right.keys[0].clone();
+            }
+            (Node::Internal(child), Node::Internal(right)) => {
+                let mut child = child.borrow_mut();
+                let mut right = right.borrow_mut();
+                let separator = std::mem::replace(&mut parent.keys[index], right.keys.remove(0));
+                child.keys.push(separator);
+                child.children.push(right.children.remove(0));
+            }
+            _ => unreachable!("siblings must be on the same level"),
+        }
+    }
+
+    //Merge children[index + 1] into children[index].
+    fn merge(parent: &mut InternalNode<K>, index: usize) {
+        let separator = parent.keys.remove(index);
+        let right = parent.children.remove(index + 1);
+        match (&parent.children[index], right) {
+            (Node::Leaf(left), Node::Leaf(right)) => {
+                let mut left = left.borrow_mut();
+                let mut right = right.borrow_mut();
+                left.keys.append(&mut right.keys);
+                left.values.append(&mut right.values);
+                left.next = right.next.take();
+            }
+            (Node::Internal(left), Node::Internal(right)) => {
+                let mut left = left.borrow_mut();
+                let mut right = right.borrow_mut();
+                left.keys.push(separator);
+                left.keys.append(&mut right.keys);
+                left.children.append(&mut right.children);
+            }
+            _ => unreachable!("siblings must be on the same level"),
+        }
+    }
+}
+
+//Invariant checker and dump, used by tests and the `.btree` REPL command.
+impl<K: IndexKey> BPlusTree<K> {
+    //Validates key ordering against the separators, fill factors, that every
+    //leaf sits at the same depth and that the `next` links visit the leaves in order.
+    pub fn check(&self) -> Result<(), String> {
+        let mut leaves = vec![];
+        self.check_node(&self.root, None, None, 1, &mut leaves)?;
+        let depths: Vec<usize> = leaves.iter().map(|(_, depth)| *depth).collect();
+        if depths.iter().any(|depth| *depth != depths[0]) {
+            return Err(format!("Leaves at different depths {:?}", depths));
+        }
+        for (i, (leaf, _)) in leaves.iter().enumerate() {
+            let next = leaf.borrow().next.clone();
+            let expected = leaves.get(i + 1).map(|(leaf, _)| leaf);
+            let linked = match (&next, expected) {
+                (Some(next), Some(expected)) => Rc::ptr_eq(next, expected),
+                (None, None) => true,
+                _ => false,
+            };
+            if !linked {
+                return Err(format!("Leaf {} has a broken next link", i));
+            }
+        }
+        let keys: Vec<K> = leaves
+            .iter()
+            .flat_map(|(leaf, _)| leaf.borrow().keys.clone())
+            .collect();
+        if keys.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err("Leaf keys are not strictly increasing".to_string());
+        }
+        if keys.windows(2).any(|pair| pair[0].encode() >= pair[1].encode()) {
+            return Err("Leaf key encodings are not strictly increasing".to_string());
+        }
+        if keys.len() != self.len {
+            return Err(format!("Tree holds {} keys but len is {}", keys.len(), self.len));
+        }
+        Ok(())
+    }
+
+    //Keys under `node` must lie within [lower, upper).
+    fn check_node(
+        &self,
+        node: &Node<K>,
+        lower: Option<&K>,
+        upper: Option<&K>,
+        depth: usize,
+        leaves: &mut Vec<(Rc<RefCell<LeafNode<K>>>, usize)>,
+    ) -> Result<(), String> {
+        let is_root = depth == 1;
+        let count = node.key_count();
+        if count > self.max_keys() {
+            return Err(format!("Node at depth {} holds {} keys, more than {}", depth, count, self.max_keys()));
+        }
+        if !is_root && count < self.min_keys() {
+            return Err(format!("Node at depth {} holds {} keys, fewer than {}", depth, count, self.min_keys()));
+        }
+        let in_bounds = |keys: &[K]| {
+            keys.windows(2).all(|pair| pair[0] < pair[1])
+                && keys.iter().all(|key| {
+                    lower.is_none_or(|lower| key >= lower) && upper.is_none_or(|upper| key < upper)
+                })
+        };
+        match node {
+            Node::Leaf(leaf) => {
+                if !in_bounds(&leaf.borrow().keys) {
+                    return Err(format!("Leaf at depth {} has keys out of order", depth));
+                }
+                if leaf.borrow().keys.len() != leaf.borrow().values.len() {
+                    return Err(format!("Leaf at depth {} has mismatched keys and values", depth));
+                }
+                leaves.push((Rc::clone(leaf), depth));
+            }
+            Node::Internal(internal) => {
+                let internal = internal.borrow();
+                if is_root && internal.keys.is_empty() {
+                    return Err("Internal root has no keys".to_string());
+                }
+                if internal.children.len() != internal.keys.len() + 1 {
+                    return Err(format!("Internal node at depth {} has {} keys but {} children",
+                        depth, internal.keys.len(), internal.children.len()));
+                }
+                if !in_bounds(&internal.keys) {
+                    return Err(format!("Internal node at depth {} has keys out of order", depth));
+                }
+                for (i, child) in internal.children.iter().enumerate() {
+                    let child_lower = if i == 0 { lower } else { Some(&internal.keys[i - 1]) };
+                    let child_upper = internal.keys.get(i).or(upper);
+                    self.check_node(child, child_lower, child_upper, depth + 1, leaves)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //Prints the tree level by level. Nodes live in memory and have no page ids, so they are
+    //labelled with breadth first ordinals; internal nodes list their children's ordinals
+    //and leaves the ordinal of their `next` leaf.
+    pub fn dump(&self, format_key: impl Fn(&K) -> String) -> String {
+        let mut out = String::new();
+        let mut level = vec![self.root.clone()];
+        let mut first_number = 0;
+        let mut depth = 0;
+        while !level.is_empty() {
+            out.push_str(&format!("level {}\n", depth));
+            let mut next_level = vec![];
+            for (i, node) in level.iter().enumerate() {
+                let number = first_number + i;
+                match node {
+                    Node::Internal(internal) => {
+                        let internal = internal.borrow();
+                        let keys: Vec<String> = internal.keys.iter().map(&format_key).collect();
+                        let first_child = first_number + level.len() + next_level.len();
+                        let children: Vec<String> = (0..internal.children.len())
+                            .map(|c| (first_child + c).to_string())
+                            .collect();
+                        out.push_str(&format!("  node {} internal keys [{}] children [{}]\n",
+                            number, keys.join(", "), children.join(", ")));
+                        next_level.extend(internal.children.iter().cloned());
+                    }
+                    Node::Leaf(leaf) => {
+                        let leaf = leaf.borrow();
+                        let keys: Vec<String> = leaf.keys.iter().map(&format_key).collect();
+                        let next = match (&leaf.next, i + 1 < level.len()) {
+                            (Some(_), true) => (number + 1).to_string(),
+                            (Some(_), false) => "?".to_string(),
+                            (None, _) => "none".to_string(),
+                        };
+                        out.push_str(&format!("  node {} leaf keys [{}] next {}\n", number, keys.join(", "), next));
+                    }
+                }
+            }
+            first_number += level.len();
+            level = next_level;
+            depth += 1;
+        }
+        out
+    }
+}
+
+impl<K: IndexKey> fmt::Debug for BPlusTree<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BPlusTree")
+            .field("order", &self.order)
+            .field("len", &self.len)
+            .field("height", &self.height())
+            .finish()
+    }
 }
 
-struct InternalNode{
-    keys: Vec<Uuid>,
-    children: Vec<Rc<RefCell<Node>>>,
-}
\ No newline at end of file
+pub struct Range<K> {
+    leaf: Option<Rc<RefCell<LeafNode<K>>>>,
+    position: usize,
+    start: Bound<K>,
+    end: Bound<K>,
+}
+
+impl<K: IndexKey> Iterator for Range<K> {
+    type Item = (K, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let leaf = self.leaf.clone()?;
+            let leaf = leaf.borrow();
+            if self.position >= leaf.keys.len() {
+                self.leaf = leaf.next.clone();
+                self.position = 0;
+                continue;
+            }
+            let key = &leaf.keys[self.position];
+            self.position += 1;
+            //Separators may point into a leaf left of the start after deletes.
+            let after_start = match &self.start {
+                Bound::Included(start) => key >= start,
+                Bound::Excluded(start) => key > start,
+                Bound::Unbounded => true,
+            };
+            if !after_start {
+                continue;
+            }
+            let before_end = match &self.end {
+                Bound::Included(end) => key <= end,
+                Bound::Excluded(end) => key < end,
+                Bound::Unbounded => true,
+            };
+            if !before_end {
+                self.leaf = None;
+                return None;
+            }
+            return Some((key.clone(), leaf.values[self.position - 1].clone()));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn key(n: u128) -> Uuid {
+        Uuid::from_u128(n)
+    }
+
+    fn leaf_keys<K: IndexKey>(tree: &BPlusTree<K>) -> Vec<K> {
+        let mut node = tree.root.clone();
+        while let Node::Internal(internal) = node {
+            node = internal.borrow().children[0].clone();
+        }
+        let Node::Leaf(first) = node else { unreachable!() };
+        let mut keys = vec![];
+        let mut leaf = Some(first);
+        while let Some(current) = leaf {
+            keys.extend(current.borrow().keys.iter().cloned());
+            leaf = current.borrow().next.clone();
+        }
+        keys
+    }
+
+    #[test]
+    fn test_insert_and_get() {
+        let mut tree = BPlusTree::new(4);
+        for n in (0..100).rev() {
+            assert_eq!(tree.insert(key(n), vec![n as u8]), None);
+        }
+        assert_eq!(tree.len, 100);
+        assert!(tree.height() > 1);
+        for n in 0..100 {
+            assert_eq!(tree.get(&key(n)), Some(vec![n as u8]));
+        }
+        assert_eq!(tree.get(&key(100)), None);
+        assert_eq!(tree.insert(key(5), vec![42]), Some(vec![5]));
+        assert_eq!(tree.len, 100);
+    }
+
+    #[test]
+    fn test_delete_keeps_leaf_links_sorted() {
+        let mut tree = BPlusTree::new(4);
+        for n in 0..50 {
+            tree.insert(key(n), vec![n as u8]);
+        }
+        for n in (0..50).filter(|n| n % 3 == 0) {
+            assert_eq!(tree.delete(&key(n)), Some(vec![n as u8]));
+        }
+        assert_eq!(tree.delete(&key(0)), None);
+        let expected: Vec<Uuid> = (0..50).filter(|n| n % 3 != 0).map(key).collect();
+        assert_eq!(leaf_keys(&tree), expected);
+        assert_eq!(tree.len, expected.len());
+        for n in 0..50 {
+            assert_eq!(tree.get(&key(n)).is_some(), n % 3 != 0);
+        }
+    }
+
+    #[test]
+    fn test_negative_int_keys_stay_ordered() {
+        let mut tree = BPlusTree::new(4);
+        for n in [3i64, -7, 0, -1, 12, -300, 5] {
+            tree.insert(n, vec![]);
+        }
+        assert_eq!(leaf_keys(&tree), vec![-300, -7, -1, 0, 3, 5, 12]);
+    }
+
+    #[test]
+    fn test_range_scan_follows_leaf_links() {
+        let mut tree = BPlusTree::new(4);
+        for n in 0..40i64 {
+            tree.insert(n * 2, vec![n as u8]);
+        }
+        let keys: Vec<i64> = tree
+            .range(Bound::Included(&10), Bound::Excluded(&20))
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(keys, vec![10, 12, 14, 16, 18]);
+        let keys: Vec<i64> = tree
+            .range(Bound::Excluded(&11), Bound::Included(&16))
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(keys, vec![12, 14, 16]);
+        assert_eq!(tree.iter().count(), 40);
+        assert_eq!(tree.range(Bound::Included(&100), Bound::Unbounded).count(), 0);
+    }
+
+    #[test]
+    fn test_bulk_load_matches_inserted_tree() {
+        for total in [0i64, 1, 3, 4, 5, 17, 100, 1000] {
+            let tree = BPlusTree::bulk_load(4, (0..total).map(|n| (n, vec![n as u8])));
+            assert_eq!(tree.len, total as usize);
+            assert_eq!(leaf_keys(&tree), (0..total).collect::<Vec<i64>>());
+            for n in 0..total {
+                assert_eq!(tree.get(&n), Some(vec![n as u8]));
+            }
+        }
+        let packed = BPlusTree::bulk_load(4, (0..1000i64).map(|n| (n, vec![])));
+        let mut inserted = BPlusTree::new(4);
+        for n in 0..1000i64 {
+            inserted.insert(n, vec![]);
+        }
+        assert!(packed.height() <= inserted.height());
+    }
+
+    #[test]
+    fn test_bulk_loaded_tree_supports_updates() {
+        let mut tree = BPlusTree::bulk_load(3, (0..50i64).map(|n| (n * 2, vec![])));
+        for n in 0..50i64 {
+            tree.insert(n * 2 + 1, vec![]);
+        }
+        for n in 0..50i64 {
+            tree.delete(&(n * 2));
+        }
+        assert_eq!(leaf_keys(&tree), (0..50).map(|n| n * 2 + 1).collect::<Vec<i64>>());
+    }
+
+    #[test]
+    #[should_panic(expected = "sorted")]
+    fn test_bulk_load_rejects_unsorted_input() {
+        BPlusTree::bulk_load(4, vec![(2i64, vec![]), (1, vec![])]);
+    }
+
+    #[test]
+    fn test_delete_all_shrinks_height() {
+        let mut tree = BPlusTree::new(3);
+        for n in 0..64 {
+            tree.insert(key(n), vec![]);
+        }
+        let tall = tree.height();
+        assert!(tall > 3);
+        for n in 0..60 {
+            tree.delete(&key(n));
+        }
+        assert!(tree.height() < tall);
+        for n in 60..64 {
+            tree.delete(&key(n));
+        }
+        assert_eq!(tree.len, 0);
+        assert_eq!(tree.height(), 1);
+        assert!(leaf_keys(&tree).is_empty());
+    }
+
+    #[test]
+    fn test_check_holds_through_inserts_and_deletes() {
+        for order in [3, 4, 5, 64] {
+            let mut tree = BPlusTree::new(order);
+            //A fixed stride visits 0..997 out of order.
+            for n in 0..997i64 {
+                tree.insert((n * 389) % 997, vec![]);
+                if n % 97 == 0 {
+                    tree.check().unwrap();
+                }
+            }
+            tree.check().unwrap();
+            for n in 0..900i64 {
+                tree.delete(&((n * 211) % 997));
+                if n % 89 == 0 {
+                    tree.check().unwrap();
+                }
+            }
+            tree.check().unwrap();
+            BPlusTree::bulk_load(order, (0..1000i64).map(|n| (n, vec![]))).check().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_check_reports_broken_trees() {
+        let tree = BPlusTree::bulk_load(4, (0..20i64).map(|n| (n, vec![])));
+        let mut node = tree.root.clone();
+        while let Node::Internal(internal) = node {
+            node = internal.borrow().children[0].clone();
+        }
+        let Node::Leaf(first) = node else { unreachable!() };
+        let second = first.borrow_mut().next.take();
+        assert!(tree.check().unwrap_err().contains("next link"));
+        first.borrow_mut().next = second;
+        tree.check().unwrap();
+        first.borrow_mut().keys.swap(0, 1);
+        assert!(tree.check().unwrap_err().contains("out of order"));
+    }
+
+    //Orders like its number but encodes little endian, so the byte order disagrees.
+    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+    struct LittleEndian(u16);
+
+    impl IndexKey for LittleEndian {
+        fn encode(&self) -> Vec<u8> {
+            self.0.to_le_bytes().to_vec()
+        }
+    }
+
+    #[test]
+    fn test_check_reports_keys_encoded_out_of_order() {
+        let small = BPlusTree::bulk_load(4, (0..20u16).map(|n| (LittleEndian(n), vec![])));
+        small.check().unwrap();
+        let tree = BPlusTree::bulk_load(4, (250..260u16).map(|n| (LittleEndian(n), vec![])));
+        assert_eq!(tree.check(), Err("Leaf key encodings are not strictly increasing".to_string()));
+    }
+
+    #[test]
+    fn test_dump_lists_levels() {
+        let tree = BPlusTree::bulk_load(3, (1..=5i64).map(|n| (n, vec![])));
+        assert_eq!(
+            tree.dump(|key| key.to_string()),
+            "level 0\n  node 0 internal keys [3, 5] children [1, 2, 3]\n\
+             level 1\n  node 1 leaf keys [1, 2] next 2\n  node 2 leaf keys [3, 4] next 3\n  node 3 leaf keys [5] next none\n"
+        );
+    }
+}
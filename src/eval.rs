@@ -0,0 +1,323 @@
+use std::cmp::Ordering;
+
+use uuid::Uuid;
+
+use crate::ast::{BinaryOperator, Expr, UnaryOperator};
+use crate::table::Value;
+
+//The row an expression is evaluated against. Booleans are INT 1 and 0 and
+//NULL is None, comparisons with NULL give NULL as in SQL.
+pub struct Scope<'a> {
+    pub table: &'a str,
+    pub columns: &'a [String],
+    pub values: &'a [Value],
+}
+
+impl Scope<'_> {
+    //For expressions outside any row, such as INSERT values.
+    pub fn empty() -> Scope<'static> {
+        Scope { table: "", columns: &[], values: &[] }
+    }
+
+    fn lookup(&self, table: Option<&str>, name: &str) -> Result<Value, String> {
+        if let Some(table) = table
+            && table != self.table
+        {
+            return Err(format!("Unknown table {}", table));
+        }
+        match self.columns.iter().position(|column| column == name) {
+            Some(position) => Ok(self.values[position].clone()),
+            None => Err(format!("Column {} not found", name)),
+        }
+    }
+}
+
+fn boolean(value: bool) -> Option<Value> {
+    Some(Value::Int(value as i64))
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Int(_) => "INT",
+        Value::Float(_) => "FLOAT",
+        Value::String(_) => "STRING",
+        Value::Uuid(_) => "UUID",
+    }
+}
+
+fn truth(value: Option<Value>) -> Result<Option<bool>, String> {
+    match value {
+        None => Ok(None),
+        Some(Value::Int(value)) => Ok(Some(value != 0)),
+        Some(Value::Float(value)) => Ok(Some(value != 0.0)),
+        Some(value) => Err(format!("Expected a boolean, found {} {}", type_name(&value), value)),
+    }
+}
+
+//Whether a WHERE clause holds for the row in `scope`, NULL counts as false.
+pub fn matches(filter: &Expr, scope: &Scope, parameters: &[Value]) -> Result<bool, String> {
+    Ok(truth(evaluate(filter, scope, parameters)?)?.unwrap_or(false))
+}
+
+//Numbers compare across INT and FLOAT, a STRING compared with a number or
+//UUID is read as one.
+pub fn compare(left: &Value, right: &Value) -> Result<Ordering, String> {
+    let mismatch = || format!("Cannot compare {} with {}", type_name(left), type_name(right));
+    let number = |value: &Value| match value {
+        Value::Int(value) => Some(*value as f64),
+        Value::Float(value) => Some(*value),
+        Value::String(value) => value.trim().parse().ok(),
+        Value::Uuid(_) => None,
+    };
+    match (left, right) {
+        (Value::Int(left), Value::Int(right)) => Ok(left.cmp(right)),
+        (Value::String(left), Value::String(right)) => Ok(left.cmp(right)),
+        (Value::Uuid(left), Value::Uuid(right)) => Ok(left.cmp(right)),
+        (Value::Uuid(left), Value::String(right)) => Ok(left.cmp(&Uuid::parse_str(right).map_err(|_| mismatch())?)),
+        (Value::String(left), Value::Uuid(right)) => Ok(Uuid::parse_str(left).map_err(|_| mismatch())?.cmp(right)),
+        _ => match (number(left), number(right)) {
+            (Some(left), Some(right)) => Ok(left.total_cmp(&right)),
+            _ => Err(mismatch()),
+        },
+    }
+}
+
+fn arithmetic(operator: BinaryOperator, left: Value, right: Value) -> Result<Value, String> {
+    let overflow = || "Integer overflow".to_string();
+    match (&left, &right) {
+        (Value::Int(a), Value::Int(b)) => {
+            let (a, b) = (*a, *b);
+            if b == 0 && matches!(operator, BinaryOperator::DIVIDE | BinaryOperator::MODULO) {
+                return Err("Division by zero".to_string());
+            }
+            let result = match operator {
+                BinaryOperator::PLUS => a.checked_add(b),
+                BinaryOperator::MINUS => a.checked_sub(b),
+                BinaryOperator::MULTIPLY => a.checked_mul(b),
+                BinaryOperator::DIVIDE => a.checked_div(b),
+                _ => a.checked_rem(b),
+            };
+            result.map(Value::Int).ok_or_else(overflow)
+        }
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+            let float = |value: &Value| match value {
+                Value::Int(value) => *value as f64,
+                Value::Float(value) => *value,
+                _ => unreachable!(),
+            };
+            let (a, b) = (float(&left), float(&right));
+            if b == 0.0 && matches!(operator, BinaryOperator::DIVIDE | BinaryOperator::MODULO) {
+                return Err("Division by zero".to_string());
+            }
+            Ok(Value::Float(match operator {
+                BinaryOperator::PLUS => a + b,
+                BinaryOperator::MINUS => a - b,
+                BinaryOperator::MULTIPLY => a * b,
+                BinaryOperator::DIVIDE => a / b,
+                _ => a % b,
+            }))
+        }
+        _ => Err(format!(
+            "Cannot apply {} to {} and {}",
+            operator.symbol(),
+            type_name(&left),
+            type_name(&right)
+        )),
+    }
+}
+
+//SQL LIKE, % matches any run of characters and _ exactly one.
+pub fn like(text: &str, pattern: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+    //matches[i] is whether the pattern so far matches the first i characters.
+    let mut matches = vec![false; text.len() + 1];
+    matches[0] = true;
+    for p in pattern {
+        let previous = matches.clone();
+        matches[0] = p == '%' && previous[0];
+        for i in 1..=text.len() {
+            matches[i] = match p {
+                '%' => previous[i] || matches[i - 1],
+                '_' => previous[i - 1],
+                c => previous[i - 1] && text[i - 1] == c,
+            };
+        }
+    }
+    matches[text.len()]
+}
+
+fn call(name: &str, args: Vec<Option<Value>>) -> Result<Option<Value>, String> {
+    let arity = |count: usize| {
+        if args.len() == count {
+            Ok(())
+        } else {
+            Err(format!("Function {} expects {} argument(s)", name, count))
+        }
+    };
+    if name == "COALESCE" {
+        return Ok(args.into_iter().flatten().next());
+    }
+    match name {
+        "LOWER" | "UPPER" | "LENGTH" | "ABS" => arity(1)?,
+        _ => return Err(format!("Unknown function {}", name)),
+    }
+    let Some(value) = args.into_iter().next().flatten() else {
+        return Ok(None);
+    };
+    let value = match (name, value) {
+        ("LOWER", value) => Value::String(value.to_string().to_lowercase()),
+        ("UPPER", value) => Value::String(value.to_string().to_uppercase()),
+        ("LENGTH", value) => Value::Int(value.to_string().chars().count() as i64),
+        ("ABS", Value::Int(value)) => Value::Int(value.checked_abs().ok_or("Integer overflow")?),
+        ("ABS", Value::Float(value)) => Value::Float(value.abs()),
+        (_, value) => return Err(format!("Function {} expects a number, found {}", name, type_name(&value))),
+    };
+    Ok(Some(value))
+}
+
+pub fn evaluate(expr: &Expr, scope: &Scope, parameters: &[Value]) -> Result<Option<Value>, String> {
+    let eval = |expr: &Expr| evaluate(expr, scope, parameters);
+    match expr {
+        Expr::Literal(value) => Ok(Some(value.clone())),
+        Expr::Null => Ok(None),
+        Expr::Column { name, .. } if name == "*" => Err("* is only allowed as a SELECT column".to_string()),
+        Expr::Column { table, name } => scope.lookup(table.as_deref(), name).map(Some),
+        Expr::Placeholder(number) => match parameters.get(number - 1) {
+            Some(value) => Ok(Some(value.clone())),
+            None => Err(format!("Parameter ${} is not bound", number)),
+        },
+        Expr::Unary { operator: UnaryOperator::NOT, expr } => Ok(truth(eval(expr)?)?.and_then(|value| boolean(!value))),
+        Expr::Unary { operator: UnaryOperator::NEGATE, expr } => match eval(expr)? {
+            None => Ok(None),
+            Some(Value::Int(value)) => value.checked_neg().map(|value| Some(Value::Int(value))).ok_or("Integer overflow".to_string()),
+            Some(Value::Float(value)) => Ok(Some(Value::Float(-value))),
+            Some(value) => Err(format!("Cannot negate {}", type_name(&value))),
+        },
+        Expr::Binary { left, operator: BinaryOperator::AND, right } => {
+            let left = truth(eval(left)?)?;
+            if left == Some(false) {
+                return Ok(boolean(false));
+            }
+            match (left, truth(eval(right)?)?) {
+                (_, Some(false)) => Ok(boolean(false)),
+                (Some(true), Some(true)) => Ok(boolean(true)),
+                _ => Ok(None),
+            }
+        }
+        Expr::Binary { left, operator: BinaryOperator::OR, right } => {
+            let left = truth(eval(left)?)?;
+            if left == Some(true) {
+                return Ok(boolean(true));
+            }
+            match (left, truth(eval(right)?)?) {
+                (_, Some(true)) => Ok(boolean(true)),
+                (Some(false), Some(false)) => Ok(boolean(false)),
+                _ => Ok(None),
+            }
+        }
+        Expr::Binary { left, operator, right } => {
+            let (Some(left), Some(right)) = (eval(left)?, eval(right)?) else {
+                return Ok(None);
+            };
+            let ordering = || compare(&left, &right);
+            match operator {
+                BinaryOperator::EQUALS => Ok(boolean(ordering()? == Ordering::Equal)),
+                BinaryOperator::NOTEQUALS => Ok(boolean(ordering()? != Ordering::Equal)),
+                BinaryOperator::LESS => Ok(boolean(ordering()? == Ordering::Less)),
+                BinaryOperator::LESSEQUALS => Ok(boolean(ordering()? != Ordering::Greater)),
+                BinaryOperator::GREATER => Ok(boolean(ordering()? == Ordering::Greater)),
+                BinaryOperator::GREATEREQUALS => Ok(boolean(ordering()? != Ordering::Less)),
+                BinaryOperator::CONCAT => Ok(Some(Value::String(format!("{}{}", left, right)))),
+                operator => arithmetic(*operator, left, right).map(Some),
+            }
+        }
+        Expr::Function { name, args } => call(name, args.iter().map(eval).collect::<Result<_, _>>()?),
+        Expr::IsNull { expr, negated } => Ok(boolean(eval(expr)?.is_none() != *negated)),
+        Expr::InList { expr, list, negated } => {
+            let Some(value) = eval(expr)? else {
+                return Ok(None);
+            };
+            let mut saw_null = false;
+            for item in list {
+                match eval(item)? {
+                    Some(item) if compare(&value, &item)? == Ordering::Equal => return Ok(boolean(!negated)),
+                    Some(_) => {}
+                    None => saw_null = true,
+                }
+            }
+            Ok(if saw_null { None } else { boolean(*negated) })
+        }
+        Expr::Between { expr, low, high, negated } => {
+            let (Some(value), Some(low), Some(high)) = (eval(expr)?, eval(low)?, eval(high)?) else {
+                return Ok(None);
+            };
+            let inside = compare(&value, &low)? != Ordering::Less && compare(&value, &high)? != Ordering::Greater;
+            Ok(boolean(inside != *negated))
+        }
+        Expr::Like { expr, pattern, negated } => {
+            let (Some(value), Some(pattern)) = (eval(expr)?, eval(pattern)?) else {
+                return Ok(None);
+            };
+            Ok(boolean(like(&value.to_string(), &pattern.to_string()) != *negated))
+        }
+        Expr::Match { .. } => Err("MATCH is only allowed as the WHERE clause".to_string()),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(value: i64) -> Expr {
+        Expr::Literal(Value::Int(value))
+    }
+
+    fn binary(left: Expr, operator: BinaryOperator, right: Expr) -> Expr {
+        Expr::Binary { left: Box::new(left), operator, right: Box::new(right) }
+    }
+
+    #[test]
+    fn test_like_wildcards() {
+        assert!(like("alice", "a%"));
+        assert!(like("alice", "%lic%"));
+        assert!(like("alice", "_lice"));
+        assert!(like("", "%"));
+        assert!(!like("alice", "a_"));
+        assert!(!like("bob", "%a%"));
+        assert!(like("50%", "50%"));
+    }
+
+    #[test]
+    fn test_evaluate_with_nulls_and_columns() {
+        let columns = ["age".to_string(), "name".to_string()];
+        let values = [Value::Int(30), Value::String("Ann".to_string())];
+        let scope = Scope { table: "people", columns: &columns, values: &values };
+        let eval = |expr: &Expr| evaluate(expr, &scope, &[Value::Float(2.5)]);
+
+        assert_eq!(eval(&binary(Expr::column("age"), BinaryOperator::DIVIDE, int(4))), Ok(Some(Value::Int(7))));
+        assert_eq!(eval(&binary(Expr::column("age"), BinaryOperator::PLUS, Expr::Placeholder(1))), Ok(Some(Value::Float(32.5))));
+        assert_eq!(eval(&binary(int(1), BinaryOperator::DIVIDE, int(0))), Err("Division by zero".to_string()));
+        assert_eq!(
+            eval(&Expr::Column { table: Some("people".to_string()), name: "name".to_string() }),
+            Ok(Some(Value::String("Ann".to_string())))
+        );
+        assert_eq!(eval(&Expr::column("missing")), Err("Column missing not found".to_string()));
+        assert_eq!(eval(&binary(Expr::Null, BinaryOperator::EQUALS, int(1))), Ok(None));
+        assert_eq!(eval(&binary(Expr::Null, BinaryOperator::OR, int(1))), Ok(Some(Value::Int(1))));
+        assert_eq!(eval(&binary(Expr::Null, BinaryOperator::AND, int(0))), Ok(Some(Value::Int(0))));
+        assert_eq!(eval(&Expr::IsNull { expr: Box::new(Expr::Null), negated: false }), Ok(Some(Value::Int(1))));
+        let in_list = |list: Vec<Expr>| Expr::InList { expr: Box::new(Expr::column("age")), list, negated: false };
+        assert_eq!(eval(&in_list(vec![int(1), int(30)])), Ok(Some(Value::Int(1))));
+        assert_eq!(eval(&in_list(vec![int(1), Expr::Null])), Ok(None));
+        assert_eq!(
+            eval(&Expr::Function { name: "UPPER".to_string(), args: vec![Expr::column("name")] }),
+            Ok(Some(Value::String("ANN".to_string())))
+        );
+        assert_eq!(
+            eval(&binary(Expr::column("name"), BinaryOperator::LESS, int(3))),
+            Err("Cannot compare STRING with INT".to_string())
+        );
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashSet;
+use std::ops::Bound;
+
+use crate::ast::{BinaryOperator, Expr, Statement};
+use crate::eval::{Scope, evaluate, matches};
+use crate::fulltext::TextMatch;
+use crate::index::KeyFunction;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::processor::Processor;
+use crate::table::{KeyRange, Value, print_rows};
+
+//Value of an expression outside any row, placeholders take theirs from `parameters`, $1 first.
+fn constant(expr: &Expr, parameters: &[Value]) -> Result<Value, String> {
+    evaluate(expr, &Scope::empty(), parameters)?.ok_or_else(|| "NULL values are not supported".to_string())
+}
+
+//`column` or `LOWER(column)` / `UPPER(column)` of `table`, as the key of a range.
+fn range_key(expr: &Expr, table: &str) -> Option<(String, Option<KeyFunction>)> {
+    match expr {
+        Expr::Column { table: None, name } => Some((name.clone(), None)),
+        Expr::Column { table: Some(qualifier), name } if qualifier == table => Some((name.clone(), None)),
+        Expr::Function { name, args } if args.len() == 1 => {
+            let function = KeyFunction::from_name(name)?;
+            match range_key(&args[0], table)? {
+                (column, None) => Some((column, Some(function))),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+//Conjuncts of `filter` comparing a column with a constant, which the table can
+//use to skip rows. The rows found are still checked against the whole filter.
+fn key_ranges(filter: &Expr, table: &str, parameters: &[Value]) -> Vec<KeyRange> {
+    let value_of = |expr: &Expr| evaluate(expr, &Scope::empty(), parameters).ok().flatten();
+    let mut ranges = vec![];
+    let mut conjuncts = vec![filter];
+    while let Some(conjunct) = conjuncts.pop() {
+        match conjunct {
+            Expr::Binary { left, operator: BinaryOperator::AND, right } => {
+                conjuncts.push(right);
+                conjuncts.push(left);
+            }
+            Expr::Binary { left, operator, right } => {
+                //Written as `key operator value`, `5 < age` becomes `age > 5`.
+                let (key, value, operator) = match (range_key(left, table), range_key(right, table)) {
+                    (Some(key), None) => (key, right, *operator),
+                    (None, Some(key)) => (
+                        key,
+                        left,
+                        match operator {
+                            BinaryOperator::LESS => BinaryOperator::GREATER,
+                            BinaryOperator::LESSEQUALS => BinaryOperator::GREATEREQUALS,
+                            BinaryOperator::GREATER => BinaryOperator::LESS,
+                            BinaryOperator::GREATEREQUALS => BinaryOperator::LESSEQUALS,
+                            operator => *operator,
+                        },
+                    ),
+                    _ => continue,
+                };
+                let Some(value) = value_of(value) else {
+                    continue;
+                };
+                let (lower, upper) = match operator {
+                    BinaryOperator::EQUALS => (Bound::Included(value.clone()), Bound::Included(value)),
+                    BinaryOperator::LESS => (Bound::Unbounded, Bound::Excluded(value)),
+                    BinaryOperator::LESSEQUALS => (Bound::Unbounded, Bound::Included(value)),
+                    BinaryOperator::GREATER => (Bound::Excluded(value), Bound::Unbounded),
+                    BinaryOperator::GREATEREQUALS => (Bound::Included(value), Bound::Unbounded),
+                    _ => continue,
+                };
+                let (column, function) = key;
+                ranges.push(KeyRange { column, function, lower, upper });
+            }
+            Expr::Between { expr, low, high, negated: false } => {
+                if let (Some((column, function)), Some(low), Some(high)) =
+                    (range_key(expr, table), value_of(low), value_of(high))
+                {
+                    ranges.push(KeyRange { column, function, lower: Bound::Included(low), upper: Bound::Included(high) });
+                }
+            }
+            _ => {}
+        }
+    }
+    ranges
+}
+
+//Runs parsed statements against the database.
+pub struct Executor<'a> {
+    processor: &'a mut Processor,
+}
+
+impl<'a> Executor<'a> {
+    pub fn new(processor: &'a mut Processor) -> Self {
+        Executor { processor }
+    }
+
+    pub fn execute(&mut self, statement: &Statement, parameters: &[Value]) -> Result<(), String> {
+        match statement {
+            Statement::Select { table, columns, filter } => {
+                let text_match = match filter {
+                    Some(Expr::Match { column, terms }) => {
+                        let terms = terms
+                            .iter()
+                            .map(|term| constant(term, parameters).map(|value| value.to_string()))
+                            .collect::<Result<Vec<String>, String>>()?;
+                        Some(TextMatch { column: column.clone(), query: terms.join(" ") })
+                    }
+                    Some(filter) => {
+                        let (header, rows) = self.select_expressions(table, columns, Some(filter), parameters)?;
+                        print_rows(header, &rows);
+                        return Ok(());
+                    }
+                    None => None,
+                };
+                //Plain column lists go through the table, which may answer them from an index.
+                let names: Option<HashSet<String>> = columns
+                    .iter()
+                    .map(|column| match column {
+                        Expr::Column { table: None, name } => Some(name.clone()),
+                        Expr::Column { table: Some(qualifier), name } if qualifier == table => Some(name.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                match names {
+                    Some(names) => self.processor.handle_select_statement(table, names, text_match),
+                    None if text_match.is_some() => Err("MATCH only supports plain column lists".to_string()),
+                    None => {
+                        let (header, rows) = self.select_expressions(table, columns, None, parameters)?;
+                        print_rows(header, &rows);
+                        Ok(())
+                    }
+                }
+            }
+            Statement::Insert { table, columns, values } => {
+                let values = values
+                    .iter()
+                    .map(|value| constant(value, parameters))
+                    .collect::<Result<Vec<Value>, String>>()?;
+                self.processor.handle_insert_statement(table, columns.iter().cloned().zip(values).collect())
+            }
+            Statement::Update { table, assignments, filter } => {
+                let ranges = match filter {
+                    Some(filter) => key_ranges(filter, table, parameters),
+                    None => vec![],
+                };
+                let columns: Vec<String> = assignments.iter().map(|(column, _)| column.clone()).collect();
+                let updated = self.processor.handle_update_statement(table, &ranges, &columns, |names, values| {
+                    let scope = Scope { table, columns: names, values };
+                    if let Some(filter) = filter
+                        && !matches(filter, &scope, parameters)?
+                    {
+                        return Ok(None);
+                    }
+                    //Every right side sees the row as it was before the update.
+                    assignments
+                        .iter()
+                        .map(|(_, expr)| {
+                            evaluate(expr, &scope, parameters)?.ok_or_else(|| "NULL values are not supported".to_string())
+                        })
+                        .collect::<Result<Vec<Value>, String>>()
+                        .map(Some)
+                })?;
+                println!("{} rows updated", updated);
+                Ok(())
+            }
+            Statement::Delete { table, filter } => {
+                let ranges = match filter {
+                    Some(filter) => key_ranges(filter, table, parameters),
+                    None => vec![],
+                };
+                let deleted = self.processor.handle_delete_statement(table, &ranges, |names, values| match filter {
+                    Some(filter) => matches(filter, &Scope { table, columns: names, values }, parameters),
+                    None => Ok(true),
+                })?;
+                println!("{} rows deleted", deleted);
+                Ok(())
+            }
+            Statement::CreateTable { name, columns, clustered_key } => {
+                self.processor.create_table(name.clone(), columns.clone(), clustered_key.clone())
+            }
+            Statement::CreateIndex { table, definition } => self.processor.create_index(table, definition.clone()),
+            Statement::DropTable { name, if_exists } => self.processor.drop_table(name, *if_exists),
+            Statement::TruncateTable { name } => self.processor.truncate_table(name),
+        }
+    }
+
+    //Header and values of the SELECT list evaluated for every row satisfying `filter`,
+    //`*` stands for all the columns.
+    pub fn select_expressions(
+        &mut self,
+        table: &str,
+        columns: &[Expr],
+        filter: Option<&Expr>,
+        parameters: &[Value],
+    ) -> Result<(Vec<String>, Vec<Vec<Value>>), String> {
+        let (names, rows) = match filter {
+            Some(filter) => self.processor.filter_table(table, &key_ranges(filter, table, parameters))?,
+            None => self.processor.scan_table(table)?,
+        };
+        let mut header = vec![];
+        for column in columns {
+            match column.column_name() {
+                Some("*") => header.extend(names.iter().cloned()),
+                _ => header.push(column.to_string()),
+            }
+        }
+        let mut output = vec![];
+        for values in &rows {
+            let scope = Scope { table, columns: &names, values };
+            if let Some(filter) = filter
+                && !matches(filter, &scope, parameters)?
+            {
+                continue;
+            }
+            let mut row = vec![];
+            for column in columns {
+                match column.column_name() {
+                    Some("*") => row.extend(values.iter().cloned()),
+                    _ => row.push(match evaluate(column, &scope, parameters)? {
+                        Some(value) => value,
+                        None => Value::String("NULL".to_string()),
+                    }),
+                }
+            }
+            output.push(row);
+        }
+        Ok((header, output))
+    }
+
+    //Parses the `;` separated statements of `sql` and runs them in order, one result per statement.
+    //A statement that fails to parse or run does not stop the ones after it.
+    pub fn run_script(&mut self, sql: &str) -> Vec<Result<(), String>> {
+        Parser::new(Lexer::new(sql))
+            .parse_script()
+            .into_iter()
+            .map(|statement| statement.and_then(|statement| self.execute(&statement, &[])))
+            .collect()
+    }
+
+    //Runs `sql` and returns the first error, if any statement failed.
+    #[cfg(test)]
+    pub fn run(&mut self, sql: &str) -> Result<(), String> {
+        self.run_script(sql).into_iter().collect()
+    }
+}
+
+//A statement parsed once and executed any number of times with the values
+//bound to its placeholders. Bound values never pass through the lexer, so a
+//string parameter is always a value and never SQL.
+pub struct PreparedStatement {
+    statement: Statement,
+    parameters: Vec<Option<Value>>,
+}
+
+impl PreparedStatement {
+    pub fn prepare(sql: &str) -> Result<Self, String> {
+        let mut parser = Parser::new(Lexer::new(sql));
+        let statement = parser.parse()?;
+        Ok(PreparedStatement {
+            statement,
+            parameters: vec![None; parser.placeholder_count()],
+        })
+    }
+
+    pub fn parameter_count(&self) -> usize {
+        self.parameters.len()
+    }
+
+    //Binds `value` to placeholder number `index`, counting from 1 like $1.
+    pub fn bind(&mut self, index: usize, value: Value) -> Result<(), String> {
+        match index.checked_sub(1).and_then(|i| self.parameters.get_mut(i)) {
+            Some(parameter) => {
+                *parameter = Some(value);
+                Ok(())
+            }
+            None => Err(format!("Parameter index {} out of range, the statement has {}", index, self.parameters.len())),
+        }
+    }
+
+    //Binds the constant written in `text`, such as 42, -1.5 or 'a b', to placeholder number `index`.
+    pub fn bind_text(&mut self, index: usize, text: &str) -> Result<(), String> {
+        let expr = Parser::new(Lexer::new(text)).parse_single_expr()?;
+        self.bind(index, constant(&expr, &[])?)
+    }
+
+    pub fn clear_bindings(&mut self) {
+        self.parameters.iter_mut().for_each(|parameter| *parameter = None);
+    }
+
+    pub fn execute(&self, processor: &mut Processor) -> Result<(), String> {
+        let parameters = self
+            .parameters
+            .iter()
+            .enumerate()
+            .map(|(i, parameter)| parameter.clone().ok_or_else(|| format!("Parameter ${} is not bound", i + 1)))
+            .collect::<Result<Vec<Value>, String>>()?;
+        Executor::new(processor).execute(&self.statement, &parameters)
+    }
+}
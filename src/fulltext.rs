@@ -0,0 +1,490 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::hash_index::HashIndex;
+use crate::pager::Pager;
+use crate::table::PAGE_SIZE;
+
+/*4 byte document count, 4 byte dictionary root page */
+const DOCUMENTS_OFFSET: usize = 0;
+const DICTIONARY_ROOT_OFFSET: usize = 4;
+/*2+4+4+4+4 bytes page header, postings follow it. The last page and the number of
+  live and removed postings are only kept up to date in a chain's first page */
+const POSTING_COUNT_OFFSET: usize = 0;
+const NEXT_PAGE_OFFSET: usize = 2;
+const LAST_PAGE_OFFSET: usize = 6;
+const LIVE_POSTINGS_OFFSET: usize = 10;
+const REMOVED_POSTINGS_OFFSET: usize = 14;
+const POSTING_HEADER_SIZE: usize = 18;
+/*8 byte row number, 4 byte term frequency, 0 for a removed posting */
+const POSTING_SIZE: usize = 12;
+const POSTINGS_PER_PAGE: usize = (PAGE_SIZE - POSTING_HEADER_SIZE) / POSTING_SIZE;
+const NO_NEXT_PAGE: u32 = u32::MAX;
+
+//`column MATCH query` in a SELECT's WHERE clause.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextMatch {
+    pub column: String,
+    pub query: String,
+}
+
+//Lowercased runs of letters and digits, everything else separates terms.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| term.to_lowercase())
+        .collect()
+}
+
+//One page of a term's posting list.
+struct PostingPage {
+    data: Vec<u8>,
+}
+
+impl PostingPage {
+    fn new() -> Self {
+        let mut page = PostingPage { data: vec![0; PAGE_SIZE] };
+        page.data[NEXT_PAGE_OFFSET..NEXT_PAGE_OFFSET + 4].copy_from_slice(&NO_NEXT_PAGE.to_le_bytes());
+        page
+    }
+
+    fn get_u32(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn set_u32(&mut self, offset: usize, value: u32) {
+        self.data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn count(&self) -> usize {
+        u16::from_le_bytes(self.data[POSTING_COUNT_OFFSET..POSTING_COUNT_OFFSET + 2].try_into().unwrap()) as usize
+    }
+
+    fn next(&self) -> Option<usize> {
+        let page = self.get_u32(NEXT_PAGE_OFFSET);
+        (page != NO_NEXT_PAGE).then_some(page as usize)
+    }
+
+    fn set_next(&mut self, page: usize) {
+        self.set_u32(NEXT_PAGE_OFFSET, page as u32);
+    }
+
+    fn last_page(&self) -> usize {
+        self.get_u32(LAST_PAGE_OFFSET) as usize
+    }
+
+    fn set_last_page(&mut self, page: usize) {
+        self.set_u32(LAST_PAGE_OFFSET, page as u32);
+    }
+
+    fn live_postings(&self) -> usize {
+        self.get_u32(LIVE_POSTINGS_OFFSET) as usize
+    }
+
+    fn set_live_postings(&mut self, count: usize) {
+        self.set_u32(LIVE_POSTINGS_OFFSET, count as u32);
+    }
+
+    fn removed_postings(&self) -> usize {
+        self.get_u32(REMOVED_POSTINGS_OFFSET) as usize
+    }
+
+    fn set_removed_postings(&mut self, count: usize) {
+        self.set_u32(REMOVED_POSTINGS_OFFSET, count as u32);
+    }
+
+    fn try_append(&mut self, row_number: usize, frequency: u32) -> bool {
+        let count = self.count();
+        if count == POSTINGS_PER_PAGE {
+            return false;
+        }
+        let offset = POSTING_HEADER_SIZE + count * POSTING_SIZE;
+        self.data[offset..offset + 8].copy_from_slice(&(row_number as u64).to_le_bytes());
+        self.set_u32(offset + 8, frequency);
+        self.data[POSTING_COUNT_OFFSET..POSTING_COUNT_OFFSET + 2].copy_from_slice(&(count as u16 + 1).to_le_bytes());
+        true
+    }
+
+    //Sets the frequency of the live posting of `row_number` to 0, its slot stays taken.
+    fn tombstone(&mut self, row_number: usize) -> bool {
+        for i in 0..self.count() {
+            let offset = POSTING_HEADER_SIZE + i * POSTING_SIZE;
+            let row = u64::from_le_bytes(self.data[offset..offset + 8].try_into().unwrap()) as usize;
+            if row == row_number && self.get_u32(offset + 8) != 0 {
+                self.set_u32(offset + 8, 0);
+                return true;
+            }
+        }
+        false
+    }
+
+    //Postings that weren't removed.
+    fn postings(&self) -> impl Iterator<Item = (usize, u32)> + '_ {
+        (0..self.count())
+            .map(|i| {
+                let offset = POSTING_HEADER_SIZE + i * POSTING_SIZE;
+                let row_number = u64::from_le_bytes(self.data[offset..offset + 8].try_into().unwrap());
+                (row_number as usize, self.get_u32(offset + 8))
+            })
+            .filter(|(_, frequency)| *frequency != 0)
+    }
+}
+
+//Inverted index for full-text search. A hash index dictionary maps every term to
+//the first page of a chain of posting pages holding (row number, term frequency) pairs.
+//Every page lives in the pager, the root page holds the document count and the dictionary.
+//Removed postings are zeroed in place. A chain is rewritten without them once they fill a page
+//and outnumber the live ones, and freed once none of its postings is left.
+pub struct FullTextIndex {
+    pager: Rc<RefCell<Pager>>,
+    root: usize,
+    dictionary: HashIndex,
+    documents: usize,
+}
+
+impl FullTextIndex {
+    pub fn create(pager: Rc<RefCell<Pager>>) -> Self {
+        let root = pager.borrow_mut().allocate();
+        let dictionary = HashIndex::create(Rc::clone(&pager));
+        let index = FullTextIndex {
+            pager,
+            root,
+            dictionary,
+            documents: 0,
+        };
+        index.write_root();
+        index
+    }
+
+    //The index whose document count and dictionary were written to the `root` page.
+    pub fn open(pager: Rc<RefCell<Pager>>, root: usize) -> Self {
+        let data = pager.borrow_mut().read(root);
+        let read = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+        let dictionary = HashIndex::open(Rc::clone(&pager), read(DICTIONARY_ROOT_OFFSET));
+        FullTextIndex {
+            pager,
+            root,
+            dictionary,
+            documents: read(DOCUMENTS_OFFSET),
+        }
+    }
+
+    pub fn root(&self) -> usize {
+        self.root
+    }
+
+    fn write_root(&self) {
+        let mut data = vec![0; 8];
+        data[DOCUMENTS_OFFSET..DOCUMENTS_OFFSET + 4].copy_from_slice(&(self.documents as u32).to_le_bytes());
+        data[DICTIONARY_ROOT_OFFSET..DICTIONARY_ROOT_OFFSET + 4].copy_from_slice(&(self.dictionary.root() as u32).to_le_bytes());
+        self.pager.borrow_mut().write(self.root, &data);
+    }
+
+    fn read_page(&self, page: usize) -> PostingPage {
+        PostingPage {
+            data: self.pager.borrow_mut().read(page),
+        }
+    }
+
+    fn write_page(&self, page: usize, posting_page: &PostingPage) {
+        self.pager.borrow_mut().write(page, &posting_page.data);
+    }
+
+    //First page of the term's posting chain.
+    fn first_page(&self, term: &str) -> Option<usize> {
+        self.dictionary.lookup(term.as_bytes()).first().copied()
+    }
+
+    fn chain(&self, first_page: usize) -> Vec<usize> {
+        let mut chain = vec![first_page];
+        while let Some(next) = self.read_page(*chain.last().unwrap()).next() {
+            chain.push(next);
+        }
+        chain
+    }
+
+    pub fn insert(&mut self, row_number: usize, text: &str) -> Result<(), String> {
+        let mut frequencies: HashMap<String, u32> = HashMap::new();
+        for term in tokenize(text) {
+            *frequencies.entry(term).or_insert(0) += 1;
+        }
+        for (term, frequency) in frequencies {
+            let first_page = match self.first_page(&term) {
+                Some(page) => page,
+                None => {
+                    let page = self.pager.borrow_mut().allocate();
+                    let mut first = PostingPage::new();
+                    first.set_last_page(page);
+                    self.write_page(page, &first);
+                    self.dictionary.insert(term.as_bytes(), page)?;
+                    page
+                }
+            };
+            let mut first = self.read_page(first_page);
+            let last_page = first.last_page();
+            let mut last = if last_page == first_page { None } else { Some(self.read_page(last_page)) };
+            let appended = last.as_mut().unwrap_or(&mut first).try_append(row_number, frequency);
+            if !appended {
+                let page = self.pager.borrow_mut().allocate();
+                let mut new_page = PostingPage::new();
+                new_page.try_append(row_number, frequency);
+                self.write_page(page, &new_page);
+                last.as_mut().unwrap_or(&mut first).set_next(page);
+                first.set_last_page(page);
+            }
+            if let Some(last) = &last {
+                self.write_page(last_page, last);
+            }
+            first.set_live_postings(first.live_postings() + 1);
+            self.write_page(first_page, &first);
+        }
+        self.documents += 1;
+        self.write_root();
+        Ok(())
+    }
+
+    //Drops `row_number` from the posting lists of the terms of `text`, the text it was inserted with.
+    //The posting is zeroed in the page holding it, a chain left without postings is freed
+    //and one mostly made of removed postings is compacted.
+    pub fn remove(&mut self, row_number: usize, text: &str) {
+        let mut terms = tokenize(text);
+        terms.sort();
+        terms.dedup();
+        for term in terms {
+            let Some(first_page) = self.first_page(&term) else {
+                continue;
+            };
+            let mut first = self.read_page(first_page);
+            let mut removed = first.tombstone(row_number);
+            let mut next = first.next();
+            while !removed && let Some(page) = next {
+                let mut posting_page = self.read_page(page);
+                if posting_page.tombstone(row_number) {
+                    self.write_page(page, &posting_page);
+                    removed = true;
+                }
+                next = posting_page.next();
+            }
+            if !removed {
+                continue;
+            }
+            first.set_live_postings(first.live_postings() - 1);
+            first.set_removed_postings(first.removed_postings() + 1);
+            if first.live_postings() == 0 {
+                let chain = self.chain(first_page);
+                self.pager.borrow_mut().free(chain);
+                self.dictionary.remove(term.as_bytes(), first_page);
+                continue;
+            }
+            self.write_page(first_page, &first);
+            if first.removed_postings() >= POSTINGS_PER_PAGE && first.removed_postings() > first.live_postings() {
+                self.compact(first_page);
+            }
+        }
+        self.documents = self.documents.saturating_sub(1);
+        self.write_root();
+    }
+
+    //Rewrites the chain with its live postings only, the pages it no longer needs are freed.
+    fn compact(&mut self, first_page: usize) {
+        let postings = self.postings(first_page);
+        let chain = self.chain(first_page);
+        let kept = postings.len().div_ceil(POSTINGS_PER_PAGE);
+        for (n, chunk) in postings.chunks(POSTINGS_PER_PAGE).enumerate() {
+            let mut posting_page = PostingPage::new();
+            for (row_number, frequency) in chunk {
+                posting_page.try_append(*row_number, *frequency);
+            }
+            if n + 1 < kept {
+                posting_page.set_next(chain[n + 1]);
+            }
+            if n == 0 {
+                posting_page.set_last_page(chain[kept - 1]);
+                posting_page.set_live_postings(postings.len());
+            }
+            self.write_page(chain[n], &posting_page);
+        }
+        self.pager.borrow_mut().free(chain[kept..].to_vec());
+    }
+
+    fn postings(&self, first_page: usize) -> Vec<(usize, u32)> {
+        let mut postings = vec![];
+        let mut page = Some(first_page);
+        while let Some(current) = page {
+            let posting_page = self.read_page(current);
+            postings.extend(posting_page.postings());
+            page = posting_page.next();
+        }
+        postings
+    }
+
+    //Rows containing any of the query terms, best match first.
+    //Each term scores (1 + ln tf) * ln(1 + N / df), so rarer terms weigh more.
+    pub fn search(&self, query: &str) -> Vec<(usize, f64)> {
+        let mut terms = tokenize(query);
+        terms.sort();
+        terms.dedup();
+        let mut scores: HashMap<usize, f64> = HashMap::new();
+        for term in terms {
+            let Some(first_page) = self.first_page(&term) else {
+                continue;
+            };
+            let documents = self.read_page(first_page).live_postings();
+            let idf = (1.0 + self.documents as f64 / documents as f64).ln();
+            for (row_number, frequency) in self.postings(first_page) {
+                *scores.entry(row_number).or_insert(0.0) += (1.0 + (frequency as f64).ln()) * idf;
+            }
+        }
+        let mut ranked: Vec<(usize, f64)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked
+    }
+
+    //Pages of every posting chain.
+    fn posting_pages(&self) -> Vec<usize> {
+        self.dictionary
+            .values()
+            .into_iter()
+            .flat_map(|first_page| self.chain(first_page))
+            .collect()
+    }
+
+    //Frees every posting chain and empties the dictionary, the root page stays.
+    pub fn clear(&mut self) {
+        let pages = self.posting_pages();
+        self.pager.borrow_mut().free(pages);
+        self.dictionary.clear();
+        self.documents = 0;
+        self.write_root();
+    }
+
+    //Gives every page back to the pager, the index can't be used afterwards.
+    pub fn release(&mut self) {
+        let pages = self.posting_pages();
+        self.pager.borrow_mut().free(pages.into_iter().chain([self.root]));
+        self.dictionary.release();
+    }
+}
+
+impl std::fmt::Debug for FullTextIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FullTextIndex")
+            .field("root", &self.root)
+            .field("documents", &self.documents)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(ranked: &[(usize, f64)]) -> Vec<usize> {
+        ranked.iter().map(|(row_number, _)| *row_number).collect()
+    }
+
+    fn new_index(name: &str) -> FullTextIndex {
+        FullTextIndex::create(Rc::new(RefCell::new(Pager::temporary(name))))
+    }
+
+    fn common_rare(row_number: usize) -> &'static str {
+        if row_number.is_multiple_of(100) { "common rare" } else { "common" }
+    }
+
+    #[test]
+    fn test_tokenize_lowercases_and_splits() {
+        assert_eq!(tokenize("Quick, brown-FOX! 42x"), vec!["quick", "brown", "fox", "42x"]);
+        assert!(tokenize("  ... ").is_empty());
+    }
+
+    #[test]
+    fn test_search_ranks_by_relevance() {
+        let mut index = new_index("fulltext_rank");
+        index.insert(0, "the quick brown fox").unwrap();
+        index.insert(1, "the lazy dog").unwrap();
+        index.insert(2, "fox fox fox and the dog").unwrap();
+        index.insert(3, "nothing to see").unwrap();
+        assert_eq!(rows(&index.search("fox")), vec![2, 0]);
+        assert_eq!(rows(&index.search("FOX dog")), vec![2, 0, 1]);
+        assert_eq!(rows(&index.search("the")), vec![0, 1, 2]);
+        assert!(index.search("cat").is_empty());
+        assert!(index.search("").is_empty());
+    }
+
+    #[test]
+    fn test_posting_lists_span_pages() {
+        let mut index = new_index("fulltext_span");
+        for row_number in 0..1000 {
+            index.insert(row_number, common_rare(row_number)).unwrap();
+        }
+        assert!(index.posting_pages().len() > 2);
+        assert_eq!(index.search("common").len(), 1000);
+        assert_eq!(rows(&index.search("rare"))[..3], [0, 100, 200]);
+        assert_eq!(rows(&index.search("rare common"))[0], 0);
+
+        let reopened = FullTextIndex::open(Rc::clone(&index.pager), index.root());
+        assert_eq!(reopened.documents, 1000);
+        assert_eq!(reopened.search("rare common"), index.search("rare common"));
+    }
+
+    #[test]
+    fn test_remove_tombstones_postings_in_place() {
+        let mut index = new_index("fulltext_remove");
+        for row_number in 0..1000 {
+            index.insert(row_number, common_rare(row_number)).unwrap();
+        }
+        //1000 common postings take 3 pages and the rare ones 1.
+        let pages = index.posting_pages();
+        assert_eq!(pages.len(), 4);
+        for row_number in 0..300 {
+            index.remove(row_number, common_rare(row_number));
+        }
+        assert_eq!(index.posting_pages(), pages);
+        assert_eq!(rows(&index.search("rare")), (3..10).map(|n| n * 100).collect::<Vec<usize>>());
+
+        //Once removed postings fill a page and outnumber the live ones the chain is compacted.
+        for row_number in 300..900 {
+            index.remove(row_number, common_rare(row_number));
+        }
+        assert_eq!(index.posting_pages().len(), 2);
+        assert_eq!(rows(&index.search("common")), (900..1000).collect::<Vec<usize>>());
+        assert_eq!(rows(&index.search("rare")), vec![900]);
+
+        //The last rare posting takes its chain with it.
+        index.remove(900, "common rare");
+        assert!(index.search("rare").is_empty());
+        assert_eq!(index.posting_pages().len(), 1);
+        index.insert(900, "rare again").unwrap();
+        assert_eq!(rows(&index.search("again rare")), vec![900]);
+        assert_eq!(index.posting_pages().len(), 3);
+    }
+
+    #[test]
+    fn test_rewriting_a_row_keeps_its_chain_bounded() {
+        let mut index = new_index("fulltext_rewrite");
+        for row_number in 0..10 {
+            index.insert(row_number, "draft").unwrap();
+        }
+        //What UPDATE does to an indexed column: the old text is removed and the new one inserted.
+        for _ in 0..5000 {
+            index.remove(3, "draft");
+            index.insert(3, "draft").unwrap();
+        }
+        assert!(index.posting_pages().len() <= 2);
+        assert_eq!(rows(&index.search("draft")), (0..10).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_release_frees_every_page() {
+        let mut index = new_index("fulltext_release");
+        for row_number in 0..500 {
+            index.insert(row_number, common_rare(row_number)).unwrap();
+        }
+        index.clear();
+        assert!(index.search("common").is_empty());
+        index.insert(0, "again").unwrap();
+        assert_eq!(rows(&index.search("again")), vec![0]);
+        //With every page released the file is back to its header.
+        index.release();
+        assert_eq!(index.pager.borrow_mut().allocate(), 1);
+    }
+}
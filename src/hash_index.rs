@@ -0,0 +1,479 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::pager::Pager;
+use crate::table::PAGE_SIZE;
+
+/*1 byte global depth, then the 4 byte bucket page of every directory slot */
+const GLOBAL_DEPTH_OFFSET: usize = 0;
+const DIRECTORY_OFFSET: usize = 1;
+
+/*1+2+2+4 bytes page header, entries follow it */
+const LOCAL_DEPTH_OFFSET: usize = 0;
+const ENTRY_COUNT_OFFSET: usize = 1;
+const USED_BYTES_OFFSET: usize = 3;
+const OVERFLOW_PAGE_OFFSET: usize = 5;
+const BUCKET_HEADER_SIZE: usize = 9;
+/*8 byte hash, 8 byte row number, 2 byte key length, then the key */
+const ENTRY_HEADER_SIZE: usize = 18;
+const NO_OVERFLOW: u32 = u32::MAX;
+//Buckets whose entries still collide at this depth grow overflow pages instead of splitting.
+//The directory of 2^MAX_DEPTH slots has to fit in the root page.
+const MAX_DEPTH: u32 = 9;
+//A key has to fit in an empty bucket page next to its entry header.
+pub const MAX_KEY_SIZE: usize = PAGE_SIZE - BUCKET_HEADER_SIZE - ENTRY_HEADER_SIZE;
+
+struct Entry {
+    hash: u64,
+    row_number: usize,
+    key: Vec<u8>,
+}
+
+//One bucket page of the hash index.
+struct BucketPage {
+    data: Vec<u8>,
+}
+
+impl BucketPage {
+    fn new(local_depth: u32) -> Self {
+        let mut page = BucketPage {
+            data: vec![0; PAGE_SIZE],
+        };
+        page.data[LOCAL_DEPTH_OFFSET] = local_depth as u8;
+        page.set_u16(USED_BYTES_OFFSET, BUCKET_HEADER_SIZE as u16);
+        page.set_overflow(None);
+        page
+    }
+
+    fn get_u16(&self, offset: usize) -> u16 {
+        u16::from_le_bytes(self.data[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn set_u16(&mut self, offset: usize, value: u16) {
+        self.data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn local_depth(&self) -> u32 {
+        self.data[LOCAL_DEPTH_OFFSET] as u32
+    }
+
+    fn overflow(&self) -> Option<usize> {
+        let page = u32::from_le_bytes(
+            self.data[OVERFLOW_PAGE_OFFSET..OVERFLOW_PAGE_OFFSET + 4]
+                .try_into()
+                .unwrap(),
+        );
+        (page != NO_OVERFLOW).then_some(page as usize)
+    }
+
+    fn set_overflow(&mut self, page: Option<usize>) {
+        let page = page.map(|p| p as u32).unwrap_or(NO_OVERFLOW);
+        self.data[OVERFLOW_PAGE_OFFSET..OVERFLOW_PAGE_OFFSET + 4].copy_from_slice(&page.to_le_bytes());
+    }
+
+    fn try_append(&mut self, entry: &Entry) -> bool {
+        let used = self.get_u16(USED_BYTES_OFFSET) as usize;
+        let size = ENTRY_HEADER_SIZE + entry.key.len();
+        if used + size > PAGE_SIZE {
+            return false;
+        }
+        self.data[used..used + 8].copy_from_slice(&entry.hash.to_le_bytes());
+        self.data[used + 8..used + 16].copy_from_slice(&(entry.row_number as u64).to_le_bytes());
+        self.data[used + 16..used + 18].copy_from_slice(&(entry.key.len() as u16).to_le_bytes());
+        self.data[used + ENTRY_HEADER_SIZE..used + size].copy_from_slice(&entry.key);
+        let count = self.get_u16(ENTRY_COUNT_OFFSET);
+        self.set_u16(ENTRY_COUNT_OFFSET, count + 1);
+        self.set_u16(USED_BYTES_OFFSET, (used + size) as u16);
+        true
+    }
+
+    fn is_empty(&self) -> bool {
+        self.get_u16(ENTRY_COUNT_OFFSET) == 0
+    }
+
+    //Removes the entry of `row_number` under `key`, the entries after it move up to close the gap.
+    fn remove(&mut self, key_hash: u64, key: &[u8], row_number: usize) -> bool {
+        let used = self.get_u16(USED_BYTES_OFFSET) as usize;
+        let mut offset = BUCKET_HEADER_SIZE;
+        for entry in self.entries() {
+            let size = ENTRY_HEADER_SIZE + entry.key.len();
+            if entry.hash == key_hash && entry.row_number == row_number && entry.key == key {
+                self.data.copy_within(offset + size..used, offset);
+                self.data[used - size..used].fill(0);
+                let count = self.get_u16(ENTRY_COUNT_OFFSET);
+                self.set_u16(ENTRY_COUNT_OFFSET, count - 1);
+                self.set_u16(USED_BYTES_OFFSET, (used - size) as u16);
+                return true;
+            }
+            offset += size;
+        }
+        false
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        let mut entries = vec![];
+        let mut offset = BUCKET_HEADER_SIZE;
+        for _ in 0..self.get_u16(ENTRY_COUNT_OFFSET) {
+            let hash = u64::from_le_bytes(self.data[offset..offset + 8].try_into().unwrap());
+            let row_number = u64::from_le_bytes(self.data[offset + 8..offset + 16].try_into().unwrap());
+            let len = self.get_u16(offset + 16) as usize;
+            let key = self.data[offset + ENTRY_HEADER_SIZE..offset + ENTRY_HEADER_SIZE + len].to_vec();
+            entries.push(Entry {
+                hash,
+                row_number: row_number as usize,
+                key,
+            });
+            offset += ENTRY_HEADER_SIZE + len;
+        }
+        entries
+    }
+}
+
+//FNV-1a, stable across runs so the same key always lands in the same bucket.
+fn hash(key: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in key {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+//Extendible hash index for equality lookups. The directory maps the low
+//`global_depth` bits of a key's hash to a bucket page, full buckets split
+//and the directory doubles when a bucket is already at the global depth.
+//Buckets are pager pages, the directory is kept in the root page and
+//mirrored in memory.
+pub struct HashIndex {
+    pager: Rc<RefCell<Pager>>,
+    root: usize,
+    directory: Vec<usize>,
+    global_depth: u32,
+}
+
+impl HashIndex {
+    //A new index with a single empty bucket.
+    pub fn create(pager: Rc<RefCell<Pager>>) -> Self {
+        let root = pager.borrow_mut().allocate();
+        let mut index = HashIndex {
+            pager,
+            root,
+            directory: vec![],
+            global_depth: 0,
+        };
+        let bucket = index.allocate_page(0);
+        index.directory.push(bucket);
+        index.write_directory();
+        index
+    }
+
+    //The index whose directory was written to the `root` page.
+    pub fn open(pager: Rc<RefCell<Pager>>, root: usize) -> Self {
+        let data = pager.borrow_mut().read(root);
+        let global_depth = data[GLOBAL_DEPTH_OFFSET] as u32;
+        let directory = (0..1usize << global_depth)
+            .map(|slot| {
+                let offset = DIRECTORY_OFFSET + slot * 4;
+                u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize
+            })
+            .collect();
+        HashIndex {
+            pager,
+            root,
+            directory,
+            global_depth,
+        }
+    }
+
+    pub fn root(&self) -> usize {
+        self.root
+    }
+
+    fn write_directory(&mut self) {
+        let mut data = vec![0; PAGE_SIZE];
+        data[GLOBAL_DEPTH_OFFSET] = self.global_depth as u8;
+        for (slot, page) in self.directory.iter().enumerate() {
+            let offset = DIRECTORY_OFFSET + slot * 4;
+            data[offset..offset + 4].copy_from_slice(&(*page as u32).to_le_bytes());
+        }
+        self.pager.borrow_mut().write(self.root, &data);
+    }
+
+    fn read_page(&self, page: usize) -> BucketPage {
+        BucketPage {
+            data: self.pager.borrow_mut().read(page),
+        }
+    }
+
+    fn write_page(&self, page: usize, bucket: &BucketPage) {
+        self.pager.borrow_mut().write(page, &bucket.data);
+    }
+
+    fn allocate_page(&mut self, local_depth: u32) -> usize {
+        let page = self.pager.borrow_mut().allocate();
+        self.write_page(page, &BucketPage::new(local_depth));
+        page
+    }
+
+    fn bucket_for(&self, hash: u64) -> usize {
+        self.directory[(hash & ((1u64 << self.global_depth) - 1)) as usize]
+    }
+
+    //The pages of a bucket, its overflow pages after it.
+    fn chain(&self, bucket: usize) -> Vec<(usize, BucketPage)> {
+        let mut chain = vec![(bucket, self.read_page(bucket))];
+        while let Some(next) = chain.last().unwrap().1.overflow() {
+            chain.push((next, self.read_page(next)));
+        }
+        chain
+    }
+
+    fn append_to_chain(&mut self, bucket: usize, entry: &Entry, allow_overflow: bool) -> bool {
+        let (last, mut page) = self.chain(bucket).pop().unwrap();
+        if page.try_append(entry) {
+            self.write_page(last, &page);
+            return true;
+        }
+        if !allow_overflow {
+            return false;
+        }
+        let overflow = self.pager.borrow_mut().allocate();
+        let mut overflow_page = BucketPage::new(page.local_depth());
+        overflow_page.try_append(entry);
+        self.write_page(overflow, &overflow_page);
+        page.set_overflow(Some(overflow));
+        self.write_page(last, &page);
+        true
+    }
+
+    //Keys have to fit in a bucket page, longer ones can't be stored.
+    pub fn check_key(key: &[u8]) -> Result<(), String> {
+        if key.len() > MAX_KEY_SIZE {
+            return Err(format!("Hash index key of {} bytes is longer than {} bytes", key.len(), MAX_KEY_SIZE));
+        }
+        Ok(())
+    }
+
+    pub fn insert(&mut self, key: &[u8], row_number: usize) -> Result<(), String> {
+        Self::check_key(key)?;
+        let entry = Entry {
+            hash: hash(key),
+            row_number,
+            key: key.to_vec(),
+        };
+        loop {
+            let bucket = self.bucket_for(entry.hash);
+            if self.append_to_chain(bucket, &entry, false) {
+                return Ok(());
+            }
+            let chain = self.chain(bucket);
+            //Splitting can't separate entries that share the whole hash.
+            let all_same_hash = chain
+                .iter()
+                .flat_map(|(_, page)| page.entries())
+                .all(|e| e.hash == entry.hash);
+            if all_same_hash || chain[0].1.local_depth() >= MAX_DEPTH {
+                self.append_to_chain(bucket, &entry, true);
+                return Ok(());
+            }
+            self.split(bucket);
+        }
+    }
+
+    fn split(&mut self, bucket: usize) {
+        let chain = self.chain(bucket);
+        let local_depth = chain[0].1.local_depth();
+        if local_depth == self.global_depth {
+            self.directory.extend_from_within(..);
+            self.global_depth += 1;
+        }
+        let entries: Vec<Entry> = chain.iter().flat_map(|(_, page)| page.entries()).collect();
+        self.pager.borrow_mut().free(chain.iter().skip(1).map(|(page, _)| *page));
+        self.write_page(bucket, &BucketPage::new(local_depth + 1));
+        let sibling = self.allocate_page(local_depth + 1);
+        for (slot, page) in self.directory.iter_mut().enumerate() {
+            if *page == bucket && (slot >> local_depth) & 1 == 1 {
+                *page = sibling;
+            }
+        }
+        self.write_directory();
+        for entry in entries.iter() {
+            let target = self.bucket_for(entry.hash);
+            self.append_to_chain(target, entry, true);
+        }
+    }
+
+    pub fn lookup(&self, key: &[u8]) -> Vec<usize> {
+        let key_hash = hash(key);
+        self.chain(self.bucket_for(key_hash))
+            .iter()
+            .flat_map(|(_, page)| page.entries())
+            .filter(|entry| entry.hash == key_hash && entry.key == key)
+            .map(|entry| entry.row_number)
+            .collect()
+    }
+
+    //Removes the entry of `row_number` under `key` from the page holding it.
+    //An overflow page left empty is unlinked from its chain and freed.
+    pub fn remove(&mut self, key: &[u8], row_number: usize) -> bool {
+        let key_hash = hash(key);
+        let mut chain = self.chain(self.bucket_for(key_hash));
+        for n in 0..chain.len() {
+            if !chain[n].1.remove(key_hash, key, row_number) {
+                continue;
+            }
+            let (page, bucket) = &chain[n];
+            if n > 0 && bucket.is_empty() {
+                let (page, next) = (*page, bucket.overflow());
+                let (previous, previous_bucket) = &mut chain[n - 1];
+                previous_bucket.set_overflow(next);
+                self.write_page(*previous, previous_bucket);
+                self.pager.borrow_mut().free([page]);
+            } else {
+                self.write_page(*page, bucket);
+            }
+            return true;
+        }
+        false
+    }
+
+    //First page of every bucket, slots sharing a bucket point at the same page.
+    fn buckets(&self) -> Vec<usize> {
+        let mut buckets = self.directory.clone();
+        buckets.sort();
+        buckets.dedup();
+        buckets
+    }
+
+    //Every page of the index, the root first.
+    fn pages(&self) -> Vec<usize> {
+        let mut pages = vec![self.root];
+        for bucket in self.buckets() {
+            pages.extend(self.chain(bucket).into_iter().map(|(page, _)| page));
+        }
+        pages
+    }
+
+    //Row numbers of every entry, in no particular order.
+    pub fn values(&self) -> Vec<usize> {
+        self.buckets()
+            .into_iter()
+            .flat_map(|bucket| self.chain(bucket))
+            .flat_map(|(_, page)| page.entries())
+            .map(|entry| entry.row_number)
+            .collect()
+    }
+
+    //Frees every bucket and starts over with a single empty one, the root page stays.
+    pub fn clear(&mut self) {
+        let buckets: Vec<usize> = self.pages().into_iter().skip(1).collect();
+        self.pager.borrow_mut().free(buckets);
+        self.global_depth = 0;
+        self.directory = vec![self.allocate_page(0)];
+        self.write_directory();
+    }
+
+    //Gives every page back to the pager, the index can't be used afterwards.
+    pub fn release(&mut self) {
+        let pages = self.pages();
+        self.pager.borrow_mut().free(pages);
+        self.directory.clear();
+    }
+}
+
+impl std::fmt::Debug for HashIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HashIndex")
+            .field("root", &self.root)
+            .field("global_depth", &self.global_depth)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(n: u64) -> Vec<u8> {
+        format!("key-{}", n).into_bytes()
+    }
+
+    fn new_index(name: &str) -> HashIndex {
+        HashIndex::create(Rc::new(RefCell::new(Pager::temporary(name))))
+    }
+
+    #[test]
+    fn test_insert_and_lookup_splits_buckets() {
+        let mut index = new_index("hash_split");
+        for n in 0..5000 {
+            index.insert(&key(n), n as usize).unwrap();
+        }
+        assert!(index.global_depth > 0);
+        assert!(index.pages().len() > 2);
+        for n in (0..5000).step_by(97) {
+            assert_eq!(index.lookup(&key(n)), vec![n as usize]);
+        }
+        assert!(index.lookup(&key(5000)).is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_keys_use_overflow_pages() {
+        let mut index = new_index("hash_duplicates");
+        for n in 0..1000 {
+            index.insert(b"same", n).unwrap();
+        }
+        index.insert(b"other", 1000).unwrap();
+        let mut rows = index.lookup(b"same");
+        rows.sort();
+        assert_eq!(rows, (0..1000).collect::<Vec<usize>>());
+        assert_eq!(index.lookup(b"other"), vec![1000]);
+    }
+
+    #[test]
+    fn test_remove_frees_emptied_overflow_pages() {
+        let mut index = new_index("hash_remove");
+        for n in 0..1000 {
+            index.insert(b"same", n).unwrap();
+        }
+        let pages = index.pages().len();
+        for n in 0..900 {
+            assert!(index.remove(b"same", n));
+        }
+        assert!(!index.remove(b"same", 0));
+        assert!(!index.remove(b"other", 950));
+        let mut rows = index.lookup(b"same");
+        rows.sort();
+        assert_eq!(rows, (900..1000).collect::<Vec<usize>>());
+        assert!(index.pages().len() < pages);
+    }
+
+    #[test]
+    fn test_reopen_reads_the_directory_from_the_root_page() {
+        let mut index = new_index("hash_reopen");
+        for n in 0..2000 {
+            index.insert(&key(n), n as usize).unwrap();
+        }
+        let reopened = HashIndex::open(Rc::clone(&index.pager), index.root());
+        assert_eq!(reopened.global_depth, index.global_depth);
+        assert_eq!(reopened.directory, index.directory);
+        assert_eq!(reopened.lookup(&key(1234)), vec![1234]);
+
+        index.clear();
+        assert_eq!(index.pages().len(), 2);
+        assert!(HashIndex::open(Rc::clone(&index.pager), index.root()).lookup(&key(1234)).is_empty());
+        //With every page released the file is back to its header.
+        index.release();
+        assert_eq!(index.pager.borrow_mut().allocate(), 1);
+    }
+
+    #[test]
+    fn test_keys_longer_than_a_page_are_rejected() {
+        let mut index = new_index("hash_long_key");
+        let longest = vec![b'x'; MAX_KEY_SIZE];
+        index.insert(&longest, 0).unwrap();
+        assert_eq!(
+            index.insert(&[longest.as_slice(), b"x"].concat(), 1),
+            Err(format!("Hash index key of {} bytes is longer than {} bytes", MAX_KEY_SIZE + 1, MAX_KEY_SIZE))
+        );
+        assert_eq!(index.lookup(&longest), vec![0]);
+    }
+}
@@ -0,0 +1,676 @@
+use std::{cell::RefCell, ops::Bound, rc::Rc};
+
+use crate::bplustree::BPlusTree;
+use crate::fulltext::FullTextIndex;
+use crate::hash_index::HashIndex;
+use crate::key::EncodedKey;
+use crate::pager::Pager;
+use crate::table::Value;
+use uuid::Uuid;
+
+pub const INDEX_ORDER: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum KeyFunction {
+    LOWER,
+    UPPER,
+}
+
+impl KeyFunction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "LOWER" => Some(KeyFunction::LOWER),
+            "UPPER" => Some(KeyFunction::UPPER),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, value: &Value) -> Value {
+        match (self, value) {
+            (KeyFunction::LOWER, Value::String(value)) => Value::String(value.to_lowercase()),
+            (KeyFunction::UPPER, Value::String(value)) => Value::String(value.to_uppercase()),
+            (_, value) => value.clone(),
+        }
+    }
+}
+
+//`column = value`, rows that don't satisfy it are left out of a partial index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Predicate {
+    pub position: usize,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum IndexMethod {
+    #[default]
+    BTREE,
+    HASH,
+    FULLTEXT,
+}
+
+impl IndexMethod {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "BTREE" => Some(IndexMethod::BTREE),
+            "HASH" => Some(IndexMethod::HASH),
+            "FULLTEXT" => Some(IndexMethod::FULLTEXT),
+            _ => None,
+        }
+    }
+}
+
+//An index as written in CREATE INDEX, before its columns are resolved against the table.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexDefinition {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub functions: Vec<Option<KeyFunction>>,
+    pub included: Vec<String>,
+    pub predicate: Option<(String, Value)>,
+    pub method: IndexMethod,
+}
+
+//Secondary index over one or more columns of a table.
+//Keys are the encoded column values followed by the row number,
+//so rows sharing the same column values still get distinct keys.
+//Leaf values hold the row number and the values of the key and included columns,
+//which lets queries touching only those columns skip the table pages.
+#[derive(Debug)]
+pub struct Index {
+    pub name: String,
+    positions: Vec<usize>,
+    pub functions: Vec<Option<KeyFunction>>,
+    included_positions: Vec<usize>,
+    pub predicate: Option<Predicate>,
+    storage: Storage,
+}
+
+//B+tree indexes answer ranges and prefixes, hash indexes only whole-key equality
+//and full-text indexes only MATCH searches.
+#[derive(Debug)]
+enum Storage {
+    BTree(BPlusTree<EncodedKey>),
+    Hash(HashIndex),
+    FullText(FullTextIndex),
+}
+
+fn encode_prefix(values: &[&Value]) -> EncodedKey {
+    let mut key = EncodedKey::new();
+    for value in values {
+        key.push_value(value);
+    }
+    key
+}
+
+fn row_number(value: &[u8]) -> usize {
+    u64::from_le_bytes(value[0..8].try_into().unwrap()) as usize
+}
+
+pub fn write_value(buff: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Int(value) => {
+            buff.push(0);
+            buff.extend_from_slice(&value.to_le_bytes());
+        }
+        Value::String(value) => {
+            buff.push(1);
+            buff.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            buff.extend_from_slice(value.as_bytes());
+        }
+        Value::Uuid(value) => {
+            buff.push(2);
+            buff.extend_from_slice(value.as_bytes());
+        }
+        Value::Float(value) => {
+            buff.push(3);
+            buff.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+}
+
+pub fn read_value(buff: &[u8], offset: &mut usize) -> Value {
+    let tag = buff[*offset];
+    *offset += 1;
+    match tag {
+        0 => {
+            let value = i64::from_le_bytes(buff[*offset..*offset + 8].try_into().unwrap());
+            *offset += 8;
+            Value::Int(value)
+        }
+        1 => {
+            let len = u32::from_le_bytes(buff[*offset..*offset + 4].try_into().unwrap()) as usize;
+            *offset += 4;
+            let value = String::from_utf8_lossy(&buff[*offset..*offset + len]).to_string();
+            *offset += len;
+            Value::String(value)
+        }
+        2 => {
+            let value = Uuid::from_slice(&buff[*offset..*offset + 16]).unwrap();
+            *offset += 16;
+            Value::Uuid(value)
+        }
+        3 => {
+            let value = f64::from_le_bytes(buff[*offset..*offset + 8].try_into().unwrap());
+            *offset += 8;
+            Value::Float(value)
+        }
+        _ => panic!("Invalid value tag in index entry"),
+    }
+}
+
+impl Index {
+    //positions are the offsets of the indexed columns within a decoded row.
+    //Hash and full-text indexes keep their pages in the pager, `root` opens one written before.
+    pub fn new(
+        definition: IndexDefinition,
+        positions: Vec<usize>,
+        included_positions: Vec<usize>,
+        predicate: Option<Predicate>,
+        pager: &Rc<RefCell<Pager>>,
+        root: Option<usize>,
+    ) -> Self {
+        let mut functions = definition.functions;
+        functions.resize(positions.len(), None);
+        Index {
+            name: definition.name,
+            positions,
+            functions,
+            included_positions,
+            predicate,
+            storage: match definition.method {
+                IndexMethod::BTREE => Storage::BTree(BPlusTree::new(INDEX_ORDER)),
+                IndexMethod::HASH => Storage::Hash(match root {
+                    Some(root) => HashIndex::open(Rc::clone(pager), root),
+                    None => HashIndex::create(Rc::clone(pager)),
+                }),
+                IndexMethod::FULLTEXT => Storage::FullText(match root {
+                    Some(root) => FullTextIndex::open(Rc::clone(pager), root),
+                    None => FullTextIndex::create(Rc::clone(pager)),
+                }),
+            },
+        }
+    }
+
+    //Page the index is opened from again, None for indexes rebuilt from the rows.
+    pub fn root(&self) -> Option<usize> {
+        match &self.storage {
+            Storage::Hash(hash) => Some(hash.root()),
+            Storage::FullText(text) => Some(text.root()),
+            Storage::BTree(_) => None,
+        }
+    }
+
+    pub fn method(&self) -> IndexMethod {
+        match self.storage {
+            Storage::BTree(_) => IndexMethod::BTREE,
+            Storage::Hash(_) => IndexMethod::HASH,
+            Storage::FullText(_) => IndexMethod::FULLTEXT,
+        }
+    }
+
+    pub fn positions(&self) -> &[usize] {
+        &self.positions
+    }
+
+    pub fn included_positions(&self) -> &[usize] {
+        &self.included_positions
+    }
+
+    //Columns whose plain values are stored in the leaves, None for expression keys.
+    fn stored_columns(&self) -> Vec<Option<usize>> {
+        let keys = self
+            .positions
+            .iter()
+            .zip(self.functions.iter())
+            .map(|(position, function)| function.is_none().then_some(*position));
+        keys.chain(self.included_positions.iter().map(|p| Some(*p)))
+            .collect()
+    }
+
+    //Whether the index holds every row matching the query's `column = value` conjuncts.
+    pub fn usable_for(&self, conjuncts: &[Predicate]) -> bool {
+        match &self.predicate {
+            Some(predicate) => conjuncts.contains(predicate),
+            None => true,
+        }
+    }
+
+    //Whether a range on `function(column)` can be looked up through the `nth` key column.
+    pub fn matches_key(&self, nth: usize, position: usize, function: Option<KeyFunction>) -> bool {
+        self.positions.get(nth) == Some(&position) && self.functions[nth] == function
+    }
+
+    //Whether every requested column can be read from the index leaves.
+    pub fn covers(&self, positions: &[usize], conjuncts: &[Predicate]) -> bool {
+        let stored = self.stored_columns();
+        self.method() == IndexMethod::BTREE
+            && self.usable_for(conjuncts)
+            && positions
+                .iter()
+                .all(|position| stored.contains(&Some(*position)))
+    }
+
+    //Index-only scan in key order, returning the requested columns of every row.
+    pub fn scan_covered(&self, positions: &[usize]) -> Vec<Vec<Value>> {
+        let Storage::BTree(tree) = &self.storage else {
+            panic!("index-only scans need a B+tree index");
+        };
+        let stored = self.stored_columns();
+        tree.iter()
+            .map(|(_, entry)| {
+                let mut offset = 8;
+                let values: Vec<Value> = stored
+                    .iter()
+                    .map(|_| read_value(&entry, &mut offset))
+                    .collect();
+                positions
+                    .iter()
+                    .map(|position| {
+                        let i = stored.iter().position(|p| *p == Some(*position)).unwrap();
+                        values[i].clone()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn accepts(&self, row: &[Value]) -> bool {
+        match &self.predicate {
+            Some(predicate) => row[predicate.position] == predicate.value,
+            None => true,
+        }
+    }
+
+    //Builds the index over existing rows with the bulk loader instead of inserting one by one.
+    //An index that can't hold one of the rows gives its pages back.
+    pub fn build(
+        mut index: Index,
+        rows: impl IntoIterator<Item = (usize, Vec<Value>)>,
+    ) -> Result<Self, String> {
+        if index.method() != IndexMethod::BTREE {
+            for (row_number, row) in rows {
+                if let Err(error) = index.insert_row(row_number, &row) {
+                    index.release();
+                    return Err(error);
+                }
+            }
+            return Ok(index);
+        }
+        let mut entries: Vec<(EncodedKey, Vec<u8>)> = rows
+            .into_iter()
+            .filter(|(_, row)| index.accepts(row))
+            .map(|(row_number, row)| index.entry_for(row_number, &row))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        index.storage = Storage::BTree(BPlusTree::bulk_load(INDEX_ORDER, entries));
+        Ok(index)
+    }
+
+    //Key values after the key functions are applied.
+    fn key_values(&self, row: &[Value]) -> Vec<Value> {
+        self.positions
+            .iter()
+            .zip(self.functions.iter())
+            .map(|(position, function)| match function {
+                Some(function) => function.apply(&row[*position]),
+                None => row[*position].clone(),
+            })
+            .collect()
+    }
+
+    fn entry_for(&self, row_number: usize, row: &[Value]) -> (EncodedKey, Vec<u8>) {
+        let values = self.key_values(row);
+        let mut key = encode_prefix(&values.iter().collect::<Vec<&Value>>());
+        key.push_int(row_number as i64);
+        let mut entry = (row_number as u64).to_le_bytes().to_vec();
+        for value in values.iter() {
+            write_value(&mut entry, value);
+        }
+        for position in self.included_positions.iter() {
+            write_value(&mut entry, &row[*position]);
+        }
+        (key, entry)
+    }
+
+    //Rejects a row whose key the index can't store, so the table can check before writing it.
+    pub fn check_row(&self, row: &[Value]) -> Result<(), String> {
+        if self.method() == IndexMethod::HASH && self.accepts(row) {
+            let values = self.key_values(row);
+            HashIndex::check_key(encode_prefix(&values.iter().collect::<Vec<&Value>>()).as_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn insert_row(&mut self, row_number: usize, row: &[Value]) -> Result<(), String> {
+        if !self.accepts(row) {
+            return Ok(());
+        }
+        let values = self.key_values(row);
+        let entry = (self.method() == IndexMethod::BTREE).then(|| self.entry_for(row_number, row));
+        match &mut self.storage {
+            Storage::BTree(tree) => {
+                let (key, value) = entry.unwrap();
+                tree.insert(key, value);
+            }
+            //Hash entries are keyed by the values alone, the bucket stores the row number.
+            Storage::Hash(hash) => {
+                let key = encode_prefix(&values.iter().collect::<Vec<&Value>>());
+                hash.insert(key.as_bytes(), row_number)?;
+            }
+            Storage::FullText(text) => text.insert(row_number, &values[0].to_string())?,
+        }
+        Ok(())
+    }
+
+    //Removes every entry, the definition stays.
+    pub fn clear(&mut self) {
+        match &mut self.storage {
+            Storage::BTree(tree) => *tree = BPlusTree::new(INDEX_ORDER),
+            Storage::Hash(hash) => hash.clear(),
+            Storage::FullText(text) => text.clear(),
+        }
+    }
+
+    //Gives the index's pages back to the pager when it or its table is dropped.
+    pub fn release(&mut self) {
+        match &mut self.storage {
+            Storage::BTree(_) => {}
+            Storage::Hash(hash) => hash.release(),
+            Storage::FullText(text) => text.release(),
+        }
+    }
+
+    //Drops the entry `insert_row` added for the row, `row` being the values it was inserted with.
+    pub fn remove_row(&mut self, row_number: usize, row: &[Value]) {
+        if !self.accepts(row) {
+            return;
+        }
+        let values = self.key_values(row);
+        let key = (self.method() == IndexMethod::BTREE).then(|| self.entry_for(row_number, row).0);
+        match &mut self.storage {
+            Storage::BTree(tree) => {
+                tree.delete(&key.unwrap());
+            }
+            Storage::Hash(hash) => {
+                let key = encode_prefix(&values.iter().collect::<Vec<&Value>>());
+                hash.remove(key.as_bytes(), row_number);
+            }
+            Storage::FullText(text) => text.remove(row_number, &values[0].to_string()),
+        }
+    }
+
+    //Rows whose leading indexed columns equal `prefix`.
+    //A hash index can only be probed with a value for every key column.
+    pub fn lookup_prefix(&self, prefix: &[Value]) -> Vec<usize> {
+        match &self.storage {
+            Storage::Hash(hash) => {
+                assert_eq!(prefix.len(), self.positions.len(), "hash index lookups need the whole key");
+                let key = encode_prefix(&prefix.iter().collect::<Vec<&Value>>());
+                hash.lookup(key.as_bytes())
+            }
+            Storage::BTree(_) => self.scan_range(prefix, Bound::Unbounded, Bound::Unbounded),
+            Storage::FullText(_) => panic!("full-text indexes are searched with MATCH"),
+        }
+    }
+
+    //Level by level dump of the index's B+tree followed by the invariant check result.
+    pub fn dump_tree(&self) -> Result<String, String> {
+        let Storage::BTree(tree) = &self.storage else {
+            return Err(format!("Index {} is not a B+tree index", self.name));
+        };
+        let mut out = tree.dump(|key| key.as_bytes().iter().map(|byte| format!("{:02x}", byte)).collect());
+        match tree.check() {
+            Ok(()) => out.push_str("invariants ok\n"),
+            Err(error) => out.push_str(&format!("invariant violated: {}\n", error)),
+        }
+        Ok(out)
+    }
+
+    //Rows matching any of the query's terms with their relevance, best first.
+    pub fn search(&self, query: &str) -> Vec<(usize, f64)> {
+        let Storage::FullText(text) = &self.storage else {
+            panic!("MATCH needs a full-text index");
+        };
+        text.search(query)
+    }
+
+    //Rows whose leading columns equal `prefix` and whose next column lies within the bounds.
+    pub fn scan_range(
+        &self,
+        prefix: &[Value],
+        lower: Bound<&Value>,
+        upper: Bound<&Value>,
+    ) -> Vec<usize> {
+        let Storage::BTree(tree) = &self.storage else {
+            panic!("range scans need a B+tree index");
+        };
+        assert!(
+            prefix.len() < self.positions.len() || (lower == Bound::Unbounded && upper == Bound::Unbounded),
+            "range column is not part of the index"
+        );
+        let prefix: Vec<&Value> = prefix.iter().collect();
+        let prefix_key = encode_prefix(&prefix);
+        let bounded = |value: &Value| {
+            let mut values = prefix.clone();
+            values.push(value);
+            encode_prefix(&values)
+        };
+        let start = match lower {
+            Bound::Included(value) | Bound::Excluded(value) => bounded(value),
+            Bound::Unbounded => prefix_key.clone(),
+        };
+        let excluded_start = match lower {
+            Bound::Excluded(value) => Some(bounded(value)),
+            _ => None,
+        };
+        let end = match upper {
+            Bound::Included(value) => Bound::Included(bounded(value)),
+            Bound::Excluded(value) => Bound::Excluded(bounded(value)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let mut rows = vec![];
+        for (key, value) in tree.range(Bound::Included(&start), Bound::Unbounded) {
+            let key = key.as_bytes();
+            if !key.starts_with(prefix_key.as_bytes()) {
+                break;
+            }
+            if let Some(excluded) = &excluded_start
+                && key.starts_with(excluded.as_bytes())
+            {
+                continue;
+            }
+            let in_range = match &end {
+                Bound::Included(end) => key <= end.as_bytes() || key.starts_with(end.as_bytes()),
+                Bound::Excluded(end) => key < end.as_bytes(),
+                Bound::Unbounded => true,
+            };
+            if !in_range {
+                break;
+            }
+            rows.push(row_number(&value));
+        }
+        rows
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pager(name: &str) -> Rc<RefCell<Pager>> {
+        Rc::new(RefCell::new(Pager::temporary(name)))
+    }
+
+    fn definition(name: &str, columns: &[&str], included: &[&str]) -> IndexDefinition {
+        IndexDefinition {
+            name: name.to_string(),
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            included: included.iter().map(|c| c.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn string(value: &str) -> Value {
+        Value::String(value.to_string())
+    }
+
+    //(user_id, amount) over rows of (order_id, user_id, amount).
+    fn orders_index() -> Index {
+        let mut index = Index::new(
+            definition("orders_user_amount", &["user_id", "amount"], &[]),
+            vec![1, 2],
+            vec![],
+            None,
+            &pager("index_orders"),
+            None,
+        );
+        let rows = [(100, 1, 50), (101, 2, 75), (102, 1, 25), (103, -1, 10), (104, 1, 50)];
+        for (row_number, (order_id, user_id, amount)) in rows.iter().enumerate() {
+            index.insert_row(
+                row_number,
+                &[Value::Int(*order_id), Value::Int(*user_id), Value::Int(*amount)],
+            ).unwrap();
+        }
+        index
+    }
+
+    #[test]
+    fn test_prefix_lookup_on_leading_column() {
+        let index = orders_index();
+        assert_eq!(index.lookup_prefix(&[Value::Int(1)]), vec![2, 0, 4]);
+        assert_eq!(index.lookup_prefix(&[Value::Int(-1)]), vec![3]);
+        assert_eq!(index.lookup_prefix(&[Value::Int(1), Value::Int(50)]), vec![0, 4]);
+        assert!(index.lookup_prefix(&[Value::Int(3)]).is_empty());
+        assert_eq!(index.lookup_prefix(&[]).len(), 5);
+    }
+
+    #[test]
+    fn test_remove_row_drops_only_its_entry() {
+        let mut index = orders_index();
+        index.remove_row(0, &[Value::Int(100), Value::Int(1), Value::Int(50)]);
+        assert_eq!(index.lookup_prefix(&[Value::Int(1)]), vec![2, 4]);
+        index.insert_row(0, &[Value::Int(100), Value::Int(2), Value::Int(5)]).unwrap();
+        assert_eq!(index.lookup_prefix(&[Value::Int(2)]), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_build_from_existing_rows() {
+        let rows = (0..500).map(|n| (n as usize, vec![Value::Int(n), Value::Int(n % 7), Value::Int(-n)]));
+        let index = Index::new(definition("by_mod", &["m"], &[]), vec![1], vec![], None, &pager("index_build"), None);
+        let mut index = Index::build(index, rows).unwrap();
+        assert_eq!(index.lookup_prefix(&[Value::Int(3)]).len(), 71);
+        index.insert_row(500, &[Value::Int(500), Value::Int(3), Value::Int(-500)]).unwrap();
+        assert_eq!(index.lookup_prefix(&[Value::Int(3)]).last(), Some(&500));
+        assert_eq!(index.lookup_prefix(&[]).len(), 501);
+    }
+
+    #[test]
+    fn test_covering_scan_reads_included_columns() {
+        let index = Index::new(definition("by_name", &["name"], &["note"]), vec![1], vec![3], None, &pager("index_covering"), None);
+        let rows = vec![
+            (0, vec![Value::Int(1), Value::String("bob".to_string()), Value::Int(7), Value::String("b".to_string())]),
+            (1, vec![Value::Int(2), Value::String("alice".to_string()), Value::Int(8), Value::String("".to_string())]),
+        ];
+        let index = Index::build(index, rows).unwrap();
+        assert!(index.covers(&[3, 1], &[]));
+        assert!(!index.covers(&[1, 2], &[]));
+        assert_eq!(
+            index.scan_covered(&[3, 1]),
+            vec![
+                vec![Value::String("".to_string()), Value::String("alice".to_string())],
+                vec![Value::String("b".to_string()), Value::String("bob".to_string())],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_partial_index_skips_rows_failing_predicate() {
+        let open = Predicate { position: 2, value: string("open") };
+        let index = Index::new(definition("open_by_owner", &["owner"], &[]), vec![1], vec![], Some(open.clone()), &pager("index_partial"), None);
+        let rows = vec![
+            (0, vec![Value::Int(1), string("ann"), string("open")]),
+            (1, vec![Value::Int(2), string("ann"), string("closed")]),
+        ];
+        let mut index = Index::build(index, rows).unwrap();
+        index.insert_row(2, &[Value::Int(3), string("ann"), string("open")]).unwrap();
+        index.insert_row(3, &[Value::Int(4), string("bo"), string("closed")]).unwrap();
+        assert_eq!(index.lookup_prefix(&[string("ann")]), vec![0, 2]);
+        assert!(index.lookup_prefix(&[string("bo")]).is_empty());
+
+        let other = Predicate { position: 1, value: string("ann") };
+        assert!(index.usable_for(&[other.clone(), open.clone()]));
+        assert!(!index.usable_for(&[other]));
+        assert!(!index.covers(&[1], &[]));
+        assert!(index.covers(&[1], &[open]));
+    }
+
+    #[test]
+    fn test_expression_index_on_lower() {
+        let mut lower = definition("by_lower_name", &["name"], &[]);
+        lower.functions = vec![Some(KeyFunction::LOWER)];
+        let rows = vec![
+            (0, vec![Value::Int(1), string("Alice")]),
+            (1, vec![Value::Int(2), string("ALICE")]),
+            (2, vec![Value::Int(3), string("bob")]),
+        ];
+        let index = Index::build(Index::new(lower, vec![1], vec![], None, &pager("index_lower"), None), rows).unwrap();
+        assert_eq!(index.lookup_prefix(&[string("alice")]), vec![0, 1]);
+        assert!(index.matches_key(0, 1, Some(KeyFunction::LOWER)));
+        assert!(!index.matches_key(0, 1, None));
+        assert!(!index.matches_key(1, 1, Some(KeyFunction::LOWER)));
+        assert!(!index.covers(&[1], &[]));
+    }
+
+    #[test]
+    fn test_range_scan_on_second_column() {
+        let index = orders_index();
+        let one = [Value::Int(1)];
+        assert_eq!(
+            index.scan_range(&one, Bound::Excluded(&Value::Int(25)), Bound::Unbounded),
+            vec![0, 4]
+        );
+        assert_eq!(
+            index.scan_range(&one, Bound::Unbounded, Bound::Excluded(&Value::Int(50))),
+            vec![2]
+        );
+        assert_eq!(
+            index.scan_range(&one, Bound::Included(&Value::Int(25)), Bound::Included(&Value::Int(50))),
+            vec![2, 0, 4]
+        );
+        assert_eq!(
+            index.scan_range(&[], Bound::Included(&Value::Int(0)), Bound::Included(&Value::Int(1))),
+            vec![2, 0, 4]
+        );
+    }
+
+    #[test]
+    fn test_hash_index_answers_whole_key_lookups() {
+        let mut hashed = definition("by_customer", &["customer", "status"], &[]);
+        hashed.method = IndexMethod::HASH;
+        let rows = (0..300).map(|n| (n as usize, vec![Value::Int(n), Value::Int(n % 10), string(if n % 2 == 0 { "open" } else { "closed" })]));
+        let mut index = Index::build(Index::new(hashed, vec![1, 2], vec![], None, &pager("index_hash"), None), rows).unwrap();
+        index.insert_row(300, &[Value::Int(300), Value::Int(4), string("open")]).unwrap();
+        let mut found = index.lookup_prefix(&[Value::Int(4), string("open")]);
+        found.sort();
+        assert_eq!(found.len(), 31);
+        assert_eq!(found.last(), Some(&300));
+        assert!(index.lookup_prefix(&[Value::Int(4), string("closed")]).is_empty());
+        assert_eq!(index.method(), IndexMethod::HASH);
+        assert!(!index.covers(&[1, 2], &[]));
+    }
+
+    #[test]
+    fn test_dump_tree_checks_invariants() {
+        let dump = orders_index().dump_tree().unwrap();
+        assert!(dump.starts_with("level 0\n  node 0 leaf keys ["));
+        assert!(dump.ends_with("invariants ok\n"));
+        let mut hashed = definition("by_amount", &["amount"], &[]);
+        hashed.method = IndexMethod::HASH;
+        let hashed = Index::new(hashed, vec![2], vec![], None, &pager("index_dump"), None);
+        assert_eq!(hashed.dump_tree(), Err("Index by_amount is not a B+tree index".to_string()));
+    }
+}
@@ -0,0 +1,160 @@
+use std::fmt::Debug;
+use uuid::Uuid;
+
+use crate::table::Value;
+
+//Keys stored in a B+tree. The ordering of a key must agree with the
+//byte ordering of its encoding so that encoded keys can be compared with memcmp,
+//`BPlusTree::check` reports keys whose encodings are out of order.
+//Strings compare bytewise, collations other than binary are not supported.
+pub trait IndexKey: Ord + Clone + Debug {
+    fn encode(&self) -> Vec<u8>;
+}
+
+impl IndexKey for Uuid {
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl IndexKey for i64 {
+    fn encode(&self) -> Vec<u8> {
+        let mut key = EncodedKey::new();
+        key.push_int(*self);
+        key.0
+    }
+}
+
+impl IndexKey for String {
+    fn encode(&self) -> Vec<u8> {
+        let mut key = EncodedKey::new();
+        key.push_string(self);
+        key.0
+    }
+}
+
+//A key made of already encoded parts, ordered bytewise.
+//INT, STRING and composite keys are all indexed through this type.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct EncodedKey(pub Vec<u8>);
+
+impl IndexKey for EncodedKey {
+    fn encode(&self) -> Vec<u8> {
+        self.0.clone()
+    }
+}
+
+impl EncodedKey {
+    pub fn new() -> Self {
+        EncodedKey(vec![])
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    //Big endian with the sign bit flipped, so negative numbers sort first.
+    pub fn push_int(&mut self, value: i64) {
+        self.0
+            .extend_from_slice(&((value as u64) ^ (1 << 63)).to_be_bytes());
+    }
+
+    //IEEE bits with the sign bit flipped for positives and every bit flipped
+    //for negatives, so the bytes order like the numbers.
+    pub fn push_float(&mut self, value: f64) {
+        let bits = value.to_bits();
+        let bits = if bits >> 63 == 1 { !bits } else { bits ^ (1 << 63) };
+        self.0.extend_from_slice(&bits.to_be_bytes());
+    }
+
+    //Zero bytes are escaped as 0x00 0xFF and the string is terminated by 0x00 0x00,
+    //so a string always sorts before any longer string it is a prefix of,
+    //even when more key parts follow it.
+    pub fn push_string(&mut self, value: &str) {
+        for byte in value.bytes() {
+            self.0.push(byte);
+            if byte == 0 {
+                self.0.push(0xFF);
+            }
+        }
+        self.0.extend_from_slice(&[0, 0]);
+    }
+
+    pub fn push_uuid(&mut self, value: &Uuid) {
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    pub fn push_value(&mut self, value: &Value) {
+        match value {
+            Value::Int(value) => self.push_int(*value),
+            Value::Float(value) => self.push_float(*value),
+            Value::String(value) => self.push_string(value),
+            Value::Uuid(value) => self.push_uuid(value),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int_key(values: &[i64]) -> EncodedKey {
+        let mut key = EncodedKey::new();
+        for value in values {
+            key.push_int(*value);
+        }
+        key
+    }
+
+    fn string_key(value: &str) -> EncodedKey {
+        let mut key = EncodedKey::new();
+        key.push_string(value);
+        key
+    }
+
+    #[test]
+    fn test_int_encoding_orders_negative_numbers() {
+        let values = [i64::MIN, -1000, -1, 0, 1, 255, 256, i64::MAX];
+        for pair in values.windows(2) {
+            assert!(pair[0].encode() < pair[1].encode(), "{:?}", pair);
+            assert!(int_key(&pair[..1]) < int_key(&pair[1..]));
+        }
+    }
+
+    #[test]
+    fn test_string_encoding_orders_prefixes_first() {
+        let mut short = EncodedKey::new();
+        short.push_string("ab");
+        short.push_int(i64::MAX);
+        let mut long = EncodedKey::new();
+        long.push_string("ab\0");
+        long.push_int(i64::MIN);
+        assert!(short < long);
+        assert!(string_key("a") < string_key("a\0"));
+        assert!(string_key("B") < string_key("a"));
+        assert!("a".to_string().encode() < "a\0".to_string().encode());
+        assert_eq!("ab".to_string().encode(), string_key("ab").encode());
+    }
+
+    #[test]
+    fn test_composite_keys_order_by_leading_column() {
+        assert!(int_key(&[-5, 100]) < int_key(&[1, -100]));
+        assert!(int_key(&[1, -100]) < int_key(&[1, 0]));
+        assert!(int_key(&[1]) < int_key(&[1, i64::MIN]));
+    }
+
+    #[test]
+    fn test_float_encoding_orders_numbers() {
+        let values = [f64::NEG_INFINITY, -1e10, -2.5, -0.0, 0.0, 1e-9, 2.5, 1e10, f64::INFINITY];
+        let encode = |value: f64| {
+            let mut key = EncodedKey::new();
+            key.push_float(value);
+            key
+        };
+        for pair in values.windows(2) {
+            assert!(encode(pair[0]) <= encode(pair[1]), "{:?}", pair);
+        }
+        assert!(encode(-2.5) < encode(-1.0));
+    }
+}
@@ -1,4 +1,7 @@
+use std::collections::VecDeque;
+
 #[derive(Debug, Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum KeyWords {
     CREATE,
     SELECT,
@@ -9,93 +12,439 @@ pub enum KeyWords {
     RIGHTPAREN,
     STRING,
     INT,
+    FLOAT,
     FROM,
     INTO,
     VALUES,
     TABLE,
+    INDEX,
+    ON,
+    PRIMARY,
+    KEY,
+    CLUSTERED,
+    INCLUDE,
+    WHERE,
+    EQUALS,
+    USING,
+    FULLTEXT,
+    MATCH,
+    NOTEQUALS,
+    LESS,
+    LESSEQUALS,
+    GREATER,
+    GREATEREQUALS,
+    PLUS,
+    MINUS,
+    SLASH,
+    PERCENT,
+    CONCAT,
+    DOT,
+    SEMICOLON,
+    AND,
+    OR,
+    NOT,
+    IS,
+    NULL,
+    IN,
+    BETWEEN,
+    LIKE,
+    SET,
+    DELETE,
+    DROP,
+    TRUNCATE,
+    IF,
+    EXISTS,
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Token {
     Keyword(KeyWords),
     Identifier(String),
+    String(String),
+    Integer(i64),
+    Float(f64),
+    //`?` or `$n`, numbered from 1. Each `?` takes the number after the previous one.
+    Placeholder(usize),
     EOL
 }
 
+//Where a token came from. offset and end are byte offsets into the input,
+//line and column are 1-based and count characters.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub offset: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+//Returned by `peek_nth` past the end of the input.
+static EOL: Token = Token::EOL;
+
+//Produces tokens on demand. Only the tokens the parser has peeked at are
+//buffered, and once the input is exhausted every call returns EOL.
 #[derive(Debug)]
 pub struct Lexer<'a> {
     input:&'a str,
-    tokens:Vec<Token>,
+    chars:Vec<char>,
+    //Index of the next character to lex, with its byte offset, line and column.
+    pos:usize,
+    offset:usize,
+    line:usize,
+    column:usize,
+    //Whether the last lexed token was a value, after which '-' is the minus operator.
+    after_value:bool,
+    //How many `?` placeholders have been lexed and whether any `$n` one has.
+    positional_placeholders:usize,
+    numbered_placeholders:bool,
+    lookahead:VecDeque<(Token, Span)>,
+    last_span:Span,
+    last_token:Token,
+    pub error:Option<(String, Span)>,
 }
 
 impl<'a> Lexer <'a>{
     pub fn new(input:&'a str) -> Self {
         Lexer {
             input,
-            tokens:Vec::new(),
+            chars:input.chars().collect(),
+            pos:0,
+            offset:0,
+            line:1,
+            column:1,
+            after_value:false,
+            positional_placeholders:0,
+            numbered_placeholders:false,
+            lookahead:VecDeque::new(),
+            last_span:Span::default(),
+            last_token:Token::EOL,
+            error:None,
         }
     }
 
     pub fn consume(&mut self) -> Token {
-        self.tokens.remove(0)
+        self.peek_nth(0);
+        let (token, span) = match self.lookahead.pop_front() {
+            Some(next) => next,
+            None => (Token::EOL, self.span_to(self.pos)),
+        };
+        self.last_span = span;
+        self.last_token = token.clone();
+        token
     }
 
-    // fn peek(&self) -> Option<&Token> {
-    //     self.tokens.get(0)
-    // }
+    pub fn peek(&mut self) -> &Token {
+        self.peek_nth(0)
+    }
 
-    pub fn tokenize(&mut self) {
-        let chars:Vec<char> = self.input.chars().collect();
-        let mut pos = 0;
-        while pos < chars.len() {
-            let current_char = chars[pos];
-            if current_char.is_whitespace() {
-                pos += 1;
-                continue;
+    //The token `n` places ahead of the next one, lexing as far as needed.
+    pub fn peek_nth(&mut self, n: usize) -> &Token {
+        while self.lookahead.len() <= n {
+            match self.lex() {
+                Some(next) => self.lookahead.push_back(next),
+                None => break,
             }
-            if current_char.is_alphanumeric() {
-                let start = pos;
-                while pos < chars.len() && chars[pos].is_alphanumeric() {
-                    pos += 1;
+        }
+        self.lookahead.get(n).map_or(&EOL, |(token, _)| token)
+    }
+
+    //Token most recently returned by `consume`.
+    pub fn last_token(&self) -> &Token {
+        &self.last_token
+    }
+
+    //Span of the token most recently returned by `consume`.
+    pub fn last_span(&self) -> Span {
+        self.last_span
+    }
+
+    //The message followed by the line of input holding `span`, a caret under
+    //the span and, when given, the tokens that would have been accepted there.
+    pub fn render_error(&self, message: &str, span: Span, expected: &[&str]) -> String {
+        let line_start = self.input[..span.offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = self.input[span.offset..].find('\n').map(|i| span.offset + i).unwrap_or(self.input.len());
+        let width = self.input[span.offset..span.end.min(line_end)].chars().count().max(1);
+        let mut out = format!(
+            "{} at line {}, column {}\n{}\n{}{}",
+            message,
+            span.line,
+            span.column,
+            &self.input[line_start..line_end],
+            " ".repeat(span.column - 1),
+            "^".repeat(width)
+        );
+        if !expected.is_empty() {
+            out.push_str(&format!("\nexpected {}", expected.join(", ")));
+        }
+        out
+    }
+
+    //Span from the current position up to the character at `end`.
+    fn span_to(&self, end: usize) -> Span {
+        Span {
+            offset: self.offset,
+            end: self.offset + self.chars[self.pos..end].iter().map(|c| c.len_utf8()).sum::<usize>(),
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn advance(&mut self, end: usize) {
+        for c in &self.chars[self.pos..end] {
+            self.offset += c.len_utf8();
+            if *c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+        self.pos = end;
+    }
+
+    //The next token and its span, None at the end of input or after an error.
+    fn lex(&mut self) -> Option<(Token, Span)> {
+        if self.error.is_some() {
+            return None;
+        }
+        let chars = &self.chars;
+        let mut pos = self.pos;
+        //`--` comments run to the end of the line, `/* */` comments may span lines.
+        loop {
+            match (chars.get(pos), chars.get(pos + 1)) {
+                (Some(c), _) if c.is_whitespace() => pos += 1,
+                (Some('-'), Some('-')) => {
+                    while pos < chars.len() && chars[pos] != '\n' {
+                        pos += 1;
+                    }
                 }
-                let word:String = chars[start..pos].iter().collect();
-                let upper_word = word.to_uppercase();
-                let token = match upper_word.as_str() {
-                    "CREATE" => Token::Keyword(KeyWords::CREATE),
-                    "SELECT" => Token::Keyword(KeyWords::SELECT),
-                    "INSERT" => Token::Keyword(KeyWords::INSERT),
-                    "UPDATE" => Token::Keyword(KeyWords::UPDATE),
-                    "STRING" => Token::Keyword(KeyWords::STRING),
-                    "INT" => Token::Keyword(KeyWords::INT),
-                    "FROM" => Token::Keyword(KeyWords::FROM),
-                    "INTO" => Token::Keyword(KeyWords::INTO),
-                    "VALUES"=>Token::Keyword(KeyWords::VALUES),
-                    "TABLE"=>Token::Keyword(KeyWords::TABLE),
-                    _ => Token::Identifier(word),
+                (Some('/'), Some('*')) => match (pos + 2..chars.len().saturating_sub(1)).find(|i| chars[*i] == '*' && chars[*i + 1] == '/') {
+                    Some(end) => pos = end + 2,
+                    None => {
+                        self.advance(pos);
+                        return self.fail("Unterminated comment".to_string(), pos + 2);
+                    }
+                },
+                _ => break,
+            }
+        }
+        self.advance(pos);
+        if pos == self.chars.len() {
+            return None;
+        }
+        match self.scan(pos) {
+            Ok((token, end)) => {
+                let token = match token {
+                    Token::Placeholder(0) => {
+                        self.positional_placeholders += 1;
+                        Token::Placeholder(self.positional_placeholders)
+                    }
+                    Token::Placeholder(number) => {
+                        self.numbered_placeholders = true;
+                        Token::Placeholder(number)
+                    }
+                    token => token,
                 };
-                self.tokens.push(token);
-                continue;
+                if self.numbered_placeholders && self.positional_placeholders > 0 {
+                    return self.fail("Cannot mix ? and $n placeholders".to_string(), end);
+                }
+                let span = self.span_to(end);
+                self.advance(end);
+                self.after_value = matches!(
+                    token,
+                    Token::Identifier(_)
+                        | Token::Integer(_)
+                        | Token::Float(_)
+                        | Token::String(_)
+                        | Token::Placeholder(_)
+                        | Token::Keyword(KeyWords::NULL | KeyWords::RIGHTPAREN)
+                );
+                Some((token, span))
             }
-            if current_char == ',' {
-                self.tokens.push(Token::Keyword(KeyWords::COMMA));
-                pos += 1;
-                continue;
-            } else if current_char == '(' {
-                self.tokens.push(Token::Keyword(KeyWords::LEFTPAREN));
-                pos += 1;
-                continue;
-            } else if current_char == ')' {
-                self.tokens.push(Token::Keyword(KeyWords::RIGHTPAREN));
+            Err((message, end)) => self.fail(message, end),
+        }
+    }
+
+    //Records a lexing error covering the input up to `end`, lexing stops there.
+    fn fail(&mut self, message: String, end: usize) -> Option<(Token, Span)> {
+        self.error = Some((message, self.span_to(end)));
+        self.advance(self.chars.len());
+        None
+    }
+
+    //The token starting at `pos` and the position after it, or an error and the end of the bad input.
+    fn scan(&self, pos: usize) -> Result<(Token, usize), (String, usize)> {
+        let chars = &self.chars;
+        let current_char = chars[pos];
+        //A '-' only starts a negative literal where a value is expected,
+        //after a value it is left for the minus operator.
+        let starts_negative = current_char == '-'
+            && chars.get(pos + 1).is_some_and(|c| c.is_ascii_digit())
+            && !self.after_value;
+        if current_char == '\'' {
+            return match Self::quoted(chars, pos) {
+                Some((value, end)) => Ok((Token::String(value), end)),
+                None => Err(("Unterminated string literal".to_string(), chars.len())),
+            };
+        }
+        if current_char.is_ascii_digit() || starts_negative {
+            return Ok(Self::number(chars, pos));
+        }
+        if current_char == '"' || current_char == '`' {
+            return match Self::quoted(chars, pos) {
+                Some((name, end)) if !name.is_empty() => Ok((Token::Identifier(name), end)),
+                Some((_, end)) => Err(("Empty quoted identifier".to_string(), end)),
+                None => Err(("Unterminated quoted identifier".to_string(), chars.len())),
+            };
+        }
+        if current_char.is_alphanumeric() || current_char == '_' {
+            let mut end = pos;
+            while end < chars.len() && Self::is_identifier_char(chars[end]) {
+                end += 1;
+            }
+            let word:String = chars[pos..end].iter().collect();
+            let upper_word = word.to_uppercase();
+            let token = match upper_word.as_str() {
+                "CREATE" => Token::Keyword(KeyWords::CREATE),
+                "SELECT" => Token::Keyword(KeyWords::SELECT),
+                "INSERT" => Token::Keyword(KeyWords::INSERT),
+                "UPDATE" => Token::Keyword(KeyWords::UPDATE),
+                "STRING" => Token::Keyword(KeyWords::STRING),
+                "INT" => Token::Keyword(KeyWords::INT),
+                "FLOAT" => Token::Keyword(KeyWords::FLOAT),
+                "FROM" => Token::Keyword(KeyWords::FROM),
+                "INTO" => Token::Keyword(KeyWords::INTO),
+                "VALUES"=>Token::Keyword(KeyWords::VALUES),
+                "TABLE"=>Token::Keyword(KeyWords::TABLE),
+                "INDEX"=>Token::Keyword(KeyWords::INDEX),
+                "ON"=>Token::Keyword(KeyWords::ON),
+                "PRIMARY"=>Token::Keyword(KeyWords::PRIMARY),
+                "KEY"=>Token::Keyword(KeyWords::KEY),
+                "CLUSTERED"=>Token::Keyword(KeyWords::CLUSTERED),
+                "INCLUDE"=>Token::Keyword(KeyWords::INCLUDE),
+                "WHERE"=>Token::Keyword(KeyWords::WHERE),
+                "USING"=>Token::Keyword(KeyWords::USING),
+                "FULLTEXT"=>Token::Keyword(KeyWords::FULLTEXT),
+                "MATCH"=>Token::Keyword(KeyWords::MATCH),
+                "AND"=>Token::Keyword(KeyWords::AND),
+                "OR"=>Token::Keyword(KeyWords::OR),
+                "NOT"=>Token::Keyword(KeyWords::NOT),
+                "IS"=>Token::Keyword(KeyWords::IS),
+                "NULL"=>Token::Keyword(KeyWords::NULL),
+                "IN"=>Token::Keyword(KeyWords::IN),
+                "BETWEEN"=>Token::Keyword(KeyWords::BETWEEN),
+                "LIKE"=>Token::Keyword(KeyWords::LIKE),
+                "SET"=>Token::Keyword(KeyWords::SET),
+                "DELETE"=>Token::Keyword(KeyWords::DELETE),
+                "DROP"=>Token::Keyword(KeyWords::DROP),
+                "TRUNCATE"=>Token::Keyword(KeyWords::TRUNCATE),
+                "IF"=>Token::Keyword(KeyWords::IF),
+                "EXISTS"=>Token::Keyword(KeyWords::EXISTS),
+                _ => Token::Identifier(word),
+            };
+            return Ok((token, end));
+        }
+        //`?` is numbered by lex, `$n` carries its own number.
+        if current_char == '?' {
+            return Ok((Token::Placeholder(0), pos + 1));
+        }
+        if current_char == '$' && chars.get(pos + 1).is_some_and(|c| c.is_ascii_digit()) {
+            let mut end = pos + 1;
+            while end < chars.len() && chars[end].is_ascii_digit() {
+                end += 1;
+            }
+            let digits: String = chars[pos + 1..end].iter().collect();
+            return match digits.parse::<usize>() {
+                Ok(number) if number > 0 => Ok((Token::Placeholder(number), end)),
+                _ => Err((format!("Invalid placeholder ${}", digits), end)),
+            };
+        }
+        let next_char = chars.get(pos + 1).copied().unwrap_or('\0');
+        let (token, width) = match (current_char, next_char) {
+            (',', _) => (Token::Keyword(KeyWords::COMMA), 1),
+            ('(', _) => (Token::Keyword(KeyWords::LEFTPAREN), 1),
+            (')', _) => (Token::Keyword(KeyWords::RIGHTPAREN), 1),
+            ('*', _) => (Token::Identifier("*".to_string()), 1),
+            ('<', '>') | ('!', '=') => (Token::Keyword(KeyWords::NOTEQUALS), 2),
+            ('<', '=') => (Token::Keyword(KeyWords::LESSEQUALS), 2),
+            ('>', '=') => (Token::Keyword(KeyWords::GREATEREQUALS), 2),
+            ('|', '|') => (Token::Keyword(KeyWords::CONCAT), 2),
+            ('=', _) => (Token::Keyword(KeyWords::EQUALS), 1),
+            ('<', _) => (Token::Keyword(KeyWords::LESS), 1),
+            ('>', _) => (Token::Keyword(KeyWords::GREATER), 1),
+            ('+', _) => (Token::Keyword(KeyWords::PLUS), 1),
+            ('-', _) => (Token::Keyword(KeyWords::MINUS), 1),
+            ('/', _) => (Token::Keyword(KeyWords::SLASH), 1),
+            ('%', _) => (Token::Keyword(KeyWords::PERCENT), 1),
+            ('.', _) => (Token::Keyword(KeyWords::DOT), 1),
+            (';', _) => (Token::Keyword(KeyWords::SEMICOLON), 1),
+            _ => return Err((format!("Unexpected character '{}'", current_char), pos + 1)),
+        };
+        Ok((token, pos + width))
+    }
+
+    fn is_identifier_char(c: char) -> bool {
+        c.is_alphanumeric() || c == '_' || c == '$'
+    }
+
+    //Text between the quote character at `start` and its closing match and the
+    //position after it. A doubled quote stands for one, as in 'it''s'.
+    //Single quotes delimit string literals, " and ` delimit identifiers.
+    fn quoted(chars: &[char], start: usize) -> Option<(String, usize)> {
+        let quote = chars[start];
+        let mut value = String::new();
+        let mut pos = start + 1;
+        loop {
+            match chars.get(pos)? {
+                c if *c == quote && chars.get(pos + 1) == Some(&quote) => {
+                    value.push(quote);
+                    pos += 2;
+                }
+                c if *c == quote => return Some((value, pos + 1)),
+                c => {
+                    value.push(*c);
+                    pos += 1;
+                }
+            }
+        }
+    }
+
+    //Integer or decimal literal starting at `start`, with an optional exponent.
+    //Digits running into letters, as in 45a, stay an identifier.
+    fn number(chars: &[char], start: usize) -> (Token, usize) {
+        let digits = |mut pos: usize| {
+            while pos < chars.len() && chars[pos].is_ascii_digit() {
                 pos += 1;
-                continue;
-            } else if current_char == '*' {
-                self.tokens.push(Token::Identifier("*".to_string()));
+            }
+            pos
+        };
+        let mut pos = digits(start + 1);
+        let mut is_float = false;
+        if chars.get(pos) == Some(&'.') && chars.get(pos + 1).is_some_and(|c| c.is_ascii_digit()) {
+            pos = digits(pos + 1);
+            is_float = true;
+        }
+        if matches!(chars.get(pos), Some('e' | 'E')) {
+            let sign = usize::from(matches!(chars.get(pos + 1), Some('+' | '-')));
+            if chars.get(pos + 1 + sign).is_some_and(|c| c.is_ascii_digit()) {
+                pos = digits(pos + 1 + sign);
+                is_float = true;
+            }
+        }
+        if chars.get(pos).is_some_and(|c| c.is_alphanumeric()) {
+            while pos < chars.len() && chars[pos].is_alphanumeric() {
                 pos += 1;
-                continue;
             }
-            pos += 1;
+            return (Token::Identifier(chars[start..pos].iter().collect()), pos);
         }
-        self.tokens.push(Token::EOL);
+        let text: String = chars[start..pos].iter().collect();
+        //Integers too large for an i64 keep their magnitude as a float.
+        let token = match text.parse::<i64>() {
+            Ok(value) if !is_float => Token::Integer(value),
+            _ => Token::Float(text.parse().unwrap()),
+        };
+        (token, pos)
     }
 
 }
@@ -111,7 +460,6 @@ mod tests {
     #[test]
     fn test_keywords_case_insensitive() {
         let mut lexer = Lexer::new("create SELECT Insert UpDaTe");
-        lexer.tokenize();
 
         match next_token(&mut lexer) {
             Token::Keyword(KeyWords::CREATE) => {}
@@ -137,8 +485,7 @@ mod tests {
 
     #[test]
     fn test_identifiers_and_symbols() {
-        let mut lexer = Lexer::new("CREATE table (id INT, name STRING)");
-        lexer.tokenize();
+        let mut lexer = Lexer::new("CREATE \"table\" (id INT, name STRING)");
 
         match next_token(&mut lexer) {
             Token::Keyword(KeyWords::CREATE) => {}
@@ -185,7 +532,6 @@ mod tests {
     #[test]
     fn test_symbols_only() {
         let mut lexer = Lexer::new(",()");
-        lexer.tokenize();
 
         match next_token(&mut lexer) {
             Token::Keyword(KeyWords::COMMA) => {}
@@ -208,7 +554,6 @@ mod tests {
     #[test]
     fn test_alphanumeric_identifier() {
         let mut lexer = Lexer::new("user1 col2a");
-        lexer.tokenize();
 
         match next_token(&mut lexer) {
             Token::Identifier(s) if s == "user1" => {}
@@ -225,26 +570,248 @@ mod tests {
     }
 
     #[test]
-    fn test_numbers_ignored_then_identifier() {
-        // Leading numbers are ignored by current lexer; trailing alpha becomes an identifier.
-        let mut lexer = Lexer::new("123 45a");
-        lexer.tokenize();
+    fn test_numeric_literals() {
+        let mut lexer = Lexer::new("123 45a, -7, 3.25, -1.5e3, 2E-2, 1e 99999999999999999999 (-4) 2 -1");
 
-        match next_token(&mut lexer) {
-            // "123" is skipped entirely, then "45a" becomes "a"
-            Token::Identifier(s) if s == "a" => {}
-            other => panic!("Expected Identifier(a), got {:?}", other),
+        let mut tokens = vec![];
+        loop {
+            match next_token(&mut lexer) {
+                Token::EOL => break,
+                token => tokens.push(format!("{:?}", token)),
+            }
         }
-        match next_token(&mut lexer) {
-            Token::EOL => {}
-            other => panic!("Expected EOL, got {:?}", other),
+        assert_eq!(tokens, vec![
+            "Integer(123)", "Identifier(\"45a\")", "Keyword(COMMA)", "Integer(-7)", "Keyword(COMMA)",
+            "Float(3.25)", "Keyword(COMMA)", "Float(-1500.0)", "Keyword(COMMA)", "Float(0.02)", "Keyword(COMMA)",
+            "Identifier(\"1e\")", "Float(1e20)", "Keyword(LEFTPAREN)", "Integer(-4)", "Keyword(RIGHTPAREN)",
+            // '-' after a value is the minus operator.
+            "Integer(2)", "Keyword(MINUS)", "Integer(1)",
+        ]);
+    }
+
+    #[test]
+    fn test_string_literals() {
+        let mut lexer = Lexer::new("('Alice', 'it''s, a test!', '', 'SELECT') name");
+
+        let mut tokens = vec![];
+        loop {
+            match next_token(&mut lexer) {
+                Token::EOL => break,
+                token => tokens.push(format!("{:?}", token)),
+            }
+        }
+        assert_eq!(tokens, vec![
+            "Keyword(LEFTPAREN)", "String(\"Alice\")", "Keyword(COMMA)", "String(\"it's, a test!\")",
+            "Keyword(COMMA)", "String(\"\")", "Keyword(COMMA)", "String(\"SELECT\")", "Keyword(RIGHTPAREN)",
+            "Identifier(\"name\")",
+        ]);
+        assert!(lexer.error.is_none());
+
+        let mut lexer = Lexer::new("VALUES ('oops)");
+        while !matches!(next_token(&mut lexer), Token::EOL) {}
+        assert_eq!(lexer.error.unwrap().0, "Unterminated string literal");
+    }
+
+    #[test]
+    fn test_underscores_and_quoted_identifiers() {
+        let mut lexer = Lexer::new("empty_table _tmp price$ \"select\" `Mixed Case` \"say \"\"hi\"\"\" FROM");
+
+        let mut tokens = vec![];
+        loop {
+            match next_token(&mut lexer) {
+                Token::EOL => break,
+                token => tokens.push(format!("{:?}", token)),
+            }
+        }
+        assert_eq!(tokens, vec![
+            "Identifier(\"empty_table\")", "Identifier(\"_tmp\")", "Identifier(\"price$\")",
+            "Identifier(\"select\")", "Identifier(\"Mixed Case\")", "Identifier(\"say \\\"hi\\\"\")",
+            "Keyword(FROM)",
+        ]);
+
+        let mut lexer = Lexer::new("SELECT `name FROM t");
+        while !matches!(next_token(&mut lexer), Token::EOL) {}
+        assert_eq!(lexer.error.unwrap().0, "Unterminated quoted identifier");
+    }
+
+    #[test]
+    fn test_operators_and_logical_keywords() {
+        let mut lexer = Lexer::new("a<>b != c<=d>=e<f>g = h+i-j/k%l || m.n; x and Or not IS null In between LIKE");
+
+        let mut tokens = vec![];
+        loop {
+            match next_token(&mut lexer) {
+                Token::EOL => break,
+                Token::Identifier(_) => {}
+                token => tokens.push(format!("{:?}", token)),
+            }
+        }
+        assert_eq!(tokens, vec![
+            "Keyword(NOTEQUALS)", "Keyword(NOTEQUALS)", "Keyword(LESSEQUALS)", "Keyword(GREATEREQUALS)",
+            "Keyword(LESS)", "Keyword(GREATER)", "Keyword(EQUALS)", "Keyword(PLUS)", "Keyword(MINUS)",
+            "Keyword(SLASH)", "Keyword(PERCENT)", "Keyword(CONCAT)", "Keyword(DOT)", "Keyword(SEMICOLON)",
+            "Keyword(AND)", "Keyword(OR)", "Keyword(NOT)", "Keyword(IS)", "Keyword(NULL)", "Keyword(IN)",
+            "Keyword(BETWEEN)", "Keyword(LIKE)",
+        ]);
+        assert!(lexer.error.is_none());
+
+        let mut lexer = Lexer::new("SELECT a FROM t WHERE a ! b");
+        while !matches!(next_token(&mut lexer), Token::EOL) {}
+        assert_eq!(lexer.error.unwrap().0, "Unexpected character '!'");
+    }
+
+    #[test]
+    fn test_comments_are_skipped() {
+        let mut lexer = Lexer::new("-- schema\nSELECT a, /* the\n b column */ b-- trailing\nFROM t; 4 - -2 /**/ / 2");
+
+        let mut tokens = vec![];
+        loop {
+            match next_token(&mut lexer) {
+                Token::EOL => break,
+                token => tokens.push(format!("{:?}", token)),
+            }
+        }
+        assert_eq!(tokens, vec![
+            "Keyword(SELECT)", "Identifier(\"a\")", "Keyword(COMMA)", "Identifier(\"b\")", "Keyword(FROM)",
+            "Identifier(\"t\")", "Keyword(SEMICOLON)", "Integer(4)", "Keyword(MINUS)", "Integer(-2)",
+            "Keyword(SLASH)", "Integer(2)",
+        ]);
+        assert!(lexer.error.is_none());
+
+        let mut lexer = Lexer::new("SELECT a FROM t; SELECT b /* open");
+        let mut tokens = 0;
+        while !matches!(next_token(&mut lexer), Token::EOL) {
+            tokens += 1;
+        }
+        assert_eq!(tokens, 7);
+        assert_eq!(lexer.error.unwrap().0, "Unterminated comment");
+    }
+
+    #[test]
+    fn test_peek_looks_ahead_and_eol_repeats() {
+        let mut lexer = Lexer::new("SELECT a, b");
+        assert!(matches!(lexer.peek_nth(2), Token::Keyword(KeyWords::COMMA)));
+        assert!(matches!(lexer.peek_nth(7), Token::EOL));
+        assert!(matches!(lexer.peek(), Token::Keyword(KeyWords::SELECT)));
+        assert!(matches!(next_token(&mut lexer), Token::Keyword(KeyWords::SELECT)));
+        assert!(matches!(lexer.peek(), Token::Identifier(name) if name == "a"));
+        for _ in 0..3 {
+            next_token(&mut lexer);
+        }
+        for _ in 0..3 {
+            assert!(matches!(lexer.peek(), Token::EOL));
+            assert!(matches!(next_token(&mut lexer), Token::EOL));
+        }
+        assert_eq!(lexer.last_span().offset, 11);
+
+        //Stops at a bad character without lexing past what was asked for.
+        let mut lexer = Lexer::new("a b @");
+        assert!(matches!(lexer.peek_nth(1), Token::Identifier(_)));
+        assert!(lexer.error.is_none());
+        assert!(matches!(lexer.peek_nth(2), Token::EOL));
+        assert_eq!(lexer.error.clone().unwrap().0, "Unexpected character '@'");
+    }
+
+    #[test]
+    fn test_placeholders() {
+        let mut lexer = Lexer::new("VALUES (?, ?,?) price$1");
+        let mut tokens = vec![];
+        loop {
+            match next_token(&mut lexer) {
+                Token::EOL => break,
+                token => tokens.push(format!("{:?}", token)),
+            }
+        }
+        assert_eq!(tokens, vec![
+            "Keyword(VALUES)", "Keyword(LEFTPAREN)", "Placeholder(1)", "Keyword(COMMA)", "Placeholder(2)",
+            "Keyword(COMMA)", "Placeholder(3)", "Keyword(RIGHTPAREN)", "Identifier(\"price$1\")",
+        ]);
+
+        let mut lexer = Lexer::new("($2, $1, $2)");
+        let mut numbers = vec![];
+        loop {
+            match next_token(&mut lexer) {
+                Token::EOL => break,
+                Token::Placeholder(number) => numbers.push(number),
+                _ => {}
+            }
         }
+        assert_eq!(numbers, vec![2, 1, 2]);
+        assert!(lexer.error.is_none());
+
+        for (sql, error) in [("($1, ?)", "Cannot mix ? and $n placeholders"), ("($0)", "Invalid placeholder $0")] {
+            let mut lexer = Lexer::new(sql);
+            while !matches!(next_token(&mut lexer), Token::EOL) {}
+            assert_eq!(lexer.error.unwrap().0, error);
+        }
+    }
+
+    #[test]
+    fn test_minus_after_placeholders_strings_and_null() {
+        let mut lexer = Lexer::new("? - 1, ? -1, 'a' -1, NULL -1");
+        let mut tokens = vec![];
+        loop {
+            match next_token(&mut lexer) {
+                Token::EOL => break,
+                token => tokens.push(format!("{:?}", token)),
+            }
+        }
+        assert!(lexer.error.is_none());
+        assert_eq!(tokens, vec![
+            "Placeholder(1)", "Keyword(MINUS)", "Integer(1)", "Keyword(COMMA)",
+            "Placeholder(2)", "Keyword(MINUS)", "Integer(1)", "Keyword(COMMA)",
+            "String(\"a\")", "Keyword(MINUS)", "Integer(1)", "Keyword(COMMA)",
+            "Keyword(NULL)", "Keyword(MINUS)", "Integer(1)",
+        ]);
+
+        let mut lexer = Lexer::new("$1 -1");
+        let mut tokens = vec![];
+        loop {
+            match next_token(&mut lexer) {
+                Token::EOL => break,
+                token => tokens.push(format!("{:?}", token)),
+            }
+        }
+        assert_eq!(tokens, vec!["Placeholder(1)", "Keyword(MINUS)", "Integer(1)"]);
+    }
+
+    #[test]
+    fn test_tokens_record_spans() {
+        let mut lexer = Lexer::new("SELECT näme,\n  'x y' FROM");
+
+        let mut spans = vec![];
+        while !matches!(next_token(&mut lexer), Token::EOL) {
+            let span = lexer.last_span();
+            spans.push((span.offset, span.end, span.line, span.column));
+        }
+        spans.push({
+            let span = lexer.last_span();
+            (span.offset, span.end, span.line, span.column)
+        });
+        assert_eq!(spans, vec![(0, 6, 1, 1), (7, 12, 1, 8), (12, 13, 1, 12), (16, 21, 2, 3), (22, 26, 2, 9), (26, 26, 2, 13)]);
+    }
+
+    #[test]
+    fn test_render_error_points_at_span() {
+        let mut lexer = Lexer::new("SELECT a\nFROM 42");
+        while !matches!(next_token(&mut lexer), Token::Integer(_)) {}
+        assert_eq!(
+            lexer.render_error("Expected table name", lexer.last_span(), &["identifier"]),
+            "Expected table name at line 2, column 6\nFROM 42\n     ^^\nexpected identifier"
+        );
+
+        let mut lexer = Lexer::new("SELECT 'open");
+        while !matches!(next_token(&mut lexer), Token::EOL) {}
+        let (message, span) = lexer.error.clone().unwrap();
+        assert_eq!(
+            lexer.render_error(&message, span, &[]),
+            "Unterminated string literal at line 1, column 8\nSELECT 'open\n       ^^^^^"
+        );
     }
 
     #[test]
     fn test_select_star_from_identifier() {
         let mut lexer = Lexer::new("SELECT * from test");
-        lexer.tokenize();
 
         match next_token(&mut lexer) {
             Token::Keyword(KeyWords::SELECT) => {}
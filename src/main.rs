@@ -3,17 +3,35 @@ use std::{io::{self, Write}};
 
 mod table;
 
+mod bplustree;
+
+mod key;
+
+mod index;
+
+mod hash_index;
+
+mod fulltext;
+
+mod zonemap;
+
 mod lexer;
 
 mod parser;
 
+mod ast;
+
+mod eval;
+
+mod executor;
+
 mod processor;
 
-use processor::Processor;
+mod pager;
 
-use lexer::Lexer;
+use processor::Processor;
 
-use parser::Parser;
+use executor::{Executor, PreparedStatement};
 
 use crate::table::DataBase;
 
@@ -22,17 +40,52 @@ use crate::table::DataBase;
 //parser implementation for Create table and update table.
 
 fn parse_command(processor: &mut Processor, input: &str) -> Result<(), io::Error> {
-    let lexer = Lexer::new(input);
-    let mut parser = Parser::new(lexer,processor);
-    match parser.parse(){
-        Err(error)=>println!("{}",error),
-        _=>{}
+    let results = Executor::new(processor).run_script(input);
+    let statements = results.len();
+    for (number, result) in results.into_iter().enumerate() {
+        match result {
+            Err(error) if statements > 1 => println!("Statement {}: {}", number + 1, error),
+            Err(error) => println!("{}", error),
+            _ => {}
+        }
     }
     Ok(())
 }
+//The REPL's prepared statement commands:
+//.prepare <sql>, .bind <n> <value>, .clear and .execute.
+fn prepared_command(processor: &mut Processor, prepared: &mut Option<PreparedStatement>, command: &str) -> Result<(), String> {
+    let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+    if name == ".prepare" {
+        let statement = PreparedStatement::prepare(argument.trim())?;
+        println!("Prepared a statement with {} parameters", statement.parameter_count());
+        *prepared = Some(statement);
+        return Ok(());
+    }
+    let statement = prepared.as_mut().ok_or("No prepared statement, use .prepare <sql> first")?;
+    match name {
+        ".bind" => {
+            let (index, value) = argument.trim().split_once(' ').ok_or("Usage: .bind <n> <value>")?;
+            let index = index.parse().map_err(|_| format!("Invalid parameter index {}", index))?;
+            statement.bind_text(index, value.trim())
+        }
+        ".clear" => {
+            statement.clear_bindings();
+            Ok(())
+        }
+        ".execute" => statement.execute(processor),
+        _ => Err(format!("Unknown command {}", name)),
+    }
+}
+
+const BTREE_USAGE: &str = "Usage: .btree <index>
+Prints the index's B+tree level by level and checks its invariants.
+B+tree indexes are kept in memory and have no pages, so nodes are numbered
+breadth first from the root instead of by page number.";
+
 fn main() -> Result<(), io::Error> {
-    let data_base = DataBase::new("test.db".to_string());
+    let data_base = DataBase::new("test.db".to_string()).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
     let mut processor = Processor::new(data_base);
+    let mut prepared = None;
     let mut input = String::new();
     loop {
         print!("test_db>");
@@ -44,31 +97,100 @@ fn main() -> Result<(), io::Error> {
         }
         match input.trim() {
             ".exit" => break,
+            ".btree" => println!("{}", BTREE_USAGE),
+            command if command.starts_with(".btree") => {
+                match processor.dump_index(command[".btree".len()..].trim()) {
+                    Ok(dump) => print!("{}", dump),
+                    Err(error) => println!("{}", error),
+                }
+            }
+            command if command.starts_with(".read") => {
+                match std::fs::read_to_string(command[".read".len()..].trim()) {
+                    Ok(script) => parse_command(&mut processor, &script)?,
+                    Err(error) => println!("{}", error),
+                }
+            }
+            command if [".prepare", ".bind", ".clear", ".execute"].contains(&command.split(' ').next().unwrap_or("")) => {
+                if let Err(error) = prepared_command(&mut processor, &mut prepared, command) {
+                    println!("{}", error);
+                }
+            }
             command => parse_command(&mut processor, command)?,
         }
-        processor.database.flush();
+        if let Err(error) = processor.database.flush() {
+            println!("{}", error);
+        }
     }
 
-    return Ok(());
+    Ok(())
 }
 
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashSet;
+    use std::ops::Bound;
+    use crate::table::{KeyRange, Value};
+    use crate::index::{IndexMethod, KeyFunction};
+    use crate::fulltext::TextMatch;
+    use crate::ast::Statement;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
 
-    fn setup_processor() -> Processor {
-        let data_base = DataBase::new("test.db".to_string());
-        Processor::new(data_base)
+    //A database file of the test's own, named after the test and the process so tests
+    //running in parallel, or two test runs at once, never open the same file.
+    fn temp_db_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("mini_db_{}_{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path.to_string_lossy().to_string()
+    }
+
+    fn setup_fresh_processor(name: &str) -> (Processor, String) {
+        let path = temp_db_path(name);
+        (Processor::new(DataBase::new(path.clone()).unwrap()), path)
+    }
+
+    //Writes everything to disk and opens the file again, as a restart would.
+    fn reopen(processor: &Processor, path: &str) -> Processor {
+        processor.database.flush().unwrap();
+        Processor::new(DataBase::new(path.to_string()).unwrap())
+    }
+
+    fn run(processor: &mut Processor, sql: &str) -> Result<(), String> {
+        Executor::new(processor).run(sql)
+    }
+
+    fn run_all(processor: &mut Processor, statements: impl IntoIterator<Item = String>) {
+        for sql in statements {
+            run(processor, &sql).unwrap();
+        }
+    }
+
+    //Header and rows of a SELECT, evaluated the way the REPL would before printing them.
+    fn select(processor: &mut Processor, sql: &str) -> Result<(Vec<String>, Vec<Vec<Value>>), String> {
+        let Ok(Statement::Select { table, columns, filter }) = Parser::new(Lexer::new(sql)).parse() else {
+            panic!("Expected SELECT");
+        };
+        Executor::new(processor).select_expressions(&table, &columns, filter.as_ref(), &[])
+    }
+
+    //Every row of the table in storage order, without the id column.
+    fn stored_rows(processor: &mut Processor, table: &str) -> Vec<Vec<Value>> {
+        let (_, rows) = processor.scan_table(table).unwrap();
+        rows.into_iter().map(|row| row[1..].to_vec()).collect()
+    }
+
+    fn first_column(rows: &[Vec<Value>]) -> Vec<Value> {
+        rows.iter().map(|row| row[0].clone()).collect()
     }
 
     #[test]
     fn test_insert_and_read_from_multiple_tables() {
-        let mut processor = setup_processor();
+        let (mut processor, _) = setup_fresh_processor("multiple_tables");
 
         // Create first table
         parse_command(&mut processor, "CREATE TABLE users (id INT, name TEXT)").unwrap();
-        
+
         // Create second table
         parse_command(&mut processor, "CREATE TABLE orders (order_id INT, user_id INT, amount INT)").unwrap();
 
@@ -90,7 +212,7 @@ mod tests {
 
     #[test]
     fn test_multiple_tables_isolation() {
-        let mut processor = setup_processor();
+        let (mut processor, _) = setup_fresh_processor("isolation");
 
         // Create tables
         parse_command(&mut processor, "CREATE TABLE products (id INT, name TEXT)").unwrap();
@@ -110,7 +232,7 @@ mod tests {
 
     #[test]
     fn test_insert_into_nonexistent_table() {
-        let mut processor = setup_processor();
+        let (mut processor, _) = setup_fresh_processor("nonexistent_table");
 
         // Try to insert into a table that doesn't exist
         parse_command(&mut processor, "INSERT INTO nonexistent VALUES (1, 'test')").unwrap();
@@ -118,7 +240,7 @@ mod tests {
 
     #[test]
     fn test_read_from_empty_table() {
-        let mut processor = setup_processor();
+        let (mut processor, _) = setup_fresh_processor("empty_table");
 
         // Create table but don't insert anything
         parse_command(&mut processor, "CREATE TABLE empty_table (id INT, value TEXT)").unwrap();
@@ -129,7 +251,7 @@ mod tests {
 
     #[test]
     fn test_multiple_inserts_same_table() {
-        let mut processor = setup_processor();
+        let (mut processor, _) = setup_fresh_processor("multiple_inserts");
 
         parse_command(&mut processor, "CREATE TABLE logs (id INT, message TEXT)").unwrap();
 
@@ -141,5 +263,776 @@ mod tests {
 
         parse_command(&mut processor, "SELECT * FROM logs").unwrap();
     }
-}
 
+    #[test]
+    fn test_create_composite_index() {
+        let (mut processor, path) = setup_fresh_processor("composite_index");
+
+        run(&mut processor, "CREATE TABLE orders (orderid INT, customer INT, amount INT)").unwrap();
+        run(&mut processor, "INSERT INTO orders (orderid, customer, amount) VALUES (100, 1, 50)").unwrap();
+        run(&mut processor, "INSERT INTO orders (orderid, customer, amount) VALUES (101, 2, 75)").unwrap();
+        run(&mut processor, "CREATE INDEX bycustomer ON orders (customer, amount)").unwrap();
+        run(&mut processor, "INSERT INTO orders (orderid, customer, amount) VALUES (102, 1, 25)").unwrap();
+
+        let check = |database: &DataBase| {
+            let tables = database.tables.borrow();
+            let orders = tables.get("orders").unwrap().borrow();
+            let index = &orders.indexes()[0];
+            assert_eq!(index.name, "bycustomer");
+            assert_eq!(index.positions(), [2, 3]);
+            assert_eq!(index.lookup_prefix(&[Value::Int(1)]), vec![2, 0]);
+            assert_eq!(index.lookup_prefix(&[Value::Int(2), Value::Int(75)]), vec![1]);
+        };
+        check(&processor.database);
+        check(&reopen(&processor, &path).database);
+    }
+
+    #[test]
+    fn test_create_index_errors() {
+        let (mut processor, _) = setup_fresh_processor("index_errors");
+
+        run(&mut processor, "CREATE TABLE orders (orderid INT, customer INT)").unwrap();
+        assert_eq!(run(&mut processor, "CREATE INDEX idx ON missing (customer)"), Err("Table not found".to_string()));
+        assert_eq!(run(&mut processor, "CREATE INDEX idx ON orders (amount)"), Err("Column amount not found".to_string()));
+        assert_eq!(run(&mut processor, "CREATE INDEX idx ON orders (customer)"), Ok(()));
+        assert_eq!(run(&mut processor, "CREATE INDEX idx ON orders (orderid)"), Err("Index idx already exists".to_string()));
+        let long_name = "by_customer_and_order_id_descending";
+        assert_eq!(
+            run(&mut processor, &format!("CREATE INDEX {} ON orders (customer, orderid)", long_name)),
+            Err(format!("Index name {} is longer than 32 bytes", long_name))
+        );
+    }
+
+    #[test]
+    fn test_catalog_full_rejects_tables_and_indexes() {
+        let (mut processor, path) = setup_fresh_processor("catalog_full");
+        //Runs `sql(n)` for n = 0, 1, ... until it fails and returns how many succeeded.
+        let fill = |processor: &mut Processor, sql: &dyn Fn(usize) -> String| {
+            let mut created = 0;
+            loop {
+                match run(processor, &sql(created)) {
+                    Ok(()) => created += 1,
+                    Err(error) => {
+                        assert!(error.starts_with("Catalog full"), "{}", error);
+                        return created;
+                    }
+                }
+            }
+        };
+
+        let created = fill(&mut processor, &|n| format!("CREATE TABLE table{} (a INT, b INT, c INT, d INT)", n));
+        assert!(processor.database.tables.borrow().get(&format!("table{}", created)).is_none());
+        assert_eq!(*processor.database.num_tables.borrow() as usize, created);
+        let indexes = fill(&mut processor, &|n| format!("CREATE INDEX by{} ON table0 (a, b, c, d)", n));
+        assert_eq!(processor.database.tables.borrow().get("table0").unwrap().borrow().indexes().len(), indexes);
+
+        //Dropping a table makes room again.
+        run(&mut processor, "DROP TABLE table1").unwrap();
+        run(&mut processor, &format!("CREATE INDEX by{} ON table0 (a, b, c, d)", indexes)).unwrap();
+
+        let reopened = reopen(&processor, &path);
+        assert_eq!(reopened.database.tables.borrow().len(), created - 1);
+        assert_eq!(reopened.database.tables.borrow().get("table0").unwrap().borrow().indexes().len(), indexes + 1);
+    }
+
+    #[test]
+    fn test_files_without_the_current_format_version_are_rejected() {
+        let path = temp_db_path("format_version");
+        let open = || DataBase::new(path.clone()).err().unwrap();
+
+        //A catalog written before the header existed starts with its table count.
+        let mut old = vec![0u8; table::PAGE_SIZE];
+        old[0] = 1;
+        old[1..7].copy_from_slice(b"people");
+        std::fs::write(&path, &old).unwrap();
+        assert!(open().ends_with("is not a database file or was written before format version 1, older files can't be upgraded"));
+
+        let mut older = b"MINIDB".to_vec();
+        older.extend_from_slice(&(pager::FORMAT_VERSION - 1).to_le_bytes());
+        older.resize(table::PAGE_SIZE, 0);
+        std::fs::write(&path, &older).unwrap();
+        assert!(open().ends_with("Older files can't be upgraded, create the database again"));
+
+        let mut newer = b"MINIDB".to_vec();
+        newer.extend_from_slice(&99u16.to_le_bytes());
+        newer.resize(table::PAGE_SIZE, 0);
+        std::fs::write(&path, &newer).unwrap();
+        assert!(open().ends_with(&format!("has format version 99, expected {}", pager::FORMAT_VERSION)));
+        assert_eq!(std::fs::read(&path).unwrap(), newer);
+    }
+
+    //A clustered events table whose rows were inserted out of key order.
+    fn setup_events(name: &str) -> (Processor, String) {
+        let (mut processor, path) = setup_fresh_processor(name);
+        run(&mut processor, "CREATE TABLE events (seq INT PRIMARY KEY, name STRING) CLUSTERED").unwrap();
+        run_all(&mut processor, [30, 10, 20, 40].map(|seq| format!("INSERT INTO events (seq, name) VALUES ({}, 'event{}')", seq, seq)));
+        (processor, path)
+    }
+
+    #[test]
+    fn test_clustered_table_orders_rows_by_primary_key() {
+        let (mut processor, path) = setup_events("clustered");
+
+        assert_eq!(
+            run(&mut processor, "INSERT INTO events (seq, name) VALUES (20, 'again')"),
+            Err("Error inserting row: Duplicate primary key Int(20)".to_string())
+        );
+        let (_, rows) = select(&mut processor, "SELECT name FROM events WHERE seq > 15 AND seq <= 30").unwrap();
+        assert_eq!(rows, vec![vec![Value::String("event20".to_string())], vec![Value::String("event30".to_string())]]);
+
+        // Pages on disk hold the rows in key order and the table stays clustered
+        let seqs = |database: &DataBase, lower: Bound<&Value>, upper: Bound<&Value>| -> Vec<Value> {
+            let tables = database.tables.borrow();
+            let mut events = tables.get("events").unwrap().borrow_mut();
+            assert!(events.is_clustered());
+            events.scan_primary_key(lower, upper).into_iter().map(|(_, row)| row[1].clone()).collect()
+        };
+        let reopened = reopen(&processor, &path);
+        assert_eq!(seqs(&reopened.database, Bound::Unbounded, Bound::Unbounded), [10, 20, 30, 40].map(Value::Int));
+        assert_eq!(
+            seqs(&reopened.database, Bound::Excluded(&Value::Int(10)), Bound::Included(&Value::Int(30))),
+            [20, 30].map(Value::Int)
+        );
+        assert_eq!(seqs(&reopened.database, Bound::Included(&Value::Int(40)), Bound::Unbounded), [Value::Int(40)]);
+    }
+
+    #[test]
+    fn test_clustered_inserts_only_write_their_page() {
+        let (mut processor, path) = setup_fresh_processor("clustered_pages");
+        let snapshot = || -> Vec<Vec<u8>> { std::fs::read(&path).unwrap().chunks(table::PAGE_SIZE).map(|page| page.to_vec()).collect() };
+        let changed = |before: &[Vec<u8>], after: &[Vec<u8>]| -> Vec<usize> {
+            (0..after.len()).filter(|page| before.get(*page) != Some(&after[*page])).collect()
+        };
+        let insert = |seq: i64| format!("INSERT INTO events (seq, name) VALUES ({}, 'event{}')", seq, seq);
+
+        //18 rows fit on a page, ascending keys fill every page before starting the next one.
+        run(&mut processor, "CREATE TABLE events (seq INT PRIMARY KEY, name STRING) CLUSTERED").unwrap();
+        run_all(&mut processor, (0..360).step_by(2).map(insert));
+        let before = snapshot();
+        assert_eq!(before.len(), 12);
+
+        //A full page splits into itself and a new page, the pages after it stay as they are.
+        run(&mut processor, &insert(1)).unwrap();
+        let after_split = snapshot();
+        assert_eq!(changed(&before, &after_split), vec![2, 12]);
+        run(&mut processor, &insert(3)).unwrap();
+        assert_eq!(changed(&after_split, &snapshot()), vec![2]);
+        let before = snapshot();
+        run(&mut processor, "UPDATE events SET name = 'renamed' WHERE seq = 100").unwrap();
+        assert_eq!(changed(&before, &snapshot()).len(), 1);
+        run(&mut processor, "UPDATE events SET seq = 5 WHERE seq = 100").unwrap();
+
+        //Deleting every row of a page frees it for the next split.
+        run(&mut processor, "DELETE FROM events WHERE seq >= 180 AND seq < 216").unwrap();
+        let pages = snapshot().len();
+        run_all(&mut processor, (181..216).step_by(2).map(insert));
+        assert_eq!(snapshot().len(), pages);
+
+        let mut expected: Vec<i64> = (0..360).step_by(2).filter(|seq| !(180..216).contains(seq) && *seq != 100).collect();
+        expected.extend([1, 3, 5]);
+        expected.extend((181..216).step_by(2));
+        expected.sort();
+        let mut reopened = reopen(&processor, &path);
+        let seqs = first_column(&stored_rows(&mut reopened, "events"));
+        assert_eq!(seqs, expected.iter().map(|seq| Value::Int(*seq)).collect::<Vec<Value>>());
+        let tables = reopened.database.tables.borrow();
+        let mut events = tables.get("events").unwrap().borrow_mut();
+        let range: Vec<Value> = events
+            .scan_primary_key(Bound::Excluded(&Value::Int(176)), Bound::Included(&Value::Int(185)))
+            .into_iter()
+            .map(|(_, row)| row[1].clone())
+            .collect();
+        assert_eq!(range, [178, 181, 183, 185].map(Value::Int));
+    }
+
+    #[test]
+    fn test_primary_key_requires_clustered_table() {
+        let (mut processor, _) = setup_fresh_processor("primary_key");
+
+        assert_eq!(
+            run(&mut processor, "CREATE TABLE events (seq INT PRIMARY KEY, name STRING)"),
+            Err("PRIMARY KEY is only supported on CLUSTERED tables".to_string())
+        );
+    }
+
+    #[test]
+    fn test_covering_index_answers_select() {
+        let (mut processor, _) = setup_fresh_processor("covering_index");
+
+        run(&mut processor, "CREATE TABLE users (age INT, name STRING, city STRING)").unwrap();
+        run(&mut processor, "INSERT INTO users (age, name, city) VALUES (30, 'bob', 'paris')").unwrap();
+        run(&mut processor, "INSERT INTO users (age, name, city) VALUES (25, 'alice', 'rome')").unwrap();
+        run(&mut processor, "CREATE INDEX byname ON users (name) INCLUDE (age)").unwrap();
+
+        let tables = processor.database.tables.borrow();
+        let mut users = tables.get("users").unwrap().borrow_mut();
+        assert_eq!(users.indexes()[0].included_positions(), [1]);
+        assert!(users.covering_index(&[1, 2]).is_some());
+        assert!(users.covering_index(&[2, 3]).is_none());
+
+        let columns: HashSet<String> = ["age".to_string(), "name".to_string()].into();
+        let (header, rows) = users.select_rows(&columns);
+        assert_eq!(header, vec!["age".to_string(), "name".to_string()]);
+        assert_eq!(rows, vec![
+            vec![Value::Int(25), Value::String("alice".to_string())],
+            vec![Value::Int(30), Value::String("bob".to_string())],
+        ]);
+    }
+
+    #[test]
+    fn test_partial_and_expression_indexes() {
+        let (mut processor, path) = setup_fresh_processor("partial_index");
+
+        run(&mut processor, "CREATE TABLE tickets (owner STRING, status STRING)").unwrap();
+        run(&mut processor, "INSERT INTO tickets (owner, status) VALUES ('Ann', 'open')").unwrap();
+        run(&mut processor, "CREATE INDEX openbyowner ON tickets (owner) WHERE status = 'open'").unwrap();
+        run(&mut processor, "CREATE INDEX byowner ON tickets (LOWER(owner))").unwrap();
+        run(&mut processor, "INSERT INTO tickets (owner, status) VALUES ('ANN', 'closed')").unwrap();
+        run(&mut processor, "INSERT INTO tickets (owner, status) VALUES ('ann', 'open')").unwrap();
+
+        let check = |database: &DataBase| {
+            let tables = database.tables.borrow();
+            let tickets = tables.get("tickets").unwrap().borrow();
+            let partial = &tickets.indexes().iter().find(|index| index.name == "openbyowner").unwrap();
+            assert_eq!(partial.lookup_prefix(&[Value::String("Ann".to_string())]), vec![0]);
+            assert_eq!(partial.lookup_prefix(&[Value::String("ann".to_string())]), vec![2]);
+            assert!(partial.lookup_prefix(&[Value::String("ANN".to_string())]).is_empty());
+            let lower = &tickets.indexes().iter().find(|index| index.name == "byowner").unwrap();
+            assert_eq!(lower.lookup_prefix(&[Value::String("ann".to_string())]), vec![0, 1, 2]);
+        };
+        check(&processor.database);
+        check(&reopen(&processor, &path).database);
+    }
+
+    //The root page an index was stored at, None if it lives in memory only.
+    fn index_root(database: &DataBase, table: &str) -> Option<usize> {
+        database.tables.borrow().get(table).unwrap().borrow().indexes()[0].root()
+    }
+
+    #[test]
+    fn test_hash_index_survives_reopen() {
+        let (mut processor, path) = setup_fresh_processor("hash_index");
+
+        run(&mut processor, "CREATE TABLE accounts (email STRING, plan STRING)").unwrap();
+        run(&mut processor, "INSERT INTO accounts (email, plan) VALUES ('ann', 'free')").unwrap();
+        run(&mut processor, "CREATE INDEX byemail ON accounts USING HASH (email)").unwrap();
+        run(&mut processor, "INSERT INTO accounts (email, plan) VALUES ('bob', 'paid')").unwrap();
+        assert_eq!(
+            run(&mut processor, "CREATE INDEX byplan ON accounts USING BITMAP (plan)").unwrap_err().lines().next(),
+            Some("Unsupported index method BITMAP at line 1, column 39")
+        );
+
+        let check = |database: &DataBase| {
+            let tables = database.tables.borrow();
+            let accounts = tables.get("accounts").unwrap().borrow();
+            let index = &accounts.indexes()[0];
+            assert_eq!(index.method(), IndexMethod::HASH);
+            assert_eq!(index.lookup_prefix(&[Value::String("bob".to_string())]), vec![1]);
+            assert_eq!(index.lookup_prefix(&[Value::String("ann".to_string())]), vec![0]);
+        };
+        check(&processor.database);
+        let reopened = reopen(&processor, &path);
+        check(&reopened.database);
+        //The buckets are opened from the stored root instead of being built again.
+        assert!(index_root(&reopened.database, "accounts").is_some());
+        assert_eq!(index_root(&reopened.database, "accounts"), index_root(&processor.database, "accounts"));
+    }
+
+    #[test]
+    fn test_hash_index_rejects_keys_longer_than_a_page() {
+        let (mut processor, _) = setup_fresh_processor("hash_long_key");
+        //Rows fit in a page, so only a key repeating a column can outgrow a bucket.
+        let sql = format!("CREATE TABLE notes (body STRING); CREATE INDEX bybody ON notes USING HASH ({})", vec!["body"; 21].join(", "));
+        run(&mut processor, &sql).unwrap();
+        let insert = |value: &str| format!("INSERT INTO notes (body) VALUES ('{}')", value);
+        assert_eq!(
+            run(&mut processor, &insert(&"x".repeat(200))),
+            Err("Error inserting row: Hash index key of 4242 bytes is longer than 4069 bytes".to_string())
+        );
+        run(&mut processor, &insert("short")).unwrap();
+        assert_eq!(stored_rows(&mut processor, "notes").len(), 1);
+    }
+
+    #[test]
+    fn test_fulltext_index_ranks_matches() {
+        let (mut processor, path) = setup_fresh_processor("fulltext");
+
+        run(&mut processor, "CREATE TABLE notes (author STRING, body STRING)").unwrap();
+        let insert = |(author, body): (&str, &str)| format!("INSERT INTO notes (author, body) VALUES ('{}', '{}')", author, body);
+        run_all(&mut processor, [("ann", "fox"), ("bob", "fox"), ("cy", "dog"), ("di", "cat")].map(insert));
+        assert_eq!(
+            run(&mut processor, "SELECT author FROM notes WHERE body MATCH fox"),
+            Err("No full-text index on column body".to_string())
+        );
+        assert_eq!(
+            run(&mut processor, "CREATE FULLTEXT INDEX byauthor ON notes (author, body)"),
+            Err("Full-text indexes need a single STRING column".to_string())
+        );
+        run(&mut processor, "CREATE FULLTEXT INDEX bybody ON notes (body)").unwrap();
+        run(&mut processor, &insert(("ed", "dog"))).unwrap();
+        assert_eq!(run(&mut processor, "SELECT author FROM notes WHERE body MATCH 'fox dog'"), Ok(()));
+
+        let check = |database: &DataBase| {
+            let tables = database.tables.borrow();
+            let mut notes = tables.get("notes").unwrap().borrow_mut();
+            let columns: HashSet<String> = ["author".to_string()].into();
+            let text_match = TextMatch { column: "body".to_string(), query: "fox dog".to_string() };
+            let (_, rows) = notes.match_rows(&columns, &text_match).unwrap();
+            let authors: Vec<String> = rows.iter().map(|row| row[0].to_string()).collect();
+            assert_eq!(authors, vec!["ann", "bob", "cy", "ed"]);
+            assert_eq!(notes.indexes()[0].method(), IndexMethod::FULLTEXT);
+        };
+        check(&processor.database);
+        let reopened = reopen(&processor, &path);
+        check(&reopened.database);
+        //The posting lists are opened from the stored root instead of being built again.
+        assert!(index_root(&reopened.database, "notes").is_some());
+        assert_eq!(index_root(&reopened.database, "notes"), index_root(&processor.database, "notes"));
+    }
+
+    #[test]
+    fn test_zone_maps_skip_pages() {
+        let (mut processor, path) = setup_fresh_processor("zone_maps");
+
+        run(&mut processor, "CREATE TABLE events (seq INT, name STRING)").unwrap();
+        //18 rows of 220 bytes fit in a page, so 60 rows span 4 pages.
+        run_all(&mut processor, (0..60).map(|n| format!("INSERT INTO events (seq, name) VALUES ({}, 'user{}')", n, n)));
+
+        let check = |database: &DataBase| {
+            let tables = database.tables.borrow();
+            let mut events = tables.get("events").unwrap().borrow_mut();
+            assert_eq!(events.pages_to_scan(|_| true), vec![0, 1, 2, 3]);
+            assert_eq!(events.pages_to_scan(|zone| zone.may_contain(1, &Value::Int(40))), vec![2]);
+            assert_eq!(
+                events.pages_to_scan(|zone| zone.may_overlap(1, Bound::Excluded(&Value::Int(50)), Bound::Unbounded)),
+                vec![2, 3]
+            );
+            assert!(events.pages_to_scan(|zone| zone.may_contain(2, &Value::String("nobody".to_string()))).len() < 2);
+            let rows = events.scan_pages(|zone| zone.may_contain(2, &Value::String("user19".to_string())));
+            assert!(rows.iter().any(|(row_number, row)| *row_number == 19 && row[1] == Value::Int(19)));
+            assert!(rows.len() <= 36);
+        };
+        check(&processor.database);
+        check(&reopen(&processor, &path).database);
+    }
+
+    #[test]
+    fn test_numeric_values_keep_their_type() {
+        let (mut processor, path) = setup_fresh_processor("numeric");
+
+        run(&mut processor, "CREATE TABLE readings (sensor INT, level FLOAT, label STRING)").unwrap();
+        run(&mut processor, "INSERT INTO readings (sensor, level, label) VALUES (-42, 2.5e-3, 17)").unwrap();
+        run(&mut processor, "INSERT INTO readings (sensor, level) VALUES (9000000000, -7)").unwrap();
+        assert_eq!(
+            run(&mut processor, "INSERT INTO readings (sensor) VALUES (1.5)"),
+            Err("Invalid INT value 1.5".to_string())
+        );
+
+        let expected = vec![
+            vec![Value::Int(-42), Value::Float(0.0025), Value::String("17".to_string())],
+            vec![Value::Int(9000000000), Value::Float(-7.0), Value::String("".to_string())],
+        ];
+        assert_eq!(stored_rows(&mut processor, "readings"), expected);
+        assert_eq!(stored_rows(&mut reopen(&processor, &path), "readings"), expected);
+    }
+
+    #[test]
+    fn test_quoted_string_literals() {
+        let (mut processor, _) = setup_fresh_processor("strings");
+
+        run(&mut processor, "CREATE TABLE people (age INT, name STRING)").unwrap();
+        run(&mut processor, "INSERT INTO people (age, name) VALUES (1, 'Alice')").unwrap();
+        run(&mut processor, "INSERT INTO people (age, name) VALUES (2, 'O''Brien, Pat (Jr.)')").unwrap();
+        run(&mut processor, "INSERT INTO people (age, name) VALUES ('3', 'SELECT')").unwrap();
+        assert_eq!(
+            run(&mut processor, "INSERT INTO people (age, name) VALUES (4, 'Bob)").unwrap_err().lines().next(),
+            Some("Unterminated string literal at line 1, column 43")
+        );
+
+        assert_eq!(stored_rows(&mut processor, "people"), vec![
+            vec![Value::Int(1), Value::String("Alice".to_string())],
+            vec![Value::Int(2), Value::String("O'Brien, Pat (Jr.)".to_string())],
+            vec![Value::Int(3), Value::String("SELECT".to_string())],
+        ]);
+    }
+
+    #[test]
+    fn test_syntax_errors_point_at_the_token() {
+        let (mut processor, _) = setup_fresh_processor("diagnostics");
+
+        run(&mut processor, "CREATE TABLE people (age INT, name STRING)").unwrap();
+        let mut error = |sql: &str| run(&mut processor, sql).unwrap_err();
+        assert_eq!(
+            error("INSERT INTO people (age, name)\nVALUES 1, 'Ann')"),
+            "Expected ( for Columns specifier at line 2, column 8\nVALUES 1, 'Ann')\n       ^\nexpected ("
+        );
+        assert_eq!(
+            error("SELECT age FROM people WHERE age IS 3"),
+            "Expected NULL after IS at line 1, column 37\nSELECT age FROM people WHERE age IS 3\n                                    ^\nexpected NULL, NOT NULL"
+        );
+        assert_eq!(
+            error("GRANT people"),
+            "Unexpected token at line 1, column 1\nGRANT people\n^^^^^\nexpected SELECT, INSERT, UPDATE, DELETE, CREATE, DROP, TRUNCATE"
+        );
+        assert_eq!(
+            error("DROP people"),
+            "Expected TABLE keyword at line 1, column 6\nDROP people\n     ^^^^^^\nexpected TABLE"
+        );
+        assert_eq!(
+            error("INSERT INTO people (age name) VALUES (1, 'Ann')"),
+            "Expected , or ) in column list at line 1, column 25\nINSERT INTO people (age name) VALUES (1, 'Ann')\n                        ^^^^\nexpected ,, )"
+        );
+        assert_eq!(
+            error("INSERT INTO people (age, name) VALUES (1 'Ann')"),
+            "Expected , or ) in value list at line 1, column 42\nINSERT INTO people (age, name) VALUES (1 'Ann')\n                                         ^^^^^\nexpected ,, )"
+        );
+        assert_eq!(
+            error("CREATE TABLE pets (tag UUID)"),
+            "Expected data type at line 1, column 24\nCREATE TABLE pets (tag UUID)\n                       ^^^^\nexpected INT, FLOAT, STRING"
+        );
+        assert_eq!(error("INSERT INTO ghosts (age) VALUES (1)"), "Table not found");
+    }
+
+    #[test]
+    fn test_scripts_run_every_statement() {
+        let (mut processor, _) = setup_fresh_processor("script");
+
+        let script = "-- schema
+            CREATE TABLE people (age INT, name STRING);
+            /* seed data,
+               one row per statement */
+            INSERT INTO people (age, name) VALUES (1, 'Ann; Lee');;
+            INSERT INTO people (age) VALUES 2;
+            INSERT INTO people (age, name) VALUES (3, 'Cy') -- trailing comment
+            ;INSERT INTO people (age, name) VALUES (4, 'Dee') garbage;
+            INSERT INTO people (age, name) VALUES (5, 'Eve')";
+        let results = Executor::new(&mut processor).run_script(script);
+        let errors: Vec<Option<&str>> = results.iter()
+            .map(|result| result.as_ref().err().and_then(|error| error.lines().next()))
+            .collect();
+        assert_eq!(errors, vec![
+            None,
+            None,
+            Some("Expected ( for Columns specifier at line 6, column 45"),
+            None,
+            Some("Expected ; after statement at line 8, column 63"),
+            None,
+        ]);
+
+        let results = Executor::new(&mut processor).run_script("INSERT INTO people (age, name) VALUES (6, 'Fay'); INSERT INTO people (age, name) VALUES (7, 'Gus)");
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(results[1].as_ref().unwrap_err().starts_with("Unterminated string literal"));
+
+        assert_eq!(first_column(&stored_rows(&mut processor, "people")), [1, 3, 5, 6].map(Value::Int));
+    }
+
+    #[test]
+    fn test_prepared_statements_bind_values() {
+        let (mut processor, _) = setup_fresh_processor("prepared");
+
+        run(&mut processor, "CREATE TABLE people (age INT, name STRING)").unwrap();
+        let mut insert = PreparedStatement::prepare("INSERT INTO people (age, name) VALUES (?, ?)").unwrap();
+        assert_eq!(insert.parameter_count(), 2);
+        for (age, name) in [(1, "Ann"), (2, "O'Brien'); CREATE TABLE oops (a INT"), (3, "Cy")] {
+            insert.bind(1, Value::Int(age)).unwrap();
+            insert.bind(2, Value::String(name.to_string())).unwrap();
+            insert.execute(&mut processor).unwrap();
+        }
+
+        let mut reordered = PreparedStatement::prepare("INSERT INTO people (name, age) VALUES ($2, $1)").unwrap();
+        assert_eq!(reordered.bind(3, Value::Int(0)), Err("Parameter index 3 out of range, the statement has 2".to_string()));
+        reordered.bind(1, Value::Int(4)).unwrap();
+        assert_eq!(reordered.execute(&mut processor), Err("Parameter $2 is not bound".to_string()));
+        reordered.bind(2, Value::String("Dee".to_string())).unwrap();
+        reordered.execute(&mut processor).unwrap();
+        reordered.clear_bindings();
+        assert!(reordered.execute(&mut processor).is_err());
+
+        assert!(PreparedStatement::prepare("INSERT INTO people (age) VALUES ('open)").is_err());
+        assert!(PreparedStatement::prepare("INSERT INTO ? (age) VALUES (5)").err().unwrap().starts_with("Expected table name"));
+
+        assert!(processor.database.tables.borrow().get("oops").is_none());
+        assert_eq!(stored_rows(&mut processor, "people"), vec![
+            vec![Value::Int(1), Value::String("Ann".to_string())],
+            vec![Value::Int(2), Value::String("O'Brien'); CREATE TABLE oops (a INT".to_string())],
+            vec![Value::Int(3), Value::String("Cy".to_string())],
+            vec![Value::Int(4), Value::String("Dee".to_string())],
+        ]);
+    }
+
+    #[test]
+    fn test_repl_prepares_and_binds_statements() {
+        let (mut processor, _) = setup_fresh_processor("repl_prepared");
+        run(&mut processor, "CREATE TABLE people (age INT, name STRING)").unwrap();
+        let mut prepared = None;
+        let mut command = |command: &str| prepared_command(&mut processor, &mut prepared, command);
+
+        assert_eq!(command(".bind 1 5"), Err("No prepared statement, use .prepare <sql> first".to_string()));
+        command(".prepare INSERT INTO people (age, name) VALUES (? -1, ?)").unwrap();
+        command(".bind 1 -5").unwrap();
+        command(".bind 2 'Ann Lee'").unwrap();
+        command(".execute").unwrap();
+        command(".bind 1 10 * 2").unwrap();
+        command(".bind 2 'Bo'").unwrap();
+        command(".execute").unwrap();
+        command(".clear").unwrap();
+        assert_eq!(command(".execute"), Err("Parameter $1 is not bound".to_string()));
+        assert_eq!(command(".bind 3 1"), Err("Parameter index 3 out of range, the statement has 2".to_string()));
+        assert_eq!(command(".bind x 1"), Err("Invalid parameter index x".to_string()));
+        assert_eq!(command(".bind 1 NULL"), Err("NULL values are not supported".to_string()));
+        assert_eq!(command(".bind 1 1 2"), Err("Expected a single expression".to_string()));
+
+        assert_eq!(stored_rows(&mut processor, "people"), vec![
+            vec![Value::Int(-6), Value::String("Ann Lee".to_string())],
+            vec![Value::Int(19), Value::String("Bo".to_string())],
+        ]);
+    }
+
+    #[test]
+    fn test_select_list_and_values_expressions() {
+        let (mut processor, _) = setup_fresh_processor("expressions");
+
+        run(&mut processor, "CREATE TABLE people (age INT, name STRING)").unwrap();
+        run(&mut processor, "INSERT INTO people (age, name) VALUES (20 + 2 * 5, 'an' || 'n')").unwrap();
+        run(&mut processor, "INSERT INTO people (age, name) VALUES (-(3 - 10), bob)").unwrap();
+        assert_eq!(run(&mut processor, "INSERT INTO people (age, name) VALUES (1 / 0, 'x')"), Err("Division by zero".to_string()));
+        assert_eq!(run(&mut processor, "INSERT INTO people (age, name) VALUES (age + 1, 'x')"), Err("Column age not found".to_string()));
+
+        let (header, rows) = select(&mut processor, "SELECT name, people.age * 2 + 1, UPPER(name) || '!', age BETWEEN 10 AND 40 FROM people").unwrap();
+        assert_eq!(header, vec!["name", "(people.age * 2) + 1", "UPPER(name) || '!'", "age BETWEEN 10 AND 40"]);
+        assert_eq!(rows, vec![
+            vec![Value::String("ann".to_string()), Value::Int(61), Value::String("ANN!".to_string()), Value::Int(1)],
+            vec![Value::String("bob".to_string()), Value::Int(15), Value::String("BOB!".to_string()), Value::Int(0)],
+        ]);
+        assert_eq!(select(&mut processor, "SELECT other.age FROM people"), Err("Unknown table other".to_string()));
+    }
+
+    //A people table of `count` rows, user<n> is <n> years old.
+    fn setup_people(name: &str, count: usize) -> (Processor, String) {
+        let (mut processor, path) = setup_fresh_processor(name);
+        run(&mut processor, "CREATE TABLE people (age INT, name STRING)").unwrap();
+        run_all(&mut processor, (0..count).map(|n| format!("INSERT INTO people (age, name) VALUES ({}, 'user{}')", n, n)));
+        (processor, path)
+    }
+
+    #[test]
+    fn test_where_filters_rows() {
+        let (mut processor, _) = setup_fresh_processor("where");
+
+        run(&mut processor, "CREATE TABLE people (age INT, name STRING, city STRING)").unwrap();
+        run_all(&mut processor, (0..60).map(|n| format!("INSERT INTO people (age, name, city) VALUES ({}, 'User{}', 'c{}')", n, n, n % 3)));
+        let select_first = |processor: &mut Processor, sql: &str| -> Result<Vec<String>, String> {
+            let (_, rows) = select(processor, sql)?;
+            Ok(rows.iter().map(|row| row[0].to_string()).collect())
+        };
+        let queries = [
+            ("SELECT name FROM people WHERE age >= 10 AND age < 13", vec!["User10", "User11", "User12"]),
+            ("SELECT name FROM people WHERE age = '7'", vec!["User7"]),
+            ("SELECT age FROM people WHERE 57 < people.age", vec!["58", "59"]),
+            ("SELECT age FROM people WHERE city = 'c1' AND age BETWEEN 20 AND 30", vec!["22", "25", "28"]),
+            ("SELECT age FROM people WHERE LOWER(name) = 'user42' OR age = 3", vec!["3", "42"]),
+            ("SELECT age FROM people WHERE city = 'c2' AND NOT age > 5", vec!["2", "5"]),
+            ("SELECT age FROM people WHERE name LIKE '%9' AND age % 2 = 1", vec!["9", "19", "29", "39", "49", "59"]),
+            ("SELECT age FROM people WHERE city = 'c0' AND age > 100", vec![]),
+        ];
+        let scanned: Vec<_> = queries.iter().map(|(sql, _)| select_first(&mut processor, sql).unwrap()).collect();
+        for ((_, expected), rows) in queries.iter().zip(scanned.iter()) {
+            assert_eq!(rows, expected);
+        }
+        assert_eq!(
+            select_first(&mut processor, "SELECT age FROM people WHERE name"),
+            Err("Expected a boolean, found STRING User0".to_string())
+        );
+
+        //The same queries answered through indexes return the same rows.
+        run(&mut processor, "CREATE INDEX bycity ON people (city, age)").unwrap();
+        run(&mut processor, "CREATE INDEX byname ON people USING HASH (LOWER(name))").unwrap();
+        for ((sql, _), rows) in queries.iter().zip(scanned.iter()) {
+            assert_eq!(&select_first(&mut processor, sql).unwrap(), rows, "{}", sql);
+        }
+
+        let tables = processor.database.tables.borrow();
+        let mut people = tables.get("people").unwrap().borrow_mut();
+        let range = |column: &str, function, lower, upper| KeyRange { column: column.to_string(), function, lower, upper };
+        let equal = |value: &str| (Bound::Included(Value::String(value.to_string())), Bound::Included(Value::String(value.to_string())));
+        let (lower, upper) = equal("c1");
+        let city = range("city", None, lower, upper);
+        let ages = range("age", None, Bound::Included(Value::Int(20)), Bound::Excluded(Value::Int(30)));
+        assert_eq!(people.filter_rows(&[city.clone(), ages.clone()]).len(), 3);
+        assert_eq!(people.filter_rows(std::slice::from_ref(&city)).len(), 20);
+        let (lower, upper) = equal("user7");
+        assert_eq!(people.filter_rows(&[range("name", Some(KeyFunction::LOWER), lower, upper)]).len(), 1);
+        //Without a usable index the zone maps still skip pages outside the range.
+        let rows = people.filter_rows(&[ages]);
+        assert!(rows.len() < 60 && rows.iter().any(|row| row[1] == Value::Int(29)));
+    }
+
+    #[test]
+    fn test_update_rewrites_rows_and_indexes() {
+        let (mut processor, path) = setup_people("update", 40);
+
+        run(&mut processor, "CREATE INDEX byname ON people (name)").unwrap();
+        run(&mut processor, "CREATE INDEX byage ON people USING HASH (age)").unwrap();
+        run(&mut processor, "UPDATE people SET age = age + 100, name = name || '!' WHERE age < 3 OR name = 'user39'").unwrap();
+        assert_eq!(run(&mut processor, "UPDATE people SET id = 'x'"), Err("Column id can't be updated".to_string()));
+        assert_eq!(run(&mut processor, "UPDATE people SET nope = 1"), Err("Column nope not found".to_string()));
+        //An error in any row leaves every row as it was.
+        assert_eq!(run(&mut processor, "UPDATE people SET age = 100 / (age - 20)"), Err("Division by zero".to_string()));
+
+        let check = |database: &DataBase| {
+            let tables = database.tables.borrow();
+            let people = tables.get("people").unwrap().borrow();
+            let byname = people.indexes().iter().find(|index| index.name == "byname").unwrap();
+            let byage = people.indexes().iter().find(|index| index.name == "byage").unwrap();
+            assert_eq!(byname.lookup_prefix(&[Value::String("user1!".to_string())]), vec![1]);
+            assert!(byname.lookup_prefix(&[Value::String("user1".to_string())]).is_empty());
+            assert_eq!(byage.lookup_prefix(&[Value::Int(139)]), vec![39]);
+            assert!(byage.lookup_prefix(&[Value::Int(39)]).is_empty());
+            assert_eq!(byage.lookup_prefix(&[Value::Int(20)]), vec![20]);
+        };
+        check(&processor.database);
+        let mut reopened = reopen(&processor, &path);
+        check(&reopened.database);
+
+        let (_, rows) = select(&mut reopened, "SELECT age, name FROM people WHERE age >= 100 OR age = 7").unwrap();
+        let rows: Vec<String> = rows.iter().map(|row| format!("{} {}", row[0], row[1])).collect();
+        assert_eq!(rows, vec!["100 user0!", "101 user1!", "102 user2!", "7 user7", "139 user39!"]);
+    }
+
+    #[test]
+    fn test_update_clustered_table_moves_rows() {
+        let (mut processor, path) = setup_events("update_clustered");
+
+        assert_eq!(
+            run(&mut processor, "UPDATE events SET seq = 30 WHERE seq = 20"),
+            Err("Duplicate primary key Int(30)".to_string())
+        );
+        run(&mut processor, "UPDATE events SET seq = 70 - seq WHERE seq >= 20").unwrap();
+
+        let rows: Vec<String> = stored_rows(&mut reopen(&processor, &path), "events")
+            .iter()
+            .map(|row| format!("{} {}", row[0], row[1]))
+            .collect();
+        assert_eq!(rows, vec!["10 event10", "30 event40", "40 event30", "50 event20"]);
+    }
+
+    #[test]
+    fn test_delete_leaves_reusable_tombstones() {
+        let (mut processor, path) = setup_people("delete", 40);
+        let ages = |processor: &mut Processor| first_column(&stored_rows(processor, "people"));
+        let name_lookup = |processor: &Processor, name: &str| -> Vec<usize> {
+            let tables = processor.database.tables.borrow();
+            let people = tables.get("people").unwrap().borrow();
+            people.indexes()[0].lookup_prefix(&[Value::String(name.to_string())])
+        };
+
+        run(&mut processor, "CREATE INDEX byname ON people (name)").unwrap();
+        run(&mut processor, "DELETE FROM people WHERE age >= 10 AND age < 15 OR name = 'user30'").unwrap();
+        assert_eq!(run(&mut processor, "DELETE FROM people WHERE name > 3"), Err("Cannot compare STRING with INT".to_string()));
+        let live: Vec<Value> = (0..40).filter(|n| !(10..15).contains(n) && *n != 30).map(Value::Int).collect();
+        assert_eq!(ages(&mut processor), live);
+        assert!(name_lookup(&processor, "user12").is_empty());
+
+        //Reopening finds the deleted slots again and inserts fill them before the table grows.
+        let mut reopened = reopen(&processor, &path);
+        assert_eq!(ages(&mut reopened), live);
+        run_all(&mut reopened, (100..107).map(|n| format!("INSERT INTO people (age, name) VALUES ({}, 'user{}')", n, n)));
+        let ages_after = ages(&mut reopened);
+        assert_eq!(ages_after.len(), 41);
+        assert_eq!(ages_after[10..16], [100, 101, 102, 103, 104, 15].map(Value::Int));
+        assert_eq!(ages_after[29..32], [29, 105, 31].map(Value::Int));
+        assert_eq!(ages_after.last(), Some(&Value::Int(106)));
+        assert_eq!(name_lookup(&reopened, "user105"), vec![30]);
+
+        run(&mut reopened, "DELETE FROM people").unwrap();
+        assert!(ages(&mut reopened).is_empty());
+    }
+
+    #[test]
+    fn test_delete_from_clustered_table() {
+        let (mut processor, path) = setup_events("delete_clustered");
+
+        run(&mut processor, "DELETE FROM events WHERE seq BETWEEN 15 AND 30").unwrap();
+        assert_eq!(first_column(&stored_rows(&mut reopen(&processor, &path), "events")), [10, 40].map(Value::Int));
+    }
+
+    #[test]
+    fn test_drop_and_truncate_tables() {
+        let (mut processor, path) = setup_people("drop", 30);
+
+        run(&mut processor, "CREATE TABLE pets (name STRING)").unwrap();
+        run(&mut processor, "CREATE INDEX byname ON people (name)").unwrap();
+        run(&mut processor, "TRUNCATE TABLE people; INSERT INTO people (age, name) VALUES (7, 'ann')").unwrap();
+        run(&mut processor, "DROP TABLE pets").unwrap();
+        assert_eq!(run(&mut processor, "DROP TABLE pets"), Err("Table not found".to_string()));
+        assert_eq!(run(&mut processor, "TRUNCATE TABLE pets"), Err("Table not found".to_string()));
+        run(&mut processor, "DROP TABLE IF EXISTS pets").unwrap();
+
+        let mut reopened = reopen(&processor, &path);
+        assert_eq!(*reopened.database.num_tables.borrow(), 1);
+        assert!(!reopened.database.tables.borrow().contains_key("pets"));
+        let (names, _) = reopened.scan_table("people").unwrap();
+        assert_eq!(names, vec!["id", "age", "name"]);
+        assert_eq!(stored_rows(&mut reopened, "people"), vec![vec![Value::Int(7), Value::String("ann".to_string())]]);
+        let tables = reopened.database.tables.borrow();
+        let people = tables.get("people").unwrap().borrow();
+        assert_eq!(people.indexes()[0].lookup_prefix(&[Value::String("ann".to_string())]), vec![0]);
+        assert!(people.indexes()[0].lookup_prefix(&[Value::String("user3".to_string())]).is_empty());
+    }
+
+    #[test]
+    fn test_dropped_and_truncated_pages_are_reused() {
+        let (mut processor, path) = setup_fresh_processor("page_reuse");
+        let file_pages = || std::fs::metadata(&path).unwrap().len() as usize / table::PAGE_SIZE;
+        let insert = |table: &'static str, prefix: &'static str| {
+            (0..200).map(move |n| format!("INSERT INTO {} (name) VALUES ('{}{}')", table, prefix, n))
+        };
+
+        run(&mut processor, "CREATE TABLE people (name STRING); CREATE TABLE pets (name STRING)").unwrap();
+        //Interleaved inserts leave the pages of pets between those of people.
+        run_all(&mut processor, insert("people", "user").zip(insert("pets", "pet")).flat_map(|(person, pet)| [person, pet]));
+        processor.database.flush().unwrap();
+        let pages = file_pages();
+
+        //A table created after a reopen fills the pages pets left behind.
+        run(&mut processor, "DROP TABLE pets").unwrap();
+        let mut reopened = reopen(&processor, &path);
+        run(&mut reopened, "CREATE TABLE toys (name STRING)").unwrap();
+        run_all(&mut reopened, insert("toys", "toy"));
+        reopened.database.flush().unwrap();
+        assert_eq!(file_pages(), pages);
+
+        //Truncated rows give their pages back as well.
+        run(&mut reopened, "TRUNCATE TABLE people").unwrap();
+        run_all(&mut reopened, insert("people", "again"));
+        reopened.database.flush().unwrap();
+        assert_eq!(file_pages(), pages);
+
+        //Without tables only the header page is left.
+        run(&mut reopened, "DROP TABLE people; DROP TABLE toys").unwrap();
+        assert_eq!(file_pages(), 1);
+        assert_eq!(*reopen(&reopened, &path).database.num_tables.borrow(), 0);
+    }
+
+    #[test]
+    fn test_underscored_and_quoted_names() {
+        let (mut processor, path) = setup_fresh_processor("quoted_names");
+
+        run(&mut processor, "CREATE TABLE user_accounts (user_id INT, \"select\" STRING, `Full Name` STRING)").unwrap();
+        run(&mut processor, "INSERT INTO user_accounts (user_id, \"select\", `Full Name`) VALUES (7, 'yes', 'Ann Lee')").unwrap();
+        run(&mut processor, "CREATE INDEX by_user_id ON user_accounts (user_id)").unwrap();
+
+        let check = |database: &DataBase| {
+            let tables = database.tables.borrow();
+            let mut accounts = tables.get("user_accounts").unwrap().borrow_mut();
+            let columns: HashSet<String> = ["select".to_string(), "Full Name".to_string()].into();
+            let (header, rows) = accounts.select_rows(&columns);
+            assert_eq!(header, vec!["select".to_string(), "Full Name".to_string()]);
+            assert_eq!(rows, vec![vec![Value::String("yes".to_string()), Value::String("Ann Lee".to_string())]]);
+            assert_eq!(accounts.indexes()[0].lookup_prefix(&[Value::Int(7)]), vec![0]);
+        };
+        check(&processor.database);
+        check(&reopen(&processor, &path).database);
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::table::PAGE_SIZE;
+
+/*The database file is a sequence of PAGE_SIZE pages.
+  page 0: 6 byte MAGIC, 2 byte format version, 4 byte first free page, then the catalog
+  page 1 on: handed out by `allocate` to table directories, table rows and index pages
+Free pages are chained in ascending order through their first 4 bytes, the header points
+at the lowest one. Pages freed at the end of the file shrink it instead of joining the chain.
+Files of another format version, or written before the header existed, are rejected rather
+than upgraded: every version changed how rows or pages are laid out, so such a database has
+to be created again from its SQL. */
+pub const MAGIC: &[u8; 6] = b"MINIDB";
+//1: INT fields stored as 8 byte little endian integers, each table in a fixed region of TABLE_MAX_PAGES pages.
+//2: pages allocated by the pager, every table lists its pages in a directory page.
+//3: hash indexes stored in pager pages, the catalog holds their root page.
+//4: full-text dictionaries and posting lists stored in pager pages as well.
+//5: table directories count the deleted rows of every page.
+//6: posting chains count their removed postings.
+pub const FORMAT_VERSION: u16 = 6;
+const VERSION_OFFSET: usize = 6;
+const FREE_HEAD_OFFSET: usize = 8;
+pub const HEADER_SIZE: usize = 12;
+pub const CATALOG_SIZE: usize = PAGE_SIZE - HEADER_SIZE;
+//Page 0 is never free, so it ends the chain.
+const NO_PAGE: u32 = 0;
+
+pub struct Pager {
+    file: fs::File,
+    page_count: usize,
+    free_pages: BTreeSet<usize>,
+}
+
+impl Pager {
+    //Opens the database file, or creates it with an empty catalog.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(|error| format!("Failed to open {}: {}", path.display(), error))?;
+        let length = file.metadata().map_err(|error| error.to_string())?.len() as usize;
+        let mut pager = Pager {
+            file,
+            page_count: length.div_ceil(PAGE_SIZE),
+            free_pages: BTreeSet::new(),
+        };
+        if length == 0 {
+            pager.page_count = 1;
+            pager.write(0, &Self::empty_header());
+            return Ok(pager);
+        }
+        let header = pager.read(0);
+        if &header[0..MAGIC.len()] != MAGIC {
+            return Err(format!(
+                "{} is not a database file or was written before format version 1, older files can't be upgraded",
+                path.display()
+            ));
+        }
+        let version = u16::from_le_bytes(header[VERSION_OFFSET..FREE_HEAD_OFFSET].try_into().unwrap());
+        if version < FORMAT_VERSION {
+            return Err(format!(
+                "{} has format version {}, expected {}. Older files can't be upgraded, create the database again",
+                path.display(), version, FORMAT_VERSION
+            ));
+        }
+        if version != FORMAT_VERSION {
+            return Err(format!("{} has format version {}, expected {}", path.display(), version, FORMAT_VERSION));
+        }
+        let mut page = Self::read_u32(&header, FREE_HEAD_OFFSET);
+        while page != NO_PAGE {
+            pager.free_pages.insert(page as usize);
+            page = Self::read_u32(&pager.read(page as usize), 0);
+        }
+        Ok(pager)
+    }
+
+    //A pager on a new file that is already unlinked, for structures tested on their own.
+    #[cfg(test)]
+    pub fn temporary(name: &str) -> Self {
+        static OPENED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let n = OPENED.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("mini_db_{}_{}_{}.db", name, std::process::id(), n));
+        let _ = fs::remove_file(&path);
+        let pager = Pager::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        pager
+    }
+
+    fn empty_header() -> Vec<u8> {
+        let mut header = vec![0; PAGE_SIZE];
+        header[0..MAGIC.len()].copy_from_slice(MAGIC);
+        header[VERSION_OFFSET..FREE_HEAD_OFFSET].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        header
+    }
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    pub fn read(&mut self, page: usize) -> Vec<u8> {
+        assert!(page < self.page_count, "page {} is past the end of the file", page);
+        let mut data = vec![0; PAGE_SIZE];
+        self.file.seek(SeekFrom::Start((page * PAGE_SIZE) as u64)).unwrap();
+        self.file.read_exact(&mut data).expect("Failed to read page from disk");
+        data
+    }
+
+    //Writes `data` at the start of `page`, the rest of the page is left as it was.
+    pub fn write(&mut self, page: usize, data: &[u8]) {
+        self.write_at(page, 0, data);
+    }
+
+    fn write_at(&mut self, page: usize, offset: usize, data: &[u8]) {
+        assert!(page < self.page_count && offset + data.len() <= PAGE_SIZE);
+        self.file.seek(SeekFrom::Start((page * PAGE_SIZE + offset) as u64)).unwrap();
+        self.file.write_all(data).expect("Failed to write page to disk");
+    }
+
+    pub fn read_catalog(&mut self) -> Vec<u8> {
+        self.read(0)[HEADER_SIZE..].to_vec()
+    }
+
+    pub fn write_catalog(&mut self, catalog: &[u8]) {
+        assert!(catalog.len() <= CATALOG_SIZE);
+        let mut buff = catalog.to_vec();
+        buff.resize(CATALOG_SIZE, 0);
+        self.write_at(0, HEADER_SIZE, &buff);
+        self.file.flush().unwrap();
+    }
+
+    //A zeroed page, the lowest free one or a new one at the end of the file.
+    pub fn allocate(&mut self) -> usize {
+        let page = match self.free_pages.pop_first() {
+            Some(page) => {
+                self.write_free_head();
+                page
+            }
+            None => {
+                self.page_count += 1;
+                self.page_count - 1
+            }
+        };
+        self.write(page, &[0; PAGE_SIZE]);
+        page
+    }
+
+    //Returns pages to the free list. Only the links of the freed pages and of the pages
+    //just before them in the chain are written again.
+    pub fn free(&mut self, pages: impl IntoIterator<Item = usize>) {
+        let mut freed = vec![];
+        for page in pages {
+            assert!(page > 0 && page < self.page_count, "page {} can't be freed", page);
+            if self.free_pages.insert(page) {
+                freed.push(page);
+            }
+        }
+        let mut shrunk = false;
+        while self.free_pages.last() == Some(&(self.page_count - 1)) {
+            self.free_pages.pop_last();
+            self.page_count -= 1;
+            shrunk = true;
+        }
+        if shrunk {
+            self.file.set_len((self.page_count * PAGE_SIZE) as u64).unwrap();
+        }
+        let mut relink: BTreeSet<usize> = BTreeSet::new();
+        for page in freed.into_iter().filter(|page| self.free_pages.contains(page)) {
+            relink.insert(page);
+            relink.extend(self.free_pages.range(..page).next_back());
+        }
+        if shrunk {
+            relink.extend(self.free_pages.last());
+        }
+        for page in relink {
+            let next = self.free_pages.range(page + 1..).next().map_or(NO_PAGE, |next| *next as u32);
+            self.write_at(page, 0, &next.to_le_bytes());
+        }
+        self.write_free_head();
+    }
+
+    fn write_free_head(&mut self) {
+        let head = self.free_pages.first().map_or(NO_PAGE, |page| *page as u32);
+        self.write_at(0, FREE_HEAD_OFFSET, &head.to_le_bytes());
+    }
+}
+
+impl std::fmt::Debug for Pager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pager")
+            .field("pages", &self.page_count)
+            .field("free", &self.free_pages.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("mini_db_{}_{}.db", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn file_pages(path: &Path) -> usize {
+        fs::metadata(path).unwrap().len() as usize / PAGE_SIZE
+    }
+
+    #[test]
+    fn test_freed_pages_are_reused_lowest_first() {
+        let mut pager = Pager::temporary("pager_reuse");
+        let pages: Vec<usize> = (0..6).map(|_| pager.allocate()).collect();
+        assert_eq!(pages, vec![1, 2, 3, 4, 5, 6]);
+        pager.write(3, &[7; PAGE_SIZE]);
+        pager.free([4, 2]);
+        pager.free([3]);
+        assert_eq!(pager.free_pages.len(), 3);
+        assert_eq!(pager.allocate(), 2);
+        assert_eq!(pager.allocate(), 3);
+        assert_eq!(pager.read(3), vec![0; PAGE_SIZE]);
+        assert_eq!(pager.allocate(), 4);
+        assert_eq!(pager.allocate(), 7);
+        assert_eq!(pager.page_count, 8);
+    }
+
+    #[test]
+    fn test_freeing_the_last_pages_shrinks_the_file() {
+        let path = temp_path("pager_shrink");
+        let mut pager = Pager::open(&path).unwrap();
+        for _ in 0..5 {
+            pager.allocate();
+        }
+        pager.free([2, 4]);
+        assert_eq!(file_pages(&path), 6);
+        //Freeing 5 lets 4 go as well, 2 stays on the free list.
+        pager.free([5]);
+        assert_eq!(file_pages(&path), 4);
+        assert_eq!(pager.free_pages.len(), 1);
+
+        let mut reopened = Pager::open(&path).unwrap();
+        assert_eq!(reopened.page_count, 4);
+        assert_eq!(reopened.allocate(), 2);
+        assert_eq!(reopened.allocate(), 4);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_free_list_and_catalog_survive_reopen() {
+        let path = temp_path("pager_reopen");
+        let mut pager = Pager::open(&path).unwrap();
+        for _ in 0..8 {
+            pager.allocate();
+        }
+        pager.free([6, 2]);
+        pager.free([4, 3]);
+        pager.write_catalog(b"catalog");
+
+        let mut reopened = Pager::open(&path).unwrap();
+        assert_eq!(&reopened.read_catalog()[..7], b"catalog");
+        assert_eq!(reopened.free_pages.len(), 4);
+        let reused: Vec<usize> = (0..5).map(|_| reopened.allocate()).collect();
+        assert_eq!(reused, vec![2, 3, 4, 6, 9]);
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -1,66 +1,271 @@
-use std::collections::HashMap;
-use std::{collections::HashSet};
-use pretty_table::table;
-
 use crate::lexer::Lexer;
 
 use crate::lexer::*;
-use crate::processor::Processor;
-use crate::table::{Column, ColumnType, DataType, ID_SIZE, INT_SIZE, STRING_SIZE};
+use crate::ast::{BinaryOperator, COMPARISON_PRECEDENCE, Expr, NEGATE_PRECEDENCE, NOT_PRECEDENCE, Statement, UnaryOperator};
+use crate::index::{IndexDefinition, IndexMethod, KeyFunction};
+use crate::table::{Column, ColumnType, DataType, FLOAT_SIZE, ID_SIZE, INT_SIZE, STRING_SIZE, Value};
 
 
 pub struct Parser<'a>{
     lexer:Lexer<'a>,
-    processor:&'a mut Processor
+    //Span of the offending token and what would have been accepted there, set by syntax_error.
+    syntax_error:Option<(Span, Vec<&'static str>)>,
+    //Highest placeholder number seen so far.
+    placeholders:usize,
 }
 
 //Insert into users(age, name) values(1,kowshick)
 
-
 impl<'a> Parser<'a> {
-    pub fn new(lexer: Lexer<'a>, processor:&'a mut Processor)->Self{
+    pub fn new(lexer: Lexer<'a>)->Self{
         Parser{
             lexer,
-            processor
+            syntax_error:None,
+            placeholders:0,
         }
     }
 
-    fn parse_select(&mut self) -> Result<(),String> {
-        let mut column_names = HashSet::new();
-        while let Token::Identifier(column_name) = self.lexer.consume() {
-            column_names.insert(column_name);
-            let next_token = self.lexer.consume();
-            match next_token {
+    //How many parameters the statements parsed so far need, the highest $n.
+    pub fn placeholder_count(&self) -> usize {
+        self.placeholders
+    }
+
+    //Value tokens keep the type the lexer gave them, bare words are strings.
+    fn consume_literal(&mut self) -> Option<Expr> {
+        match self.lexer.consume() {
+            Token::Identifier(value) | Token::String(value) => Some(Expr::Literal(Value::String(value))),
+            Token::Integer(value) => Some(Expr::Literal(Value::Int(value))),
+            Token::Float(value) => Some(Expr::Literal(Value::Float(value))),
+            Token::Placeholder(number) => {
+                self.placeholders = self.placeholders.max(number);
+                Some(Expr::Placeholder(number))
+            }
+            _ => None,
+        }
+    }
+
+    //Remembers where parsing failed so parse() can point at the token just consumed.
+    fn syntax_error(&mut self, message: &str, expected: &[&'static str]) -> String {
+        self.syntax_error = Some((self.lexer.last_span(), expected.to_vec()));
+        message.to_string()
+    }
+
+    fn parse_select(&mut self) -> Result<Statement,String> {
+        let mut columns = vec![];
+        loop {
+            columns.push(self.parse_expr()?);
+            match self.lexer.consume() {
                 Token::Keyword(KeyWords::COMMA) => continue,
                 Token::Keyword(KeyWords::FROM) =>  break,
-                _ => return Err("Error parsing Select Statement".to_string())
+                _ => return Err(self.syntax_error("Error parsing Select Statement", &[",", "FROM"]))
             }
         }
         let table_name = match self.lexer.consume() {
             Token::Identifier(table_name)=>table_name,
-            _ =>return Err("Expected table name".to_string())
+            _ =>return Err(self.syntax_error("Expected table name", &["table name"]))
+        };
+        let mut filter = None;
+        if let Token::Keyword(KeyWords::WHERE) = self.lexer.peek() {
+            self.lexer.consume();
+            filter = Some(self.parse_expr()?);
+        }
+        Ok(Statement::Select { table: table_name, columns, filter })
+    }
+
+    pub fn parse_expr(&mut self) -> Result<Expr,String> {
+        self.parse_binary(0)
+    }
+
+    //Precedence climbing: operands are joined by operators binding at least as
+    //tightly as `min_precedence`, tighter operators recurse for their right side.
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr,String> {
+        let mut left = self.parse_prefix()?;
+        loop {
+            if COMPARISON_PRECEDENCE >= min_precedence && self.predicate_follows() {
+                left = self.parse_predicate(left)?;
+                continue;
+            }
+            let operator = match self.lexer.peek() {
+                Token::Keyword(KeyWords::OR) => BinaryOperator::OR,
+                Token::Keyword(KeyWords::AND) => BinaryOperator::AND,
+                Token::Keyword(KeyWords::EQUALS) => BinaryOperator::EQUALS,
+                Token::Keyword(KeyWords::NOTEQUALS) => BinaryOperator::NOTEQUALS,
+                Token::Keyword(KeyWords::LESS) => BinaryOperator::LESS,
+                Token::Keyword(KeyWords::LESSEQUALS) => BinaryOperator::LESSEQUALS,
+                Token::Keyword(KeyWords::GREATER) => BinaryOperator::GREATER,
+                Token::Keyword(KeyWords::GREATEREQUALS) => BinaryOperator::GREATEREQUALS,
+                Token::Keyword(KeyWords::CONCAT) => BinaryOperator::CONCAT,
+                Token::Keyword(KeyWords::PLUS) => BinaryOperator::PLUS,
+                Token::Keyword(KeyWords::MINUS) => BinaryOperator::MINUS,
+                Token::Identifier(name) if name == "*" => BinaryOperator::MULTIPLY,
+                Token::Keyword(KeyWords::SLASH) => BinaryOperator::DIVIDE,
+                Token::Keyword(KeyWords::PERCENT) => BinaryOperator::MODULO,
+                _ => break,
+            };
+            if operator.precedence() < min_precedence {
+                break;
+            }
+            self.lexer.consume();
+            let right = self.parse_binary(operator.precedence() + 1)?;
+            left = Expr::Binary { left: Box::new(left), operator, right: Box::new(right) };
+        }
+        Ok(left)
+    }
+
+    fn parse_prefix(&mut self) -> Result<Expr,String> {
+        let operator = match self.lexer.peek() {
+            Token::Keyword(KeyWords::NOT) => (UnaryOperator::NOT, NOT_PRECEDENCE),
+            Token::Keyword(KeyWords::MINUS) => (UnaryOperator::NEGATE, NEGATE_PRECEDENCE),
+            _ => return self.parse_primary(),
         };
-        if column_names.len() == 0 {
-            return Err(String::from("Columns not provided."));
+        self.lexer.consume();
+        let expr = self.parse_binary(operator.1)?;
+        Ok(Expr::Unary { operator: operator.0, expr: Box::new(expr) })
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr,String> {
+        match self.lexer.consume() {
+            Token::Integer(value) => Ok(Expr::Literal(Value::Int(value))),
+            Token::Float(value) => Ok(Expr::Literal(Value::Float(value))),
+            Token::String(value) => Ok(Expr::Literal(Value::String(value))),
+            Token::Keyword(KeyWords::NULL) => Ok(Expr::Null),
+            Token::Placeholder(number) => {
+                self.placeholders = self.placeholders.max(number);
+                Ok(Expr::Placeholder(number))
+            }
+            Token::Keyword(KeyWords::LEFTPAREN) => {
+                let expr = self.parse_expr()?;
+                match self.lexer.consume() {
+                    Token::Keyword(KeyWords::RIGHTPAREN) => Ok(expr),
+                    _ => Err(self.syntax_error("Expected ) to close expression", &[")"]))
+                }
+            }
+            Token::Identifier(name) if name == "*" => Ok(Expr::column("*")),
+            Token::Identifier(name) => match self.lexer.peek() {
+                Token::Keyword(KeyWords::LEFTPAREN) => {
+                    self.lexer.consume();
+                    let args = self.parse_expr_list()?;
+                    Ok(Expr::Function { name: name.to_uppercase(), args })
+                }
+                Token::Keyword(KeyWords::DOT) => {
+                    self.lexer.consume();
+                    match self.lexer.consume() {
+                        Token::Identifier(column) => Ok(Expr::Column { table: Some(name), name: column }),
+                        _ => Err(self.syntax_error("Expected column name", &["column name"]))
+                    }
+                }
+                _ => Ok(Expr::Column { table: None, name }),
+            },
+            _ => Err(self.syntax_error("Expected expression", &["value", "column", "("]))
         }
-        println!("Table name: {}, Columns: {:?}",table_name, column_names);
-        self.processor.handle_select_statement(&table_name, column_names)
     }
 
-    fn parse_insert(&mut self) -> Result<(),String> {
+    //Expressions up to the closing parenthesis, the opening one has already been consumed.
+    fn parse_expr_list(&mut self) -> Result<Vec<Expr>,String> {
+        let mut list = vec![];
+        if let Token::Keyword(KeyWords::RIGHTPAREN) = self.lexer.peek() {
+            self.lexer.consume();
+            return Ok(list);
+        }
+        loop {
+            list.push(self.parse_expr()?);
+            match self.lexer.consume() {
+                Token::Keyword(KeyWords::COMMA) => continue,
+                Token::Keyword(KeyWords::RIGHTPAREN) => break,
+                _ => return Err(self.syntax_error("Expected , or ) in list", &[",", ")"]))
+            }
+        }
+        Ok(list)
+    }
+
+    fn predicate_follows(&mut self) -> bool {
+        match self.lexer.peek() {
+            Token::Keyword(KeyWords::IS | KeyWords::MATCH | KeyWords::IN | KeyWords::BETWEEN | KeyWords::LIKE) => true,
+            Token::Keyword(KeyWords::NOT) => {
+                matches!(self.lexer.peek_nth(1), Token::Keyword(KeyWords::IN | KeyWords::BETWEEN | KeyWords::LIKE))
+            }
+            _ => false,
+        }
+    }
+
+    //`IS [NOT] NULL`, `[NOT] IN (...)`, `[NOT] BETWEEN a AND b`, `[NOT] LIKE p` or
+    //`MATCH terms` applied to `left`.
+    fn parse_predicate(&mut self, left: Expr) -> Result<Expr,String> {
+        if let Token::Keyword(KeyWords::IS) = self.lexer.peek() {
+            self.lexer.consume();
+            let negated = matches!(self.lexer.peek(), Token::Keyword(KeyWords::NOT));
+            if negated {
+                self.lexer.consume();
+            }
+            return match self.lexer.consume() {
+                Token::Keyword(KeyWords::NULL) => Ok(Expr::IsNull { expr: Box::new(left), negated }),
+                _ => Err(self.syntax_error("Expected NULL after IS", &["NULL", "NOT NULL"]))
+            };
+        }
+        if let Token::Keyword(KeyWords::MATCH) = self.lexer.peek() {
+            self.lexer.consume();
+            let Expr::Column { name: column, .. } = left else {
+                return Err(self.syntax_error("MATCH needs a column on its left", &[]));
+            };
+            return self.parse_text_match(column);
+        }
+        let negated = matches!(self.lexer.peek(), Token::Keyword(KeyWords::NOT));
+        if negated {
+            self.lexer.consume();
+        }
+        let expr = Box::new(left);
+        match self.lexer.consume() {
+            Token::Keyword(KeyWords::IN) => {
+                match self.lexer.consume() {
+                    Token::Keyword(KeyWords::LEFTPAREN) => {},
+                    _ => return Err(self.syntax_error("Expected ( after IN", &["("]))
+                }
+                let list = self.parse_expr_list()?;
+                Ok(Expr::InList { expr, list, negated })
+            }
+            Token::Keyword(KeyWords::BETWEEN) => {
+                let low = Box::new(self.parse_binary(COMPARISON_PRECEDENCE + 1)?);
+                match self.lexer.consume() {
+                    Token::Keyword(KeyWords::AND) => {},
+                    _ => return Err(self.syntax_error("Expected AND in BETWEEN", &["AND"]))
+                }
+                let high = Box::new(self.parse_binary(COMPARISON_PRECEDENCE + 1)?);
+                Ok(Expr::Between { expr, low, high, negated })
+            }
+            Token::Keyword(KeyWords::LIKE) => {
+                let pattern = Box::new(self.parse_binary(COMPARISON_PRECEDENCE + 1)?);
+                Ok(Expr::Like { expr, pattern, negated })
+            }
+            _ => Err(self.syntax_error("Expected IN, BETWEEN or LIKE", &["IN", "BETWEEN", "LIKE"])),
+        }
+    }
+
+    //The terms after `column MATCH`, every following word is part of the query.
+    fn parse_text_match(&mut self, column: String) -> Result<Expr,String> {
+        let mut terms = vec![];
+        while let Token::Identifier(_) | Token::String(_) | Token::Placeholder(_) = self.lexer.peek() {
+            terms.extend(self.consume_literal());
+        }
+        if terms.is_empty() {
+            return Err(self.syntax_error("Expected search terms after MATCH", &["search term"]));
+        }
+        Ok(Expr::Match { column, terms })
+    }
+
+    fn parse_insert(&mut self) -> Result<Statement,String> {
         let into = self.lexer.consume();
         match into {
             Token::Keyword(KeyWords::INTO) => {},
-            _ => return Err("Expected INTO keyword".to_string())
+            _ => return Err(self.syntax_error("Expected INTO keyword", &["INTO"]))
         }
         let table_name = match self.lexer.consume() {
             Token::Identifier(name) => name,
-            _ => return Err("Expected table name".to_string())
+            _ => return Err(self.syntax_error("Expected table name", &["table name"]))
         };
         let mut column_names = vec![];
         match self.lexer.consume() {
             Token::Keyword(KeyWords::LEFTPAREN)=>{},
-            _ => return Err("Expected ( for Columns specifier".to_string())
+            _ => return Err(self.syntax_error("Expected ( for Columns specifier", &["("]))
         };
         while let Token::Identifier(column_name) = self.lexer.consume() {
             column_names.push(column_name);
@@ -68,72 +273,307 @@ impl<'a> Parser<'a> {
             match next_token {
                 Token::Keyword(KeyWords::COMMA) => continue,
                 Token::Keyword(KeyWords::RIGHTPAREN) =>  break,
-                _ => return Err("Error parsing Select Statement".to_string())
+                _ => return Err(self.syntax_error("Expected , or ) in column list", &[",", ")"]))
             }
         }
         match self.lexer.consume() {
             Token::Keyword(KeyWords::VALUES)=>{},
-            _=> return Err("Expected Values for Inserting values".to_string())
+            _=> return Err(self.syntax_error("Expected Values for Inserting values", &["VALUES"]))
         }
         match self.lexer.consume() {
             Token::Keyword(KeyWords::LEFTPAREN)=>{},
-            _ => return Err("Expected ( for Columns specifier".to_string())
+            _ => return Err(self.syntax_error("Expected ( for Columns specifier", &["("]))
         };
         let mut column_values = vec![];
-        while let Token::Identifier(column_value) = self.lexer.consume() {
+        loop {
+            //A bare word stays a string value, as in VALUES (1, kowshick).
+            let column_value = match self.parse_expr()? {
+                Expr::Column { table: None, name } => Expr::Literal(Value::String(name)),
+                expr => expr,
+            };
             column_values.push(column_value);
             let next_token = self.lexer.consume();
             match next_token {
                 Token::Keyword(KeyWords::COMMA) => continue,
                 Token::Keyword(KeyWords::RIGHTPAREN) =>  break,
-                _ => return Err("Error parsing Select Statement".to_string())
+                _ => return Err(self.syntax_error("Expected , or ) in value list", &[",", ")"]))
             }
         }
         if column_names.len() != column_values.len() {
             return Err("Colums and values doesn't match".to_string());
         }
-        let colum_map:HashMap<String,String> = column_names.into_iter().zip(column_values.into_iter()).collect();
-        return self.processor.handle_insert_statement(&table_name, colum_map);
+        Ok(Statement::Insert { table: table_name, columns: column_names, values: column_values })
     }
 
-    fn parse_update(&mut self) -> Result<(),String> {
-        // To be implemented
-        Ok(())
+    fn parse_update(&mut self) -> Result<Statement,String> {
+        let table_name = match self.lexer.consume() {
+            Token::Identifier(name) => name,
+            _ => return Err(self.syntax_error("Expected table name", &["table name"]))
+        };
+        match self.lexer.consume() {
+            Token::Keyword(KeyWords::SET) => {},
+            _ => return Err(self.syntax_error("Expected SET keyword", &["SET"]))
+        }
+        let mut assignments: Vec<(String, Expr)> = vec![];
+        loop {
+            let column = match self.lexer.consume() {
+                Token::Identifier(name) => name,
+                _ => return Err(self.syntax_error("Expected column name", &["column name"]))
+            };
+            if assignments.iter().any(|(assigned, _)| *assigned == column) {
+                return Err(self.syntax_error(&format!("Column {} is assigned more than once", column), &[]));
+            }
+            match self.lexer.consume() {
+                Token::Keyword(KeyWords::EQUALS) => {},
+                _ => return Err(self.syntax_error("Expected = after column name", &["="]))
+            }
+            assignments.push((column, self.parse_expr()?));
+            match self.lexer.peek() {
+                Token::Keyword(KeyWords::COMMA) => {
+                    self.lexer.consume();
+                }
+                _ => break,
+            }
+        }
+        let mut filter = None;
+        if let Token::Keyword(KeyWords::WHERE) = self.lexer.peek() {
+            self.lexer.consume();
+            filter = Some(self.parse_expr()?);
+        }
+        Ok(Statement::Update { table: table_name, assignments, filter })
     }
-    fn parse_create(&mut self) -> Result<(),String> {
-        let table_keyword = self.lexer.consume();
-        println!("lexer {:?}",self.lexer);
-        match table_keyword {
-            Token::Keyword(KeyWords::TABLE)=>{},
-            _=> return Err("Expected TABLE keyword".to_string())
+    fn parse_delete(&mut self) -> Result<Statement,String> {
+        match self.lexer.consume() {
+            Token::Keyword(KeyWords::FROM) => {},
+            _ => return Err(self.syntax_error("Expected FROM keyword", &["FROM"]))
         }
         let table_name = match self.lexer.consume() {
             Token::Identifier(name) => name,
-            _ => return Err("Expected table name".to_string())
+            _ => return Err(self.syntax_error("Expected table name", &["table name"]))
+        };
+        let mut filter = None;
+        if let Token::Keyword(KeyWords::WHERE) = self.lexer.peek() {
+            self.lexer.consume();
+            filter = Some(self.parse_expr()?);
+        }
+        Ok(Statement::Delete { table: table_name, filter })
+    }
+    fn parse_drop(&mut self) -> Result<Statement,String> {
+        match self.lexer.consume() {
+            Token::Keyword(KeyWords::TABLE) => {},
+            _ => return Err(self.syntax_error("Expected TABLE keyword", &["TABLE"]))
+        }
+        let mut if_exists = false;
+        if let Token::Keyword(KeyWords::IF) = self.lexer.peek() {
+            self.lexer.consume();
+            match self.lexer.consume() {
+                Token::Keyword(KeyWords::EXISTS) => if_exists = true,
+                _ => return Err(self.syntax_error("Expected EXISTS after IF", &["EXISTS"]))
+            }
+        }
+        match self.lexer.consume() {
+            Token::Identifier(name) => Ok(Statement::DropTable { name, if_exists }),
+            _ => Err(self.syntax_error("Expected table name", &["table name"]))
+        }
+    }
+    fn parse_truncate(&mut self) -> Result<Statement,String> {
+        match self.lexer.consume() {
+            Token::Keyword(KeyWords::TABLE) => {},
+            _ => return Err(self.syntax_error("Expected TABLE keyword", &["TABLE"]))
+        }
+        match self.lexer.consume() {
+            Token::Identifier(name) => Ok(Statement::TruncateTable { name }),
+            _ => Err(self.syntax_error("Expected table name", &["table name"]))
+        }
+    }
+    fn parse_create(&mut self) -> Result<Statement,String> {
+        let keyword = self.lexer.consume();
+        match keyword {
+            Token::Keyword(KeyWords::TABLE)=>self.parse_create_table(),
+            Token::Keyword(KeyWords::INDEX)=>self.parse_create_index(IndexMethod::BTREE),
+            Token::Keyword(KeyWords::FULLTEXT)=>match self.lexer.consume() {
+                Token::Keyword(KeyWords::INDEX)=>self.parse_create_index(IndexMethod::FULLTEXT),
+                _=> Err(self.syntax_error("Expected INDEX keyword", &["INDEX"]))
+            },
+            _=> Err(self.syntax_error("Expected TABLE or INDEX keyword", &["TABLE", "INDEX", "FULLTEXT"]))
+        }
+    }
+
+    fn parse_create_index(&mut self, method: IndexMethod) -> Result<Statement,String> {
+        let index_name = match self.lexer.consume() {
+            Token::Identifier(name) => name,
+            _ => return Err(self.syntax_error("Expected index name", &["index name"]))
+        };
+        match self.lexer.consume() {
+            Token::Keyword(KeyWords::ON)=>{},
+            _ => return Err(self.syntax_error("Expected ON keyword", &["ON"]))
+        };
+        let table_name = match self.lexer.consume() {
+            Token::Identifier(name) => name,
+            _ => return Err(self.syntax_error("Expected table name", &["table name"]))
+        };
+        let mut next_token = self.lexer.consume();
+        let mut method = method;
+        if let Token::Keyword(KeyWords::USING) = next_token {
+            method = match self.lexer.consume() {
+                Token::Identifier(name) => match IndexMethod::from_name(&name) {
+                    Some(method) => method,
+                    None => return Err(self.syntax_error(&format!("Unsupported index method {}", name), &["BTREE", "HASH", "FULLTEXT"]))
+                },
+                _ => return Err(self.syntax_error("Expected index method after USING", &["BTREE", "HASH", "FULLTEXT"]))
+            };
+            next_token = self.lexer.consume();
+        }
+        match next_token {
+            Token::Keyword(KeyWords::LEFTPAREN)=>{},
+            _ => return Err(self.syntax_error("Expected ( for Columns specifier", &["("]))
+        };
+        let (columns, functions) = self.parse_index_keys()?;
+        let mut included = vec![];
+        if let Token::Keyword(KeyWords::INCLUDE) = self.lexer.peek() {
+            self.lexer.consume();
+            included = self.parse_column_list()?;
+        }
+        let mut predicate = None;
+        if let Token::Keyword(KeyWords::WHERE) = self.lexer.peek() {
+            self.lexer.consume();
+            let column = match self.lexer.consume() {
+                Token::Identifier(name) => name,
+                _ => return Err(self.syntax_error("Expected column name", &["column name"]))
+            };
+            match self.lexer.consume() {
+                Token::Keyword(KeyWords::EQUALS)=>{},
+                _ => return Err(self.syntax_error("Expected = in index predicate", &["="]))
+            };
+            let value = match self.consume_literal() {
+                Some(Expr::Literal(value)) => value,
+                Some(_) => return Err(self.syntax_error("Placeholders are not allowed in CREATE INDEX", &["value"])),
+                None => return Err(self.syntax_error("Expected value in index predicate", &["value"]))
+            };
+            predicate = Some((column, value));
+        }
+        let definition = IndexDefinition {
+            name: index_name,
+            columns,
+            functions,
+            included,
+            predicate,
+            method,
+        };
+        Ok(Statement::CreateIndex { table: table_name, definition })
+    }
+
+    //Index keys are columns, optionally wrapped in LOWER(...) or UPPER(...).
+    //The opening parenthesis has already been consumed.
+    fn parse_index_keys(&mut self) -> Result<(Vec<String>, Vec<Option<KeyFunction>>),String> {
+        let mut columns = vec![];
+        let mut functions = vec![];
+        loop {
+            let name = match self.lexer.consume() {
+                Token::Identifier(name) => name,
+                _ => return Err(self.syntax_error("Expected column name", &["column name"]))
+            };
+            let mut next_token = self.lexer.consume();
+            if let Token::Keyword(KeyWords::LEFTPAREN) = next_token {
+                let function = match KeyFunction::from_name(&name) {
+                    Some(function) => function,
+                    None => return Err(self.syntax_error(&format!("Unsupported index function {}", name), &[]))
+                };
+                match self.lexer.consume() {
+                    Token::Identifier(column) => columns.push(column),
+                    _ => return Err(self.syntax_error("Expected column name", &["column name"]))
+                };
+                match self.lexer.consume() {
+                    Token::Keyword(KeyWords::RIGHTPAREN)=>{},
+                    _ => return Err(self.syntax_error("Expected ) after function argument", &[")"]))
+                };
+                functions.push(Some(function));
+                next_token = self.lexer.consume();
+            } else {
+                columns.push(name);
+                functions.push(None);
+            }
+            match next_token {
+                Token::Keyword(KeyWords::COMMA) => continue,
+                Token::Keyword(KeyWords::RIGHTPAREN) =>  break,
+                _ => return Err(self.syntax_error("Error parsing Index Columns", &[",", ")"]))
+            }
+        }
+        Ok((columns, functions))
+    }
+
+    fn parse_column_list(&mut self) -> Result<Vec<String>,String> {
+        match self.lexer.consume() {
+            Token::Keyword(KeyWords::LEFTPAREN)=>{},
+            _ => return Err(self.syntax_error("Expected ( for Columns specifier", &["("]))
+        };
+        let mut column_names = vec![];
+        loop {
+            match self.lexer.consume() {
+                Token::Identifier(name) => column_names.push(name),
+                _ => return Err(self.syntax_error("Expected column name", &["column name"]))
+            };
+            match self.lexer.consume() {
+                Token::Keyword(KeyWords::COMMA) => continue,
+                Token::Keyword(KeyWords::RIGHTPAREN) =>  break,
+                _ => return Err(self.syntax_error("Error parsing Index Columns", &[",", ")"]))
+            }
+        }
+        Ok(column_names)
+    }
+
+    fn parse_create_table(&mut self) -> Result<Statement,String> {
+        let table_name = match self.lexer.consume() {
+            Token::Identifier(name) => name,
+            _ => return Err(self.syntax_error("Expected table name", &["table name"]))
         };
         match self.lexer.consume() {
             Token::Keyword(KeyWords::LEFTPAREN)=>{},
-            _ => return Err("Expected ( for Columns specifier".to_string())
+            _ => return Err(self.syntax_error("Expected ( for Columns specifier", &["("]))
         };
         let mut columns_meta:Vec<(String,DataType)> = vec![];
+        let mut primary_key: Option<String> = None;
         loop {
             let column_name = match self.lexer.consume() {
                 Token::Identifier(name) => name,
-                _ => return Err("Expected column name".to_string())
+                _ => return Err(self.syntax_error("Expected column name", &["column name"]))
             };
             let data_type = match self.lexer.consume() {
                 Token::Keyword(KeyWords::INT)=>DataType::INT,
+                Token::Keyword(KeyWords::FLOAT)=>DataType::FLOAT,
                 Token::Keyword(KeyWords::STRING)=>DataType::STRING,
-                _ => return Err("Expected data type".to_string())
+                _ => return Err(self.syntax_error("Expected data type", &["INT", "FLOAT", "STRING"]))
             };
+            let mut next_token = self.lexer.consume();
+            if let Token::Keyword(KeyWords::PRIMARY) = next_token {
+                match self.lexer.consume() {
+                    Token::Keyword(KeyWords::KEY)=>{},
+                    _ => return Err(self.syntax_error("Expected KEY after PRIMARY", &["KEY"]))
+                };
+                if primary_key.is_some() {
+                    return Err("Multiple primary keys defined".to_string());
+                }
+                primary_key = Some(column_name.clone());
+                next_token = self.lexer.consume();
+            }
             columns_meta.push((column_name, data_type));
-            let next_token = self.lexer.consume();
             match next_token {
                 Token::Keyword(KeyWords::COMMA) => continue,
                 Token::Keyword(KeyWords::RIGHTPAREN) =>  break,
-                _ => return Err("Error parsing Columns".to_string())
+                _ => return Err(self.syntax_error("Error parsing Columns", &[",", ")", "PRIMARY"]))
             }
         }
+        //Without a declared primary key a clustered table is ordered by its generated id.
+        let clustered = matches!(self.lexer.peek(), Token::Keyword(KeyWords::CLUSTERED));
+        if clustered {
+            self.lexer.consume();
+        }
+        let clustered_key = match (clustered, primary_key) {
+            (true, Some(primary_key)) => Some(primary_key),
+            (true, None) => Some("id".to_string()),
+            (_, Some(_)) => return Err("PRIMARY KEY is only supported on CLUSTERED tables".to_string()),
+            (_, None) => None,
+        };
         let mut columns = vec![];
         for (column_name, data_type) in columns_meta.iter(){
             let col = Column{
@@ -141,6 +581,7 @@ impl<'a> Parser<'a> {
                 data_type: data_type.clone(),
                 size: match data_type {
                     DataType::INT=>INT_SIZE,
+                    DataType::FLOAT=>FLOAT_SIZE,
                     DataType::STRING=>STRING_SIZE,
                     DataType::UUID=>ID_SIZE
                 },
@@ -148,127 +589,264 @@ impl<'a> Parser<'a> {
             };
             columns.push(col);
         }
-        self.processor.create_table(table_name, columns)?;
-        Ok(())
+        Ok(Statement::CreateTable { name: table_name, columns, clustered_key })
     }
 
-    fn parse_command(&mut self) -> Result<(),String> {
+    fn parse_command(&mut self) -> Result<Statement,String> {
         let token = self.lexer.consume();
         if let Token::Keyword(keyword) = token {
             return match keyword {
                 KeyWords::INSERT =>self.parse_insert(),
                 KeyWords::SELECT =>self.parse_select(),
                 KeyWords::UPDATE =>self.parse_update(),
+                KeyWords::DELETE =>self.parse_delete(),
+                KeyWords::DROP =>self.parse_drop(),
+                KeyWords::TRUNCATE =>self.parse_truncate(),
                 KeyWords::CREATE =>self.parse_create(),
-                _=>return Err("Unsupported command".to_string()),
+                _=>return Err(self.syntax_error("Unsupported command", &["SELECT", "INSERT", "UPDATE", "DELETE", "CREATE", "DROP", "TRUNCATE"])),
             };
         }
-        Err("Unexpected token".to_string())
+        Err(self.syntax_error("Unexpected token", &["SELECT", "INSERT", "UPDATE", "DELETE", "CREATE", "DROP", "TRUNCATE"]))
     }
 
-    pub fn parse(&mut self) -> Result<(),String> {
-        self.lexer.tokenize();
-        self.parse_command()
+    //The next `;` separated statement of the input, None once it is used up.
+    //A statement that fails to parse is skipped up to the next `;` so the ones after it can still be read.
+    pub fn next_statement(&mut self) -> Option<Result<Statement,String>> {
+        while let Token::Keyword(KeyWords::SEMICOLON) = self.lexer.peek() {
+            self.lexer.consume();
+        }
+        if let Token::EOL = self.lexer.peek() {
+            return self.lexer.error.take().map(|(message, span)| Err(self.lexer.render_error(&message, span, &[])));
+        }
+        let result = self.parse_command().and_then(|statement| match self.lexer.peek() {
+            Token::Keyword(KeyWords::SEMICOLON) | Token::EOL => Ok(statement),
+            _ => {
+                self.lexer.consume();
+                Err(self.syntax_error("Expected ; after statement", &[";"]))
+            }
+        });
+        if result.is_err() && !matches!(self.lexer.last_token(), Token::Keyword(KeyWords::SEMICOLON) | Token::EOL) {
+            while !matches!(self.lexer.peek(), Token::Keyword(KeyWords::SEMICOLON) | Token::EOL) {
+                self.lexer.consume();
+            }
+        }
+        let syntax_error = self.syntax_error.take();
+        //A lexing error ends the input, it is reported in place of whatever the parser made of the truncated statement.
+        Some(match self.lexer.error.take() {
+            Some((message, span)) => Err(self.lexer.render_error(&message, span, &[])),
+            None => result.map_err(|message| match syntax_error {
+                Some((span, expected)) => self.lexer.render_error(&message, span, &expected),
+                None => message,
+            }),
+        })
+    }
+
+    //Every statement of the input, one result per statement.
+    pub fn parse_script(&mut self) -> Vec<Result<Statement,String>> {
+        std::iter::from_fn(|| self.next_statement()).collect()
+    }
+
+    //The input as a single expression, such as a value typed for a placeholder.
+    pub fn parse_single_expr(&mut self) -> Result<Expr,String> {
+        let expr = self.parse_expr();
+        if let Some((message, _)) = self.lexer.error.take() {
+            return Err(message);
+        }
+        match self.lexer.peek() {
+            Token::EOL => expr,
+            _ => Err("Expected a single expression".to_string()),
+        }
+    }
+
+    //The input as a single statement.
+    pub fn parse(&mut self) -> Result<Statement,String> {
+        let statement = self.next_statement().unwrap_or_else(|| Err("Empty statement".to_string()))?;
+        match self.next_statement() {
+            None => Ok(statement),
+            Some(_) => Err("Expected a single statement".to_string()),
+        }
     }
 }
 
 
 #[cfg(test)]
 mod tests {
-    use std::mem::MaybeUninit;
-
     use super::*;
     
     #[test]
     fn test_insert_missing_into() {
         let sql = "INSERT users (a) VALUES (b)";
-        let mut lexer = Lexer::new(sql);
-        lexer.tokenize();
-        let mut dummy: MaybeUninit<Processor> = MaybeUninit::uninit();
-        let processor = unsafe { &mut *dummy.as_mut_ptr() };
-        let mut parser = Parser::new(lexer, processor);
+        let lexer = Lexer::new(sql);
+        let mut parser = Parser::new(lexer);
 
-        let res = parser.parse_insert();
+        let res = parser.parse_command();
         assert_eq!(res, Err("Expected INTO keyword".to_string()));
     }
 
     #[test]
     fn test_insert_missing_table_name() {
         let sql = "INSERT INTO (a) VALUES (b)";
-        let mut lexer = Lexer::new(sql);
-        lexer.tokenize();
-        let mut dummy: MaybeUninit<Processor> = MaybeUninit::uninit();
-        let processor = unsafe { &mut *dummy.as_mut_ptr() };
-        let mut parser = Parser::new(lexer, processor);
+        let lexer = Lexer::new(sql);
+        let mut parser = Parser::new(lexer);
 
-        let res = parser.parse_insert();
+        let res = parser.parse_command();
         assert_eq!(res, Err("Expected table name".to_string()));
     }
 
     #[test]
     fn test_insert_missing_columns_paren() {
         let sql = "INSERT INTO users a VALUES (b)";
-        let mut lexer = Lexer::new(sql);
-        lexer.tokenize();
-        let mut dummy: MaybeUninit<Processor> = MaybeUninit::uninit();
-        let processor = unsafe { &mut *dummy.as_mut_ptr() };
-        let mut parser = Parser::new(lexer, processor);
+        let lexer = Lexer::new(sql);
+        let mut parser = Parser::new(lexer);
 
-        let res = parser.parse_insert();
+        let res = parser.parse_command();
         assert_eq!(res, Err("Expected ( for Columns specifier".to_string()));
     }
 
     #[test]
     fn test_insert_invalid_columns_separator() {
         let sql = "INSERT INTO users (a b) VALUES (c d)";
-        let mut lexer = Lexer::new(sql);
-        lexer.tokenize();
-        let mut dummy: MaybeUninit<Processor> = MaybeUninit::uninit();
-        let processor = unsafe { &mut *dummy.as_mut_ptr() };
-        let mut parser = Parser::new(lexer, processor);
+        let lexer = Lexer::new(sql);
+        let mut parser = Parser::new(lexer);
 
-        let res = parser.parse_insert();
-        assert_eq!(res, Err("Error parsing Select Statement".to_string()));
+        let res = parser.parse_command();
+        assert_eq!(res, Err("Expected , or ) in column list".to_string()));
     }
 
     #[test]
     fn test_insert_missing_values_keyword() {
         let sql = "INSERT INTO users (a,b) VALUE (c,d)";
-        let mut lexer = Lexer::new(sql);
-        lexer.tokenize();
-        let mut dummy: MaybeUninit<Processor> = MaybeUninit::uninit();
-        let processor = unsafe { &mut *dummy.as_mut_ptr() };
-        let mut parser = Parser::new(lexer, processor);
+        let lexer = Lexer::new(sql);
+        let mut parser = Parser::new(lexer);
 
-        let res = parser.parse_insert();
+        let res = parser.parse_command();
         assert_eq!(res, Err("Expected Values for Inserting values".to_string()));
     }
 
     #[test]
     fn test_insert_missing_values_paren() {
         let sql = "INSERT INTO users (a,b) VALUES c,d";
-        let mut lexer = Lexer::new(sql);
-        lexer.tokenize();
-        let mut dummy: MaybeUninit<Processor> = MaybeUninit::uninit();
-        let processor = unsafe { &mut *dummy.as_mut_ptr() };
-        let mut parser = Parser::new(lexer, processor);
+        let lexer = Lexer::new(sql);
+        let mut parser = Parser::new(lexer);
 
-        let res = parser.parse_insert();
+        let res = parser.parse_command();
         assert_eq!(res, Err("Expected ( for Columns specifier".to_string()));
     }
 
     #[test]
     fn test_insert_mismatched_counts() {
         let sql = "INSERT INTO users (a,b) VALUES (c)";
-        let mut lexer = Lexer::new(sql);
-        lexer.tokenize();
-        let mut dummy: MaybeUninit<Processor> = MaybeUninit::uninit();
-        let processor = unsafe { &mut *dummy.as_mut_ptr() };
-        let mut parser = Parser::new(lexer, processor);
+        let lexer = Lexer::new(sql);
+        let mut parser = Parser::new(lexer);
 
-        let res = parser.parse_insert();
+        let res = parser.parse_command();
         assert_eq!(res, Err("Colums and values doesn't match".to_string()));
     }
-}
 
+    #[test]
+    fn test_parse_builds_statements() {
+        let parse = |sql: &str| Parser::new(Lexer::new(sql)).parse();
+        assert_eq!(
+            parse("INSERT INTO users (age, name) VALUES (4, 'Ann')"),
+            Ok(Statement::Insert {
+                table: "users".to_string(),
+                columns: vec!["age".to_string(), "name".to_string()],
+                values: vec![Expr::Literal(Value::Int(4)), Expr::Literal(Value::String("Ann".to_string()))],
+            })
+        );
+        assert_eq!(
+            parse("SELECT name, age FROM users WHERE bio MATCH rust ?;"),
+            Ok(Statement::Select {
+                table: "users".to_string(),
+                columns: vec![Expr::column("name"), Expr::column("age")],
+                filter: Some(Expr::Match {
+                    column: "bio".to_string(),
+                    terms: vec![Expr::Literal(Value::String("rust".to_string())), Expr::Placeholder(1)],
+                }),
+            })
+        );
+        let Ok(Statement::CreateTable { name, columns, clustered_key }) = parse("CREATE TABLE users (id INT PRIMARY KEY, name STRING) CLUSTERED") else {
+            panic!("Expected CREATE TABLE");
+        };
+        assert_eq!(name, "users");
+        assert_eq!(columns.iter().map(|column| column.name.as_str()).collect::<Vec<_>>(), vec!["id", "name"]);
+        assert_eq!(clustered_key, Some("id".to_string()));
+        assert_eq!(
+            parse("CREATE INDEX byname ON users USING HASH (name)"),
+            Ok(Statement::CreateIndex {
+                table: "users".to_string(),
+                definition: IndexDefinition {
+                    name: "byname".to_string(),
+                    columns: vec!["name".to_string()],
+                    functions: vec![None],
+                    method: IndexMethod::HASH,
+                    ..Default::default()
+                },
+            })
+        );
+        assert_eq!(
+            parse("UPDATE users SET age = age + 1, name = ? WHERE age < 18"),
+            Ok(Statement::Update {
+                table: "users".to_string(),
+                assignments: vec![
+                    (
+                        "age".to_string(),
+                        Expr::Binary {
+                            left: Box::new(Expr::column("age")),
+                            operator: BinaryOperator::PLUS,
+                            right: Box::new(Expr::Literal(Value::Int(1))),
+                        },
+                    ),
+                    ("name".to_string(), Expr::Placeholder(1)),
+                ],
+                filter: Some(Expr::Binary {
+                    left: Box::new(Expr::column("age")),
+                    operator: BinaryOperator::LESS,
+                    right: Box::new(Expr::Literal(Value::Int(18))),
+                }),
+            })
+        );
+        assert!(parse("UPDATE users SET age = 1, age = 2").unwrap_err().starts_with("Column age is assigned more than once"));
+        assert!(parse("UPDATE users age = 1").unwrap_err().starts_with("Expected SET keyword"));
+        assert_eq!(
+            parse("DELETE FROM users WHERE name IS NULL"),
+            Ok(Statement::Delete {
+                table: "users".to_string(),
+                filter: Some(Expr::IsNull { expr: Box::new(Expr::column("name")), negated: false }),
+            })
+        );
+        assert_eq!(parse("DELETE FROM users"), Ok(Statement::Delete { table: "users".to_string(), filter: None }));
+        assert_eq!(parse("DROP TABLE users"), Ok(Statement::DropTable { name: "users".to_string(), if_exists: false }));
+        assert_eq!(parse("DROP TABLE IF EXISTS users"), Ok(Statement::DropTable { name: "users".to_string(), if_exists: true }));
+        assert!(parse("DROP TABLE IF users").unwrap_err().starts_with("Expected EXISTS after IF"));
+        assert_eq!(parse("TRUNCATE TABLE users"), Ok(Statement::TruncateTable { name: "users".to_string() }));
+        assert_eq!(parse("SELECT a FROM t; SELECT b FROM t"), Err("Expected a single statement".to_string()));
+        assert_eq!(parse(" ; "), Err("Empty statement".to_string()));
+    }
+
+    #[test]
+    fn test_parse_script_recovers_after_errors() {
+        let mut parser = Parser::new(Lexer::new("INSERT INTO t (a) VALUES ($2); SELECT FROM; INSERT INTO t (a) VALUES ($1)"));
+        let results = parser.parse_script();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok() && results[2].is_ok());
+        assert!(results[1].as_ref().unwrap_err().starts_with("Expected expression at line 1, column 39"));
+        assert_eq!(parser.placeholder_count(), 2);
+    }
 
+    #[test]
+    fn test_expression_precedence() {
+        let expr = |sql: &str| Parser::new(Lexer::new(sql)).parse_expr().map(|expr| expr.to_string());
+        assert_eq!(expr("1 + 2 * 3 - 4"), Ok("(1 + (2 * 3)) - 4".to_string()));
+        assert_eq!(expr("a OR b AND NOT c = 1"), Ok("a OR (b AND (NOT (c = 1)))".to_string()));
+        assert_eq!(expr("-(a + 1) * 2 || 'x'"), Ok("(-(a + 1) * 2) || 'x'".to_string()));
+        assert_eq!(expr("a + 1 BETWEEN 2 AND 3 AND b"), Ok("((a + 1) BETWEEN 2 AND 3) AND b".to_string()));
+        assert_eq!(expr("name NOT LIKE 'A%' OR t.age NOT IN (1, ?, NULL)"), Ok("(name NOT LIKE 'A%') OR (t.age NOT IN (1, $1, NULL))".to_string()));
+        assert_eq!(expr("lower(name) IS NOT NULL"), Ok("LOWER(name) IS NOT NULL".to_string()));
+        assert_eq!(expr("bio MATCH rust 'web dev' AND x"), Ok("(bio MATCH 'rust' 'web dev') AND x".to_string()));
+        assert_eq!(expr("10 - 2 - 3"), Ok("(10 - 2) - 3".to_string()));
+        assert!(expr("(1 + 2").unwrap_err().starts_with("Expected ) to close expression"));
+        assert!(expr("a IS 3").unwrap_err().starts_with("Expected NULL after IS"));
+        assert!(expr("1 +").unwrap_err().starts_with("Expected expression"));
+    }
+}
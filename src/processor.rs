@@ -1,6 +1,8 @@
 use std::{collections::{HashMap, HashSet}, rc::Rc};
 
-use crate::table::{Column, DataBase};
+use crate::fulltext::TextMatch;
+use crate::index::IndexDefinition;
+use crate::table::{Column, DataBase, KeyRange, Value};
 
 pub struct Processor {
     pub database: Rc<DataBase>,
@@ -10,37 +12,142 @@ impl Processor {
     pub fn new(database:Rc<DataBase>)->Self {
         Processor { database }
     }
-    pub fn handle_select_statement(&mut self,table_name: &str, colums: HashSet<String>) -> Result<(),String>{
+    pub fn handle_select_statement(&mut self,table_name: &str, colums: HashSet<String>, text_match: Option<TextMatch>) -> Result<(),String>{
         let mut tables = self.database.tables.borrow_mut();
-        println!("{:?}",tables);
         let table = tables.get_mut(table_name);
-        println!("Selected Table: {}",table_name);
         match table {
-            Some(table) => table.borrow_mut().print_table(colums),
+            Some(table) => table.borrow_mut().print_table(colums, text_match)?,
             None=> return Err(String::from("Table not found"))
         }
         
         Ok(())
     }
 
-    pub fn handle_insert_statement(&mut self, table_name:&str, column_map:HashMap<String,String>)->Result<(),String>{
+    //Column names and every row of the table, for SELECT lists the executor evaluates itself.
+    pub fn scan_table(&mut self, table_name: &str) -> Result<(Vec<String>, Vec<Vec<Value>>), String> {
+        let tables = self.database.tables.borrow();
+        match tables.get(table_name) {
+            Some(table) => {
+                let mut table = table.borrow_mut();
+                Ok((table.column_names(), table.scan_rows()))
+            }
+            None => Err(String::from("Table not found"))
+        }
+    }
+
+    //Column names and the rows that may fall within `ranges`, for SELECT ... WHERE.
+    pub fn filter_table(&mut self, table_name: &str, ranges: &[KeyRange]) -> Result<(Vec<String>, Vec<Vec<Value>>), String> {
+        let tables = self.database.tables.borrow();
+        match tables.get(table_name) {
+            Some(table) => {
+                let mut table = table.borrow_mut();
+                Ok((table.column_names(), table.filter_rows(ranges)))
+            }
+            None => Err(String::from("Table not found"))
+        }
+    }
+
+    //Sets `columns` of the rows for which `values_for` returns values, given the column names and a row.
+    pub fn handle_update_statement(
+        &mut self,
+        table_name: &str,
+        ranges: &[KeyRange],
+        columns: &[String],
+        mut values_for: impl FnMut(&[String], &[Value]) -> Result<Option<Vec<Value>>, String>,
+    ) -> Result<usize, String> {
+        let tables = self.database.tables.borrow();
+        match tables.get(table_name) {
+            Some(table) => {
+                let mut table = table.borrow_mut();
+                let names = table.column_names();
+                table.update_rows(ranges, columns, |row| values_for(&names, row))
+            }
+            None => Err(String::from("Table not found"))
+        }
+    }
+
+    //Deletes the rows for which `matches` holds, given the column names and a row.
+    pub fn handle_delete_statement(
+        &mut self,
+        table_name: &str,
+        ranges: &[KeyRange],
+        mut matches: impl FnMut(&[String], &[Value]) -> Result<bool, String>,
+    ) -> Result<usize, String> {
+        let tables = self.database.tables.borrow();
+        match tables.get(table_name) {
+            Some(table) => {
+                let mut table = table.borrow_mut();
+                let names = table.column_names();
+                table.delete_rows(ranges, |row| matches(&names, row))
+            }
+            None => Err(String::from("Table not found"))
+        }
+    }
+
+    pub fn handle_insert_statement(&mut self, table_name:&str, column_map:HashMap<String,Value>)->Result<(),String>{
         let mut tables = self.database.tables.borrow_mut();
         let table = tables.get_mut(table_name);
         match table {
             Some(table)=> {
-                let row_vector = table.borrow_mut().match_columns(column_map);
+                let row_vector = table.borrow_mut().match_columns(column_map)?;
                 let row = &table.borrow_mut().construct_row(row_vector);
-                return match table.borrow_mut().insert_rows(vec![row]){
+                match table.borrow_mut().insert_rows(vec![row]){
                     Ok(_)=>Ok(()),
                     Err(e)=>Err(format!("Error inserting row: {}",e))
                 }
             } ,
-           None=> return Err(String::from("Table not found"))
+           None=> Err(String::from("Table not found"))
         }
     }
 
-    pub fn create_table(&mut self, table_name:String, columns:Vec<Column>)->Result<(),String>{
-        self.database.add_table(table_name, columns, 0, None, true);
-        Ok(())
+    pub fn create_table(&mut self, table_name:String, columns:Vec<Column>, clustered_key:Option<String>)->Result<(),String>{
+        self.database.add_table(table_name, columns, 0, None, clustered_key, true)
     }
-}
\ No newline at end of file
+
+    pub fn create_index(&mut self, table_name:&str, definition:IndexDefinition)->Result<(),String>{
+        let table = {
+            let tables = self.database.tables.borrow();
+            if tables.values().any(|table| table.borrow().indexes().iter().any(|index| index.name == definition.name)) {
+                return Err(format!("Index {} already exists", definition.name));
+            }
+            match tables.get(table_name) {
+                Some(table) => Rc::clone(table),
+                None => return Err(String::from("Table not found"))
+            }
+        };
+        let index_name = definition.name.clone();
+        table.borrow_mut().create_index(definition)?;
+        //An index the catalog has no room for is dropped again.
+        self.database.flush().inspect_err(|_| table.borrow_mut().drop_index(&index_name))
+    }
+
+    pub fn drop_table(&mut self, table_name:&str, if_exists:bool)->Result<(),String>{
+        //IF EXISTS only skips a missing table, errors while dropping one are still reported.
+        if if_exists && !self.database.tables.borrow().contains_key(table_name) {
+            return Ok(());
+        }
+        self.database.drop_table(table_name)
+    }
+
+    pub fn truncate_table(&mut self, table_name:&str)->Result<(),String>{
+        {
+            let tables = self.database.tables.borrow();
+            match tables.get(table_name) {
+                Some(table) => table.borrow_mut().truncate(),
+                None => return Err(String::from("Table not found"))
+            }
+        }
+        self.database.flush()
+    }
+
+    pub fn dump_index(&self, index_name:&str)->Result<String,String>{
+        let tables = self.database.tables.borrow();
+        for table in tables.values() {
+            let table = table.borrow();
+            if let Some(index) = table.indexes().iter().find(|index| index.name == index_name) {
+                return index.dump_tree();
+            }
+        }
+        Err(format!("Index {} not found", index_name))
+    }
+}
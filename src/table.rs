@@ -1,31 +1,38 @@
 use pretty_table::prelude::*;
 use std::{
     cell::RefCell,
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet},
     error::Error,
-    fs::{self, OpenOptions},
-    io::{self, Read, Seek, Write},
+    fmt,
+    ops::Bound,
     path::Path,
     rc::{Rc, Weak},
 };
 use uuid::Uuid;
 
+use crate::fulltext::TextMatch;
+use crate::index::{Index, IndexDefinition, IndexMethod, KeyFunction, Predicate, read_value, write_value};
+use crate::key::EncodedKey;
+use crate::pager::{CATALOG_SIZE, Pager};
+use crate::zonemap::ZoneMap;
+
 pub const PAGE_SIZE: usize = 4096;
 pub const TABLE_MAX_PAGES: usize = 100;
 
 pub const ID_SIZE: usize = std::mem::size_of::<Uuid>();
-pub const INT_SIZE: usize = std::mem::size_of::<u32>();
+pub const INT_SIZE: usize = std::mem::size_of::<i64>();
+pub const FLOAT_SIZE: usize = std::mem::size_of::<f64>();
 pub const STRING_SIZE: usize = 200;
 
-const INT_DEFAULT: &str = "0";
+const INT_DEFAULT: Value = Value::Int(0);
+const FLOAT_DEFAULT: Value = Value::Float(0.0);
 const STRING_DEFAULT: &str = "";
 
-const META_SIZE: usize = 4096;
 
 //pages should be allocated from eof.
 //all pages must have pointer to next page.
 
-/*32+2+1+8+(32+1)*Number of columns */
+/*32+2+1+8+(32+1)*Number of columns, the 8 byte location is the table's directory page */
 const TABLE_NAME_SIZE: usize = 32;
 const TOTAL_COLUMNS_INFO_SIZE: usize = 1;
 const TOTAL_ROWS_SIZE:usize = 2;
@@ -33,21 +40,38 @@ const TABLE_DATA_LOCATION_SIZE: usize = 8;
 const COLUMN_NAME_SIZE: usize = 32;
 //0 for ID, 1 for INT, 2 for STRING
 const COLUMN_TYPE_META: usize = 1;
+//Set on the column type of the primary key of a clustered table.
+const PRIMARY_KEY_FLAG: u8 = 0x80;
+
+/*2 byte page count, then a 4 byte page number, 2 byte row slot count and 2 byte deleted row count per page */
+const DIRECTORY_COUNT_SIZE: usize = 2;
+const DIRECTORY_ENTRY_SIZE: usize = 8;
+
+/*32+32+1+2*Number of columns+1+1*Number of included columns+predicate+1 method byte+4 root page, stored after all tables */
+const INDEX_NAME_SIZE: usize = 32;
+const TOTAL_INDEX_COLUMNS_SIZE: usize = 1;
+//column position followed by 0 for a plain column, 1 for LOWER, 2 for UPPER
+const INDEX_COLUMN_META: usize = 2;
+//0 for indexes rebuilt from the rows when the database is opened
+const INDEX_ROOT_SIZE: usize = 4;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum DataType {
     INT,
+    FLOAT,
     STRING,
     UUID,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum ColumnType {
     ID,
     FIELD,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Column {
     pub name: String,
     pub size: usize,
@@ -66,6 +90,91 @@ impl Column {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    String(String),
+    Uuid(Uuid),
+}
+
+//Converts a literal to the type of the column it is compared with or stored in.
+pub fn coerce(value: Value, data_type: &DataType) -> Result<Value, String> {
+    match (value, data_type) {
+        (Value::String(value), DataType::INT) => value
+            .trim()
+            .parse()
+            .map(Value::Int)
+            .map_err(|_| format!("Invalid INT value {}", value)),
+        (Value::String(value), DataType::FLOAT) => value
+            .trim()
+            .parse()
+            .map(Value::Float)
+            .map_err(|_| format!("Invalid FLOAT value {}", value)),
+        (Value::Int(value), DataType::FLOAT) => Ok(Value::Float(value as f64)),
+        (Value::Float(value), DataType::INT) if value.fract() == 0.0 && value.abs() < i64::MAX as f64 => {
+            Ok(Value::Int(value as i64))
+        }
+        (Value::Float(value), DataType::INT) => Err(format!("Invalid INT value {}", value)),
+        (Value::Int(value), DataType::STRING) => Ok(Value::String(value.to_string())),
+        (Value::Float(value), DataType::STRING) => Ok(Value::String(value.to_string())),
+        (value, _) => Ok(value),
+    }
+}
+
+pub fn print_rows(header: Vec<String>, rows: &[Vec<Value>]) {
+    let mut row_values: Vec<Vec<String>> = vec![header];
+    for row in rows.iter() {
+        row_values.push(row.iter().map(|value| value.to_string()).collect());
+    }
+    print_table!(row_values.clone());
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Uuid(value) => write!(f, "{}", value.hyphenated()),
+        }
+    }
+}
+
+fn decode_field(data_type: &DataType, field: &[u8]) -> Value {
+    let trim_len = field
+        .iter()
+        .rposition(|&b| b != 0)
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let bytes = &field[..trim_len];
+    match data_type {
+        DataType::UUID => Value::Uuid(Uuid::from_slice(field).unwrap_or_default()),
+        DataType::STRING => Value::String(String::from_utf8_lossy(bytes).to_string()),
+        DataType::INT => Value::Int(i64::from_le_bytes(field[..INT_SIZE].try_into().unwrap())),
+        DataType::FLOAT => Value::Float(f64::from_le_bytes(field[..FLOAT_SIZE].try_into().unwrap())),
+    }
+}
+
+//Fixed size on-disk form of a value already coerced to the column's type.
+fn encode_field(value: &Value, size: usize) -> Vec<u8> {
+    let mut buffer = vec![0; size];
+    let bytes = match value {
+        Value::Int(value) => value.to_le_bytes().to_vec(),
+        Value::Float(value) => value.to_le_bytes().to_vec(),
+        Value::String(value) => value.as_bytes().to_vec(),
+        Value::Uuid(value) => value.as_bytes().to_vec(),
+    };
+    let len = bytes.len().min(size);
+    buffer[0..len].copy_from_slice(&bytes[0..len]);
+    buffer
+}
+
+//A deleted row keeps its slot with every byte zeroed, live rows never have a nil id.
+fn is_deleted(row: &[Value]) -> bool {
+    row.first() == Some(&Value::Uuid(Uuid::nil()))
+}
+
 #[derive(Debug)]
 pub struct Page {
     data: [u8; PAGE_SIZE],
@@ -100,11 +209,57 @@ impl Page {
         Ok(())
     }
 
-    // pub fn update_row(&mut self, row_number: usize, data: &[u8]) -> Result<(), Box<dyn Error>> {
-    //     let start = row_number * self.row_size;
-    //     self.data[start..start + self.row_size].copy_from_slice(data);
-    //     Ok(())
-    // }
+    pub fn update_row(&mut self, row_number: usize, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let start = row_number * self.row_size;
+        self.data[start..start + self.row_size].copy_from_slice(data);
+        Ok(())
+    }
+
+    //Writes `data` at `row_number`, the rows from there on move down one slot.
+    pub fn insert_row(&mut self, row_number: usize, data: &[u8]) {
+        let start = row_number * self.row_size;
+        let end = self.current_row * self.row_size;
+        self.data.copy_within(start..end, start + self.row_size);
+        self.data[start..start + self.row_size].copy_from_slice(data);
+        self.current_row += 1;
+    }
+
+    //Removes the row at `row_number`, the rows after it move up one slot.
+    pub fn remove_row(&mut self, row_number: usize) {
+        let start = row_number * self.row_size;
+        let end = self.current_row * self.row_size;
+        self.data.copy_within(start + self.row_size..end, start);
+        self.data[end - self.row_size..end].fill(0);
+        self.current_row -= 1;
+    }
+
+    //Removes the rows from `row_number` on and returns them.
+    pub fn split_off(&mut self, row_number: usize) -> Vec<Vec<u8>> {
+        let rows = (row_number..self.current_row).map(|row| self.read_row(row).to_vec()).collect();
+        self.data[row_number * self.row_size..].fill(0);
+        self.current_row = row_number;
+        rows
+    }
+}
+
+//A WHERE conjunct bounding `function(column)` by constants, which lets a scan
+//go through the clustered key, an index or the zone maps instead of every row.
+//Equality has both bounds included and equal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyRange {
+    pub column: String,
+    pub function: Option<KeyFunction>,
+    pub lower: Bound<Value>,
+    pub upper: Bound<Value>,
+}
+
+impl KeyRange {
+    fn equal_value(&self) -> Option<&Value> {
+        match (&self.lower, &self.upper) {
+            (Bound::Included(lower), Bound::Included(upper)) if lower == upper => Some(lower),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -112,9 +267,52 @@ pub struct Table {
     table_name: String,
     pages: Vec<Option<Page>>,
     columns: Vec<Column>,
+    //Live rows, `row_slots` also counts the slots of deleted rows.
     total_rows: usize,
-    start_offset: usize,
+    row_slots: usize,
+    free_slots: Vec<usize>,
+    //Pages with deleted rows whose slots aren't in `free_slots` yet, they are read once an insert needs a slot.
+    pages_with_deleted_rows: BTreeSet<usize>,
+    //Page `n` of the table is stored at `page_entries[n]`, the list is kept in the directory page.
+    directory_page: usize,
+    page_entries: Vec<PageEntry>,
+    directory_dirty: bool,
     data_base: Weak<DataBase>,
+    indexes: Vec<Index>,
+    clustered: Option<Clustered>,
+    //One summary per page, None until a scan first needs it.
+    zone_maps: Vec<Option<ZoneMap>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PageEntry {
+    page_id: usize,
+    rows: usize,
+    //Slots of DELETEd rows, always 0 for clustered tables.
+    deleted: usize,
+}
+
+//Rows of a clustered table are stored in primary key order. Every page holds a sorted
+//run of rows and may have free slots at its end, a full page splits in two when a row
+//has to go on it and a page is freed once its last row is deleted. The first key of
+//every page is kept in memory to find the page a key belongs on.
+#[derive(Debug)]
+struct Clustered {
+    key_position: usize,
+    first_keys: Vec<EncodedKey>,
+}
+
+impl Clustered {
+    fn key_for(&self, row: &[Value]) -> EncodedKey {
+        let mut key = EncodedKey::new();
+        key.push_value(&row[self.key_position]);
+        key
+    }
+
+    //The last page starting at or before `key`, the first page for keys before every row.
+    fn page_for(&self, key: &EncodedKey) -> usize {
+        self.first_keys.partition_point(|first| first <= key).saturating_sub(1)
+    }
 }
 
 //TODO fetch FIRST PAGE ON TABLE CREATION.
@@ -124,19 +322,14 @@ impl Table {
         columns: Vec<Column>,
         data_base: Weak<DataBase>,
         total_rows: usize,
-        start_offset: usize,
+        directory_page: Option<usize>,
+        clustered_key: Option<String>,
     ) -> Self {
 
-        let mut hasIdColumn = false;
-        for column in columns.iter(){
-            match column.col_type {
-                ColumnType::ID => hasIdColumn = true,
-                _=>{}
-            }
-        }
+        let has_id_column = columns.iter().any(|column| column.col_type == ColumnType::ID);
 
         let mut columns = columns;
-        if !hasIdColumn {
+        if !has_id_column {
             columns.insert(0, Column::new("id".to_string(), ID_SIZE, ColumnType::ID, DataType::UUID));
         }
         let mut table = Table {
@@ -144,29 +337,89 @@ impl Table {
             pages: vec![],
             columns,
             total_rows,
-            start_offset,
+            row_slots: total_rows,
+            free_slots: vec![],
+            pages_with_deleted_rows: BTreeSet::new(),
+            directory_page: 0,
+            page_entries: vec![],
+            directory_dirty: false,
             data_base,
+            indexes: vec![],
+            clustered: None,
+            zone_maps: vec![],
         };
 
-        if total_rows > 0 {
+        match directory_page {
+            Some(page) => table.load_directory(page),
+            None => {
+                table.directory_page = table.pager().borrow_mut().allocate();
+                table.directory_dirty = true;
+            }
+        }
+        if let Some(key_name) = clustered_key {
+            let key_position = table
+                .columns
+                .iter()
+                .position(|c| c.name == key_name)
+                .expect("Primary key column not found");
+            table.load_clustered(key_position);
+        } else if total_rows > 0 {
             table.get_page(0);
+            table.load_deleted_counts();
         }
+        table.zone_maps.resize_with(table.page_entries.len(), || None);
 
-        // let file_size: usize = table
-        //     .data_base
-        //     .upgrade()
-        //     .unwrap()
-        //     .file_handle
-        //     .borrow()
-        //     .metadata()
-        //     .unwrap()
-        //     .len() as usize
-        //     - META_SIZE;
-        // let row_size = table.row_size();
-        // table.total_rows = file_size / row_size;
         table
     }
 
+    fn pager(&self) -> Rc<RefCell<Pager>> {
+        Rc::clone(&self.data_base.upgrade().unwrap().pager)
+    }
+
+    fn load_directory(&mut self, directory_page: usize) {
+        let data = self.pager().borrow_mut().read(directory_page);
+        let count = u16::from_le_bytes(data[0..DIRECTORY_COUNT_SIZE].try_into().unwrap()) as usize;
+        self.directory_page = directory_page;
+        self.page_entries = (0..count)
+            .map(|n| {
+                let offset = DIRECTORY_COUNT_SIZE + n * DIRECTORY_ENTRY_SIZE;
+                PageEntry {
+                    page_id: u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as usize,
+                    rows: u16::from_le_bytes(data[offset + 4..offset + 6].try_into().unwrap()) as usize,
+                    deleted: u16::from_le_bytes(data[offset + 6..offset + 8].try_into().unwrap()) as usize,
+                }
+            })
+            .collect();
+    }
+
+    //Writes the directory page if pages were added, freed or changed their row count since the last flush.
+    pub fn flush_directory(&mut self) {
+        if !self.directory_dirty {
+            return;
+        }
+        let mut data = vec![0; PAGE_SIZE];
+        data[0..DIRECTORY_COUNT_SIZE].copy_from_slice(&(self.page_entries.len() as u16).to_le_bytes());
+        for (n, entry) in self.page_entries.iter().enumerate() {
+            let offset = DIRECTORY_COUNT_SIZE + n * DIRECTORY_ENTRY_SIZE;
+            data[offset..offset + 4].copy_from_slice(&(entry.page_id as u32).to_le_bytes());
+            data[offset + 4..offset + 6].copy_from_slice(&(entry.rows as u16).to_le_bytes());
+            data[offset + 6..offset + 8].copy_from_slice(&(entry.deleted as u16).to_le_bytes());
+        }
+        self.pager().borrow_mut().write(self.directory_page, &data);
+        self.directory_dirty = false;
+    }
+
+    //Frees every page of the table and its indexes, the directory page included, for a DROP TABLE.
+    pub fn release(&mut self) {
+        let pages: Vec<usize> = self.page_entries.iter().map(|entry| entry.page_id).collect();
+        self.pager().borrow_mut().free(pages.into_iter().chain([self.directory_page]));
+        self.page_entries.clear();
+        self.pages.clear();
+        for index in self.indexes.iter_mut() {
+            index.release();
+        }
+    }
+
     fn row_size(&self) -> usize {
         let mut size: usize = 0;
         for column in self.columns.iter() {
@@ -175,187 +428,863 @@ impl Table {
         size
     }
 
+    //Page `page_number` of the table, a page one past the last is allocated from the pager.
     fn get_page(&mut self, page_number: usize) -> &mut Page {
-        if self.pages.len() >= TABLE_MAX_PAGES {
-            panic!("Table full.");
+        if page_number == self.page_entries.len() {
+            self.insert_page(page_number);
         }
-
         if self.pages.len() <= page_number {
             self.pages.resize_with(page_number + 1, || None);
         }
         if self.pages[page_number].is_none() {
             let row_size = self.row_size();
             self.pages[page_number] = Some(Page::new(row_size));
-            if self.total_rows > page_number * (PAGE_SIZE / self.row_size()) {
-                self.fill_page_from_disk(page_number);
-            }
+            self.fill_page_from_disk(page_number);
         }
         self.pages[page_number].as_mut().unwrap()
     }
 
     fn fill_page_from_disk(&mut self, page_number: usize) {
-        let data_base = self.data_base.upgrade().unwrap();
-        let mut file_handle = data_base.file_handle.borrow_mut();
-        file_handle
-            .seek(io::SeekFrom::Start(
-                ((page_number * PAGE_SIZE) + self.start_offset) as u64,
-            ))
-            .unwrap();
+        let entry = self.page_entries[page_number];
+        let data = self.pager().borrow_mut().read(entry.page_id);
         let page = self.pages[page_number].as_mut().unwrap();
-        let bytes_read = file_handle
-            .read(&mut page.data)
-            .expect("Failed to read page from disk");
-        page.current_row = bytes_read / page.row_size;
+        page.data.copy_from_slice(&data);
+        page.current_row = entry.rows;
+    }
+
+    //Allocates an empty page at `page_number`, the pages from there on move up one place.
+    fn insert_page(&mut self, page_number: usize) {
+        if self.page_entries.len() >= TABLE_MAX_PAGES {
+            panic!("Table full.");
+        }
+        let page_id = self.pager().borrow_mut().allocate();
+        self.page_entries.insert(page_number, PageEntry { page_id, rows: 0, deleted: 0 });
+        self.directory_dirty = true;
+        if self.pages.len() < page_number {
+            self.pages.resize_with(page_number, || None);
+        }
+        let row_size = self.row_size();
+        self.pages.insert(page_number, Some(Page::new(row_size)));
+        let zone_map = self.new_zone_map();
+        self.zone_maps.insert(page_number, Some(zone_map));
+    }
+
+    //Frees the page at `page_number`, the pages after it move down one place.
+    fn remove_page(&mut self, page_number: usize) {
+        let entry = self.page_entries.remove(page_number);
+        self.pager().borrow_mut().free([entry.page_id]);
+        self.directory_dirty = true;
+        if page_number < self.pages.len() {
+            self.pages.remove(page_number);
+        }
+        self.zone_maps.remove(page_number);
+        if let Some(clustered) = self.clustered.as_mut() {
+            clustered.first_keys.remove(page_number);
+        }
+    }
+
+    //Row numbers of every used slot in page order, the rows of page `n` start at `n` times the rows per page.
+    fn row_numbers(&self) -> Vec<usize> {
+        let rows_per_page = PAGE_SIZE / self.row_size();
+        self.page_entries
+            .iter()
+            .enumerate()
+            .flat_map(|(page, entry)| page * rows_per_page..page * rows_per_page + entry.rows)
+            .collect()
+    }
+
+    fn load_clustered(&mut self, key_position: usize) {
+        let mut clustered = Clustered {
+            key_position,
+            first_keys: vec![],
+        };
+        let rows_per_page = PAGE_SIZE / self.row_size();
+        for page_number in 0..self.page_entries.len() {
+            let row = self.read_row_values(page_number * rows_per_page);
+            clustered.first_keys.push(clustered.key_for(&row));
+        }
+        self.clustered = Some(clustered);
+    }
+
+    //Takes the deleted rows out of the row count from the directory, their slots are only
+    //looked up once an insert needs one.
+    fn load_deleted_counts(&mut self) {
+        for (page_number, entry) in self.page_entries.iter().enumerate() {
+            if entry.deleted > 0 {
+                self.pages_with_deleted_rows.insert(page_number);
+                self.total_rows -= entry.deleted;
+            }
+        }
+    }
+
+    //A slot freed by DELETE, the most recently freed one first. Pages with rows deleted
+    //before the table was opened are read lowest first when no other slot is left.
+    fn take_free_slot(&mut self) -> Option<usize> {
+        let rows_per_page = PAGE_SIZE / self.row_size();
+        while self.free_slots.is_empty() {
+            let page_number = self.pages_with_deleted_rows.pop_first()?;
+            let first_row = page_number * rows_per_page;
+            for row_number in (first_row..first_row + self.page_entries[page_number].rows).rev() {
+                if is_deleted(&self.read_row_values(row_number)) {
+                    self.free_slots.push(row_number);
+                }
+            }
+        }
+        let slot = self.free_slots.pop()?;
+        self.page_entries[slot / rows_per_page].deleted -= 1;
+        self.directory_dirty = true;
+        Some(slot)
+    }
+
+    pub fn is_clustered(&self) -> bool {
+        self.clustered.is_some()
+    }
+
+    //Primary keys of the rows on a page of a clustered table, in order.
+    fn page_keys(&mut self, page_number: usize) -> Vec<EncodedKey> {
+        let rows_per_page = PAGE_SIZE / self.row_size();
+        (0..self.page_entries[page_number].rows)
+            .map(|slot| {
+                let row = self.read_row_values(page_number * rows_per_page + slot);
+                self.clustered.as_ref().unwrap().key_for(&row)
+            })
+            .collect()
+    }
+
+    //Row number of the row with `key` in a clustered table.
+    fn find_clustered_row(&mut self, key: &EncodedKey) -> Option<usize> {
+        let page_number = self.clustered.as_ref()?.page_for(key);
+        if page_number >= self.page_entries.len() {
+            return None;
+        }
+        let slot = self.page_keys(page_number).binary_search(key).ok()?;
+        Some(page_number * (PAGE_SIZE / self.row_size()) + slot)
+    }
+
+    //Puts the row on its page in key order, only the rows after it on that page move.
+    //A full page is split first: an insert past its last row starts a new page,
+    //anywhere else the upper half of the rows moves to the new page.
+    fn insert_clustered(&mut self, value: &[u8]) -> Result<(), Box<dyn Error>> {
+        let row_values = self.decode_row(value);
+        let clustered = self.clustered.as_ref().unwrap();
+        let key = clustered.key_for(&row_values);
+        let key_position = clustered.key_position;
+        let mut page_number = clustered.page_for(&key);
+        if self.page_entries.is_empty() {
+            self.insert_page(0);
+            self.clustered.as_mut().unwrap().first_keys.push(key.clone());
+        }
+        let mut slot = match self.page_keys(page_number).binary_search(&key) {
+            Ok(_) => return Err(format!("Duplicate primary key {:?}", row_values[key_position]).into()),
+            Err(slot) => slot,
+        };
+        let rows_per_page = PAGE_SIZE / self.row_size();
+        if self.page_entries[page_number].rows == rows_per_page {
+            if slot == rows_per_page {
+                self.split_page(page_number, rows_per_page);
+                page_number += 1;
+                slot = 0;
+            } else {
+                let keep = rows_per_page / 2;
+                self.split_page(page_number, keep);
+                if slot > keep {
+                    page_number += 1;
+                    slot -= keep;
+                }
+            }
+        }
+        self.get_page(page_number).insert_row(slot, value);
+        self.flush_page_to_disk(page_number);
+        if slot == 0 {
+            self.clustered.as_mut().unwrap().first_keys[page_number] = key;
+        }
+        self.add_to_zone_map(page_number, &row_values);
+        self.total_rows += 1;
+        self.row_slots += 1;
+        Ok(())
+    }
+
+    //Moves the rows of a clustered table's page from slot `keep` on to a new page right after it.
+    fn split_page(&mut self, page_number: usize, keep: usize) {
+        let moved = self.get_page(page_number).split_off(keep);
+        self.flush_page_to_disk(page_number);
+        self.insert_page(page_number + 1);
+        let new_page = self.get_page(page_number + 1);
+        for row in moved.iter() {
+            new_page.write_row(row).unwrap();
+        }
+        self.flush_page_to_disk(page_number + 1);
+        let clustered = self.clustered.as_ref().unwrap();
+        //A page that stays empty gets its first key from the row about to be inserted.
+        let first_key = match moved.first() {
+            Some(row) => clustered.key_for(&self.decode_row(row)),
+            None => clustered.first_keys[page_number].clone(),
+        };
+        self.clustered.as_mut().unwrap().first_keys.insert(page_number + 1, first_key);
+        //The old page's summary still covers the rows it kept, the new page's is built when a scan needs it.
+        self.zone_maps[page_number + 1] = None;
+    }
+
+    //Removes a row of a clustered table, the rows after it on its page move up one slot.
+    //A page left without rows is freed.
+    fn remove_clustered_row(&mut self, row_number: usize) {
+        let rows_per_page = PAGE_SIZE / self.row_size();
+        let (page_number, slot) = (row_number / rows_per_page, row_number % rows_per_page);
+        let page = self.get_page(page_number);
+        page.remove_row(slot);
+        let empty = page.current_row == 0;
+        self.total_rows -= 1;
+        self.row_slots -= 1;
+        if empty {
+            self.remove_page(page_number);
+            return;
+        }
+        self.flush_page_to_disk(page_number);
+        if slot == 0 {
+            let first_key = self.page_keys(page_number).swap_remove(0);
+            self.clustered.as_mut().unwrap().first_keys[page_number] = first_key;
+        }
+    }
+
+    fn new_zone_map(&self) -> ZoneMap {
+        let data_types: Vec<DataType> = self.columns.iter().map(|c| c.data_type.clone()).collect();
+        ZoneMap::new(&data_types)
+    }
+
+    //Summary of the rows on one page.
+    fn page_zone_map(&mut self, page_number: usize) -> ZoneMap {
+        let rows_per_page = PAGE_SIZE / self.row_size();
+        let mut zone_map = self.new_zone_map();
+        for slot in 0..self.page_entries[page_number].rows {
+            let row = self.read_row_values(page_number * rows_per_page + slot);
+            if !is_deleted(&row) {
+                zone_map.add_row(&row);
+            }
+        }
+        zone_map
+    }
+
+    //Adds a row to its page's summary, a summary that isn't built yet will see it when it is.
+    fn add_to_zone_map(&mut self, page_number: usize, row: &[Value]) {
+        if let Some(zone_map) = &mut self.zone_maps[page_number] {
+            zone_map.add_row(row);
+        }
+    }
+
+    //Pages whose zone map may hold a matching row, the rest never need to be read again.
+    //A page's summary is built the first time a scan asks for it.
+    pub fn pages_to_scan(&mut self, may_match: impl Fn(&ZoneMap) -> bool) -> Vec<usize> {
+        let mut pages = vec![];
+        for page_number in 0..self.zone_maps.len() {
+            if self.zone_maps[page_number].is_none() {
+                self.zone_maps[page_number] = Some(self.page_zone_map(page_number));
+            }
+            if may_match(self.zone_maps[page_number].as_ref().unwrap()) {
+                pages.push(page_number);
+            }
+        }
+        pages
+    }
+
+    //Row numbers and values of the rows on pages that may match.
+    //Rows still have to be checked against the predicate itself.
+    pub fn scan_pages(&mut self, may_match: impl Fn(&ZoneMap) -> bool) -> Vec<(usize, Vec<Value>)> {
+        let rows_per_page = PAGE_SIZE / self.row_size();
+        let mut rows = vec![];
+        for page in self.pages_to_scan(may_match) {
+            let first_row = page * rows_per_page;
+            for row_number in first_row..first_row + self.page_entries[page].rows {
+                let row = self.read_row_values(row_number);
+                if !is_deleted(&row) {
+                    rows.push((row_number, row));
+                }
+            }
+        }
+        rows
+    }
+
+    //Row numbers and values of the rows of a clustered table whose primary key lies within
+    //the bounds, in key order. The scan starts on the page holding the lower bound.
+    pub fn scan_primary_key(&mut self, lower: Bound<&Value>, upper: Bound<&Value>) -> Vec<(usize, Vec<Value>)> {
+        let Some(clustered) = &self.clustered else {
+            return vec![];
+        };
+        let encode = |value: &Value| {
+            let mut key = EncodedKey::new();
+            key.push_value(value);
+            key
+        };
+        let lower = lower.map(encode);
+        let upper = upper.map(encode);
+        let first_page = match &lower {
+            Bound::Included(key) | Bound::Excluded(key) => clustered.page_for(key),
+            Bound::Unbounded => 0,
+        };
+        let key_position = clustered.key_position;
+        let rows_per_page = PAGE_SIZE / self.row_size();
+        let mut rows = vec![];
+        for page_number in first_page..self.page_entries.len() {
+            for slot in 0..self.page_entries[page_number].rows {
+                let row_number = page_number * rows_per_page + slot;
+                let row = self.read_row_values(row_number);
+                let key = encode(&row[key_position]);
+                let before_lower = match &lower {
+                    Bound::Included(lower) => key < *lower,
+                    Bound::Excluded(lower) => key <= *lower,
+                    Bound::Unbounded => false,
+                };
+                let past_upper = match &upper {
+                    Bound::Included(upper) => key > *upper,
+                    Bound::Excluded(upper) => key >= *upper,
+                    Bound::Unbounded => false,
+                };
+                if past_upper {
+                    return rows;
+                }
+                if !before_lower {
+                    rows.push((row_number, row));
+                }
+            }
+        }
+        rows
+    }
+
+    //`value` converted to the column's type when the key order of the result agrees
+    //with how the WHERE clause compares them, None when the bound can't be used.
+    fn key_value(&self, position: usize, value: &Value) -> Option<Value> {
+        let data_type = &self.columns[position].data_type;
+        match (data_type, value) {
+            (DataType::STRING, Value::String(_)) | (DataType::UUID, Value::Uuid(_)) => Some(value.clone()),
+            (DataType::INT | DataType::FLOAT, Value::Int(_) | Value::Float(_) | Value::String(_)) => {
+                coerce(value.clone(), data_type).ok()
+            }
+            _ => None,
+        }
+    }
+
+    //Ranges resolved against the columns, with the bounds on the same key merged.
+    //An equality wins over other bounds, otherwise the first lower and upper bound are kept.
+    fn resolve_ranges(&self, ranges: &[KeyRange]) -> Vec<(usize, KeyRange)> {
+        let mut resolved: Vec<(usize, KeyRange)> = vec![];
+        for range in ranges {
+            let Some(position) = self.columns.iter().position(|c| c.name == range.column) else {
+                continue;
+            };
+            let convert = |bound: &Bound<Value>| match bound {
+                Bound::Included(value) => self.key_value(position, value).map_or(Bound::Unbounded, Bound::Included),
+                Bound::Excluded(value) => self.key_value(position, value).map_or(Bound::Unbounded, Bound::Excluded),
+                Bound::Unbounded => Bound::Unbounded,
+            };
+            let range = KeyRange {
+                lower: convert(&range.lower),
+                upper: convert(&range.upper),
+                ..range.clone()
+            };
+            if range.lower == Bound::Unbounded && range.upper == Bound::Unbounded {
+                continue;
+            }
+            match resolved.iter_mut().find(|(p, r)| *p == position && r.function == range.function) {
+                Some((_, existing)) if existing.equal_value().is_some() => {}
+                Some((_, existing)) if range.equal_value().is_some() => *existing = range,
+                Some((_, existing)) => {
+                    if existing.lower == Bound::Unbounded {
+                        existing.lower = range.lower;
+                    }
+                    if existing.upper == Bound::Unbounded {
+                        existing.upper = range.upper;
+                    }
+                }
+                None => resolved.push((position, range)),
+            }
+        }
+        resolved
+    }
+
+    //Row numbers from the index that pins down the most key columns: equalities on a
+    //prefix of its key, then a range on the next column for B+trees. Hash indexes need
+    //an equality on every key column and partial indexes an equality implying their predicate.
+    fn index_lookup(&self, ranges: &[(usize, KeyRange)]) -> Option<Vec<usize>> {
+        let equalities: Vec<Predicate> = ranges
+            .iter()
+            .filter(|(_, range)| range.function.is_none())
+            .filter_map(|(position, range)| {
+                range.equal_value().map(|value| Predicate { position: *position, value: value.clone() })
+            })
+            .collect();
+        let mut best: Option<(usize, &Index, Vec<Value>, Option<&KeyRange>)> = None;
+        for index in self.indexes.iter() {
+            if index.method() == IndexMethod::FULLTEXT || !index.usable_for(&equalities) {
+                continue;
+            }
+            let mut prefix = vec![];
+            let mut next = None;
+            for nth in 0..index.positions().len() {
+                let Some((_, range)) = ranges.iter().find(|(position, range)| index.matches_key(nth, *position, range.function)) else {
+                    break;
+                };
+                match range.equal_value() {
+                    Some(value) => prefix.push(value.clone()),
+                    None => {
+                        next = Some(range);
+                        break;
+                    }
+                }
+            }
+            let usable = match index.method() {
+                IndexMethod::HASH => prefix.len() == index.positions().len(),
+                _ => !prefix.is_empty() || next.is_some(),
+            };
+            let score = prefix.len() * 2 + next.is_some() as usize;
+            if usable && best.as_ref().is_none_or(|(best, ..)| score > *best) {
+                best = Some((score, index, prefix, next));
+            }
+        }
+        let (_, index, prefix, next) = best?;
+        let mut row_numbers = match next {
+            Some(range) => index.scan_range(&prefix, range.lower.as_ref(), range.upper.as_ref()),
+            None => index.lookup_prefix(&prefix),
+        };
+        row_numbers.sort();
+        Some(row_numbers)
+    }
+
+    //Row numbers and values of the rows that may satisfy every range, found through the
+    //clustered key, an index or the zone maps. Clustered tables number their rows in key order.
+    fn candidates(&mut self, ranges: &[KeyRange]) -> Vec<(usize, Vec<Value>)> {
+        let ranges = self.resolve_ranges(ranges);
+        let key_position = self.clustered.as_ref().map(|clustered| clustered.key_position);
+        if let Some(key_position) = key_position
+            && let Some((_, key)) = ranges
+                .iter()
+                .find(|(position, range)| *position == key_position && range.function.is_none())
+        {
+            return self.scan_primary_key(key.lower.as_ref(), key.upper.as_ref());
+        }
+        if let Some(row_numbers) = self.index_lookup(&ranges) {
+            return row_numbers
+                .into_iter()
+                .map(|row_number| (row_number, self.read_row_values(row_number)))
+                .collect();
+        }
+        let plain: Vec<(usize, KeyRange)> = ranges.into_iter().filter(|(_, range)| range.function.is_none()).collect();
+        self.scan_pages(|zone| {
+            plain.iter().all(|(position, range)| match range.equal_value() {
+                Some(value) => zone.may_contain(*position, value),
+                None => zone.may_overlap(*position, range.lower.as_ref(), range.upper.as_ref()),
+            })
+        })
+    }
+
+    //Rows that may satisfy every range. The caller still has to check its WHERE clause against each row.
+    pub fn filter_rows(&mut self, ranges: &[KeyRange]) -> Vec<Vec<Value>> {
+        self.candidates(ranges).into_iter().map(|(_, row)| row).collect()
     }
 
     pub fn insert_rows(&mut self, values: Vec<&[u8]>) -> Result<(), Box<dyn Error>> {
         for value in values {
+            if self.is_clustered() {
+                self.insert_clustered(value)?;
+                continue;
+            }
+            let row_values = self.decode_row(value);
+            for index in self.indexes.iter() {
+                index.check_row(&row_values)?;
+            }
             let row_size = self.row_size();
             let rows_per_page = PAGE_SIZE / row_size;
-            let current_page = self.total_rows / rows_per_page;
-            let page = self.get_page(current_page);
-            page.write_row(value)?;
-            self.flush_page_to_disk(current_page);
+            //Slots freed by DELETE are filled before the table grows.
+            let row_number = match self.take_free_slot() {
+                Some(slot) => {
+                    self.update_row(slot, value)?;
+                    slot
+                }
+                None => {
+                    let page = self.get_page(self.row_slots / rows_per_page);
+                    page.write_row(value)?;
+                    self.flush_page_to_disk(self.row_slots / rows_per_page);
+                    self.row_slots += 1;
+                    self.row_slots - 1
+                }
+            };
+            for index in self.indexes.iter_mut() {
+                index.insert_row(row_number, &row_values)?;
+            }
+            self.add_to_zone_map(row_number / rows_per_page, &row_values);
             self.total_rows += 1;
         }
         Ok(())
     }
 
+    pub fn decode_row(&self, row: &[u8]) -> Vec<Value> {
+        let mut values = vec![];
+        let mut offset = 0;
+        for column in self.columns.iter() {
+            values.push(decode_field(&column.data_type, &row[offset..offset + column.size]));
+            offset += column.size;
+        }
+        values
+    }
+
+    pub fn read_row_values(&mut self, row_number: usize) -> Vec<Value> {
+        let rows_per_page = PAGE_SIZE / self.row_size();
+        let page = self.get_page(row_number / rows_per_page);
+        let row = page.read_row(row_number % rows_per_page).to_vec();
+        self.decode_row(&row)
+    }
+
+    pub fn indexes(&self) -> &[Index] {
+        &self.indexes
+    }
+
+    fn column_positions(&self, columns: &[String]) -> Result<Vec<usize>, String> {
+        let mut positions = vec![];
+        for column_name in columns.iter() {
+            match self.columns.iter().position(|c| &c.name == column_name) {
+                Some(position) => positions.push(position),
+                None => return Err(format!("Column {} not found", column_name)),
+            }
+        }
+        Ok(positions)
+    }
+
+    pub fn create_index(&mut self, definition: IndexDefinition) -> Result<(), String> {
+        if self.is_clustered() {
+            return Err("Indexes on clustered tables are not supported".to_string());
+        }
+        if self.indexes.iter().any(|index| index.name == definition.name) {
+            return Err(format!("Index {} already exists", definition.name));
+        }
+        if definition.name.len() > INDEX_NAME_SIZE {
+            return Err(format!("Index name {} is longer than {} bytes", definition.name, INDEX_NAME_SIZE));
+        }
+        let index = self.resolve_index(definition, None)?;
+        let rows: Vec<(usize, Vec<Value>)> = self
+            .row_numbers()
+            .into_iter()
+            .map(|row_number| (row_number, self.read_row_values(row_number)))
+            .filter(|(_, row)| !is_deleted(row))
+            .collect();
+        self.indexes.push(Index::build(index, rows)?);
+        Ok(())
+    }
+
+    //An index stored in its own pages, opened from `root` without reading the rows.
+    fn load_index(&mut self, definition: IndexDefinition, root: usize) -> Result<(), String> {
+        let index = self.resolve_index(definition, Some(root))?;
+        self.indexes.push(index);
+        Ok(())
+    }
+
+    //The index for the definition with its columns looked up in the table.
+    fn resolve_index(&self, definition: IndexDefinition, root: Option<usize>) -> Result<Index, String> {
+        let positions = self.column_positions(&definition.columns)?;
+        let included_positions = self.column_positions(&definition.included)?;
+        if definition.method == IndexMethod::FULLTEXT
+            && (positions.len() != 1
+                || !matches!(self.columns[positions[0]].data_type, DataType::STRING)
+                || definition.functions.iter().any(|f| f.is_some())
+                || !included_positions.is_empty())
+        {
+            return Err("Full-text indexes need a single STRING column".to_string());
+        }
+        let predicate = match &definition.predicate {
+            Some((column, value)) => {
+                let position = self.column_positions(std::slice::from_ref(column))?[0];
+                let value = coerce(value.clone(), &self.columns[position].data_type)?;
+                Some(Predicate { position, value })
+            }
+            None => None,
+        };
+        Ok(Index::new(definition, positions, included_positions, predicate, &self.pager(), root))
+    }
+
+    pub fn drop_index(&mut self, index_name: &str) {
+        if let Some(position) = self.indexes.iter().position(|index| index.name == index_name) {
+            self.indexes.remove(position).release();
+        }
+    }
+
     fn flush_page_to_disk(&mut self, page_index: usize) {
-        let data_base = self.data_base.upgrade().unwrap();
-        let file_handle = &mut data_base.file_handle.borrow_mut();
         let p = match &self.pages[page_index] {
             Some(page) => page,
             //To be handled properly
             None => return,
         };
-        file_handle
-            .seek(io::SeekFrom::Start(
-                ((page_index * PAGE_SIZE) + self.start_offset) as u64,
-            ))
-            .unwrap();
-        file_handle
-            .write_all(&p.data[0..(p.current_row * p.row_size)])
-            .unwrap();
-        file_handle.flush().unwrap();
-    }
-
-    pub fn print_table(&mut self, columns: HashSet<String>) {
-        let row_size = self.row_size();
-        if row_size == 0 || PAGE_SIZE < row_size {
-            return;
+        let entry = &mut self.page_entries[page_index];
+        self.data_base.upgrade().unwrap().pager.borrow_mut().write(entry.page_id, &p.data);
+        if entry.rows != p.current_row {
+            entry.rows = p.current_row;
+            self.directory_dirty = true;
         }
-        let rows_per_page = PAGE_SIZE / row_size;
-        let columns_meta: Vec<(String, usize, ColumnType)> = self
-            .columns
+    }
+
+    pub fn scan_rows(&mut self) -> Vec<Vec<Value>> {
+        self.row_numbers()
+            .into_iter()
+            .map(|row_number| self.read_row_values(row_number))
+            .filter(|row| !is_deleted(row))
+            .collect()
+    }
+
+    pub fn fulltext_index(&self, position: usize) -> Option<&Index> {
+        self.indexes
             .iter()
-            .map(|c| (c.name.clone(), c.size, c.col_type.clone()))
+            .find(|index| index.method() == IndexMethod::FULLTEXT && index.positions()[0] == position)
+    }
+
+    pub fn covering_index(&self, positions: &[usize]) -> Option<&Index> {
+        if positions.is_empty() {
+            return None;
+        }
+        self.indexes.iter().find(|index| index.covers(positions, &[]))
+    }
+
+    //Selected column names and values, read from a covering index when one holds
+    //every selected column so that no table page is touched.
+    pub fn select_rows(&mut self, columns: &HashSet<String>) -> (Vec<String>, Vec<Vec<Value>>) {
+        let (positions, header) = self.selected_columns(columns);
+        if let Some(index) = self.covering_index(&positions) {
+            return (header, index.scan_covered(&positions));
+        }
+        let rows = self
+            .scan_rows()
+            .into_iter()
+            .map(|row| positions.iter().map(|p| row[*p].clone()).collect())
             .collect();
+        (header, rows)
+    }
+
+    fn selected_columns(&self, columns: &HashSet<String>) -> (Vec<usize>, Vec<String>) {
+        let select_all = columns.contains("*");
+        let positions: Vec<usize> = (0..self.columns.len())
+            .filter(|p| select_all || columns.contains(&self.columns[*p].name))
+            .collect();
+        let header = positions
+            .iter()
+            .map(|p| self.columns[*p].name.clone())
+            .collect();
+        (positions, header)
+    }
+
+    //Rows whose column matches the full-text query, most relevant first.
+    pub fn match_rows(
+        &mut self,
+        columns: &HashSet<String>,
+        text_match: &TextMatch,
+    ) -> Result<(Vec<String>, Vec<Vec<Value>>), String> {
+        let position = self.column_positions(std::slice::from_ref(&text_match.column))?[0];
+        let ranked = match self.fulltext_index(position) {
+            Some(index) => index.search(&text_match.query),
+            None => return Err(format!("No full-text index on column {}", text_match.column)),
+        };
+        let (positions, header) = self.selected_columns(columns);
+        let rows = ranked
+            .into_iter()
+            .map(|(row_number, _)| {
+                let row = self.read_row_values(row_number);
+                positions.iter().map(|p| row[*p].clone()).collect()
+            })
+            .collect();
+        Ok((header, rows))
+    }
+
+    pub fn print_table(&mut self, columns: HashSet<String>, text_match: Option<TextMatch>) -> Result<(), String> {
+        let row_size = self.row_size();
+        if row_size == 0 || PAGE_SIZE < row_size {
+            return Ok(());
+        }
         println!("Table: {} [{} rows]", self.table_name, self.total_rows);
-        let mut row_values: Vec<Vec<String>> = vec![];
-        let mut table_meta: Vec<String> = vec![];
-        for (col_name, _, _) in &columns_meta {
-            if columns.contains(&("*".to_string())) || columns.contains(&col_name[..]) {
-                table_meta.push(col_name.clone());
+        let (header, rows) = match &text_match {
+            Some(text_match) => self.match_rows(&columns, text_match)?,
+            None => self.select_rows(&columns),
+        };
+        println!("total rows {}", self.total_rows);
+        print_rows(header, &rows);
+        Ok(())
+    }
+
+    pub fn column_names(&self) -> Vec<String> {
+        self.columns.iter().map(|column| column.name.clone()).collect()
+    }
+
+    pub fn update_row(&mut self, row_number: usize, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let row_size = self.row_size();
+        let rows_per_page = PAGE_SIZE / row_size;
+        let page_number = row_number / rows_per_page;
+        let page = self.get_page(page_number);
+        let row_index = row_number % rows_per_page;
+        page.update_row(row_index, data)?;
+        self.flush_page_to_disk(page_number);
+        Ok(())
+    }
+
+    //Deletes the candidate rows for which `matches` holds. Deleted rows of a heap table leave a
+    //zeroed slot behind that the next insert fills, clustered tables close the gap on the page.
+    //Returns the number of rows deleted.
+    pub fn delete_rows(
+        &mut self,
+        ranges: &[KeyRange],
+        mut matches: impl FnMut(&[Value]) -> Result<bool, String>,
+    ) -> Result<usize, String> {
+        let mut deleted = vec![];
+        for (row_number, row) in self.candidates(ranges) {
+            if matches(&row)? {
+                deleted.push((row_number, row));
             }
         }
-        row_values.push(table_meta);
-        println!("total rows {}", self.total_rows);
-        for row_number in 0..self.total_rows {
+        if self.is_clustered() {
+            //Going from the back keeps the row numbers of the rows still to delete valid.
+            for (row_number, _) in deleted.iter().rev() {
+                self.remove_clustered_row(*row_number);
+            }
+            return Ok(deleted.len());
+        }
+        let tombstone = vec![0; self.row_size()];
+        let rows_per_page = PAGE_SIZE / self.row_size();
+        for (row_number, row) in deleted.iter() {
+            self.update_row(*row_number, &tombstone).map_err(|e| e.to_string())?;
+            for index in self.indexes.iter_mut() {
+                index.remove_row(*row_number, row);
+            }
             let page_number = row_number / rows_per_page;
-            let row_index = row_number % rows_per_page;
-            let page = self.get_page(page_number);
-            let row = page.read_row(row_index);
-
-            let mut offset = 0;
-            let mut field_values: Vec<String> = vec![];
-            for (name, size, col_type) in &columns_meta {
-                let end = offset + *size;
-                if end > row.len() {
-                    break;
-                }
-                let field = &row[offset..end];
-                offset = end;
-
-                let trim_len = field
-                    .iter()
-                    .rposition(|&b| b != 0)
-                    .map(|i| i + 1)
-                    .unwrap_or(0);
-                let bytes = &field[..trim_len];
-                if !columns.contains(&("*".to_string())) && !columns.contains(name) {
-                    continue;
-                }
-                match col_type {
-                    ColumnType::ID => {
-                        if let Ok(value) = Uuid::from_slice(bytes) {
-                            field_values.push(value.hyphenated().to_string());
-                        } else {
-                            field_values.push(String::from(""));
-                        }
-                    }
-                    ColumnType::FIELD => {
-                        let value: std::borrow::Cow<'_, str> = String::from_utf8_lossy(bytes);
-                        field_values.push(value.to_string());
-                    }
-                }
+            self.page_entries[page_number].deleted += 1;
+            self.directory_dirty = true;
+            //Slots of a page that wasn't read for its free slots yet are found when it is.
+            if !self.pages_with_deleted_rows.contains(&page_number) {
+                self.free_slots.push(*row_number);
             }
-            row_values.push(field_values.clone());
-        }
-        print_table!(row_values.clone());
-    }
-
-    // pub fn update_row(&mut self, row_number: usize, data: &[u8]) -> Result<(), Box<dyn Error>> {
-    //     let row_size = self.row_size();
-    //     let rows_per_page = PAGE_SIZE / row_size;
-    //     let page_number = row_number / rows_per_page;
-    //     let page = self.get_page(page_number);
-    //     let row_index = row_number % rows_per_page;
-    //     page.update_row(row_index, data)?;
-    //     self.flush_page_to_disk(page_number);
-    //     Ok(())
-    // }
-    pub fn construct_row(&mut self, args: Vec<String>) -> Vec<u8> {
-        // TO be refactored
-        let mut field_buffers = self
-            .columns
+            self.total_rows -= 1;
+        }
+        Ok(deleted.len())
+    }
+
+    //Drops every row while keeping the columns and index definitions.
+    //The table's data pages go back to the pager, the directory page is kept.
+    pub fn truncate(&mut self) {
+        let pages: Vec<usize> = self.page_entries.drain(..).map(|entry| entry.page_id).collect();
+        self.pager().borrow_mut().free(pages);
+        self.directory_dirty = true;
+        self.pages.clear();
+        self.zone_maps.clear();
+        self.free_slots.clear();
+        self.pages_with_deleted_rows.clear();
+        self.total_rows = 0;
+        self.row_slots = 0;
+        if let Some(clustered) = self.clustered.as_mut() {
+            clustered.first_keys.clear();
+        }
+        for index in self.indexes.iter_mut() {
+            index.clear();
+        }
+    }
+
+    //On-disk form of a whole row, id included.
+    fn encode_row(&self, values: &[Value]) -> Vec<u8> {
+        self.columns
             .iter()
-            .map(|col: &Column| vec![0; col.size])
-            .collect::<Vec<Vec<u8>>>();
+            .zip(values.iter())
+            .flat_map(|(column, value)| encode_field(value, column.size))
+            .collect()
+    }
+
+    //Sets `columns` of every candidate row for which `values_for` returns new values.
+    //All new rows are computed and checked before anything is written, so an error
+    //leaves the table as it was. Returns the number of rows updated.
+    pub fn update_rows(
+        &mut self,
+        ranges: &[KeyRange],
+        columns: &[String],
+        mut values_for: impl FnMut(&[Value]) -> Result<Option<Vec<Value>>, String>,
+    ) -> Result<usize, String> {
+        let positions = self.column_positions(columns)?;
+        if let Some(position) = positions.iter().find(|p| self.columns[**p].col_type == ColumnType::ID) {
+            return Err(format!("Column {} can't be updated", self.columns[*position].name));
+        }
+        let mut updates = vec![];
+        for (row_number, row) in self.candidates(ranges) {
+            let Some(values) = values_for(&row)? else {
+                continue;
+            };
+            let mut new_row = row.clone();
+            for (position, value) in positions.iter().zip(values) {
+                new_row[*position] = coerce(value, &self.columns[*position].data_type)?;
+            }
+            for index in self.indexes.iter() {
+                index.check_row(&new_row)?;
+            }
+            updates.push((row_number, row, new_row));
+        }
+        if let Some(clustered) = &self.clustered {
+            //Rows keeping their key are written in place, the others are deleted and inserted again.
+            let (moved, in_place): (Vec<_>, Vec<_>) = updates
+                .iter()
+                .partition(|(_, old_row, new_row)| clustered.key_for(old_row) != clustered.key_for(new_row));
+            let old_keys: Vec<EncodedKey> = moved.iter().map(|(_, row, _)| clustered.key_for(row)).collect();
+            let new_keys: Vec<(EncodedKey, &Vec<Value>)> =
+                moved.iter().map(|(_, _, row)| (clustered.key_for(row), row)).collect();
+            let key_position = clustered.key_position;
+            for (n, (key, row)) in new_keys.iter().enumerate() {
+                let taken = new_keys[..n].iter().any(|(other, _)| other == key)
+                    || (!old_keys.contains(key) && self.find_clustered_row(key).is_some());
+                if taken {
+                    return Err(format!("Duplicate primary key {:?}", row[key_position]));
+                }
+            }
+            let rows_per_page = PAGE_SIZE / self.row_size();
+            for (row_number, _, new_row) in in_place.iter() {
+                self.update_row(*row_number, &self.encode_row(new_row)).map_err(|e| e.to_string())?;
+                self.add_to_zone_map(row_number / rows_per_page, new_row);
+            }
+            for (row_number, _, _) in moved.iter().rev() {
+                self.remove_clustered_row(*row_number);
+            }
+            for (_, _, new_row) in moved.iter() {
+                self.insert_clustered(&self.encode_row(new_row)).map_err(|e| e.to_string())?;
+            }
+            return Ok(updates.len());
+        }
+        let rows_per_page = PAGE_SIZE / self.row_size();
+        for (row_number, old_row, new_row) in updates.iter() {
+            self.update_row(*row_number, &self.encode_row(new_row)).map_err(|e| e.to_string())?;
+            for index in self.indexes.iter_mut() {
+                index.remove_row(*row_number, old_row);
+                index.insert_row(*row_number, new_row)?;
+            }
+            //Zone maps only have to cover every value of their page, so the old values can stay.
+            self.add_to_zone_map(row_number / rows_per_page, new_row);
+        }
+        Ok(updates.len())
+    }
+
+    pub fn construct_row(&mut self, args: Vec<Value>) -> Vec<u8> {
         let id: Uuid = Uuid::now_v7();
         let mut row_data = vec![];
         row_data.extend_from_slice(id.as_bytes());
         for (i, arg) in args.iter().enumerate() {
-            let bytes = arg.as_bytes();
-            let buffer: &mut Vec<u8> = &mut field_buffers[i+1];
-            let len = bytes.len().min(buffer.len());
-            buffer[0..len].copy_from_slice(&bytes[0..len]);
-            row_data.extend_from_slice(buffer);
+            row_data.extend_from_slice(&encode_field(arg, self.columns[i + 1].size));
         }
         row_data
     }
 
-    pub fn match_columns(&self, column_map: HashMap<String, String>) -> Vec<String> {
-        let mut row_values: Vec<String> = vec![];
+    //Values for every field column in table order, converted to the column types.
+    //Columns missing from the insert get their type's default.
+    pub fn match_columns(&self, column_map: HashMap<String, Value>) -> Result<Vec<Value>, String> {
+        let mut row_values: Vec<Value> = vec![];
         for column in &self.columns {
-            let buffer = match column.col_type {
-                ColumnType::FIELD => Some(
-                    column_map
-                        .get(&column.name)
-                        .unwrap_or(&(|| match column.data_type {
-                            DataType::INT => INT_DEFAULT.to_string(),
-                            DataType::STRING => STRING_DEFAULT.to_string(),
-                            _ => STRING_DEFAULT.to_string(),
-                        })())
-                        .clone(),
-                ),
-                ColumnType::ID => None,
-            };
-            if let Some(value) = buffer {
-                row_values.push(value);
+            if let ColumnType::ID = column.col_type {
+                continue;
             }
+            let value = match column_map.get(&column.name) {
+                Some(value) => value.clone(),
+                None => match column.data_type {
+                    DataType::INT => INT_DEFAULT,
+                    DataType::FLOAT => FLOAT_DEFAULT,
+                    _ => Value::String(STRING_DEFAULT.to_string()),
+                },
+            };
+            row_values.push(coerce(value, &column.data_type)?);
         }
-        row_values
+        Ok(row_values)
     }
 
     fn get_table_meta(&mut self) -> Vec<u8> {
@@ -363,16 +1292,16 @@ impl Table {
         table_info_size += 43;
         table_info_size += 33 * self.columns.len();
 
-        let mut buff: Vec<u8> = Vec::new();
-        buff.resize(table_info_size, 0u8);
+        let mut buff: Vec<u8> = vec![0u8; table_info_size];
 
         let mut table_name_buff = [0u8; 32];
         table_name_buff[0..self.table_name.len()].copy_from_slice(self.table_name.as_bytes());
         buff[0..32].copy_from_slice(&table_name_buff);
 
         buff[32] = self.columns.len() as u8;
-        buff[33..35].copy_from_slice(&(self.total_rows as u16).to_le_bytes());
-        buff[35..43].copy_from_slice(&(self.start_offset as u64).to_le_bytes());
+        //The row count on disk covers every slot, deleted rows are found again when the table is loaded.
+        buff[33..35].copy_from_slice(&(self.row_slots as u16).to_le_bytes());
+        buff[35..43].copy_from_slice(&(self.directory_page as u64).to_le_bytes());
         let mut offset = 43;
 
         for column in self.columns.iter() {
@@ -384,30 +1313,70 @@ impl Table {
                 DataType::UUID => 0,
                 DataType::STRING => 1,
                 DataType::INT => 2,
+                DataType::FLOAT => 3,
             };
+            if let Some(clustered) = &self.clustered
+                && self.columns[clustered.key_position].name == column.name
+            {
+                buff[offset + 32] |= PRIMARY_KEY_FLAG;
+            }
             offset += 33;
         }
         buff
     }
+
+    fn get_index_meta(&self) -> Vec<u8> {
+        let mut buff: Vec<u8> = Vec::new();
+        for index in self.indexes.iter() {
+            let mut index_name_buff = [0u8; INDEX_NAME_SIZE];
+            index_name_buff[0..index.name.len()].copy_from_slice(index.name.as_bytes());
+            buff.extend_from_slice(&index_name_buff);
+            let mut table_name_buff = [0u8; TABLE_NAME_SIZE];
+            table_name_buff[0..self.table_name.len()].copy_from_slice(self.table_name.as_bytes());
+            buff.extend_from_slice(&table_name_buff);
+            buff.push(index.positions().len() as u8);
+            for (position, function) in index.positions().iter().zip(index.functions.iter()) {
+                buff.push(*position as u8);
+                buff.push(match function {
+                    None => 0,
+                    Some(KeyFunction::LOWER) => 1,
+                    Some(KeyFunction::UPPER) => 2,
+                });
+            }
+            buff.push(index.included_positions().len() as u8);
+            for position in index.included_positions() {
+                buff.push(*position as u8);
+            }
+            match &index.predicate {
+                Some(predicate) => {
+                    buff.push(1);
+                    buff.push(predicate.position as u8);
+                    write_value(&mut buff, &predicate.value);
+                }
+                None => buff.push(0),
+            }
+            buff.push(match index.method() {
+                IndexMethod::BTREE => 0,
+                IndexMethod::HASH => 1,
+                IndexMethod::FULLTEXT => 2,
+            });
+            buff.extend_from_slice(&(index.root().unwrap_or(0) as u32).to_le_bytes());
+        }
+        buff
+    }
 }
 
 pub struct DataBase {
     pub tables: RefCell<HashMap<String, Rc<RefCell<Table>>>>,
     pub num_tables: RefCell<u8>,
-    file_handle: RefCell<fs::File>,
+    pub pager: Rc<RefCell<Pager>>,
 }
 
 impl DataBase {
-    pub fn new(file_name: String) -> Rc<Self> {
+    pub fn new(file_name: String) -> Result<Rc<Self>, String> {
         let tables: RefCell<HashMap<String, Rc<RefCell<Table>>>> = RefCell::new(HashMap::new());
-        let mut file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(Path::new(&file_name))
-            .expect("Failed to open file");
-        let mut table_meta = [0; META_SIZE];
-        let _ = file.read(&mut table_meta).unwrap();
+        let mut pager = Pager::open(Path::new(&file_name))?;
+        let table_meta = pager.read_catalog();
 
         let num_tables = table_meta[0];
 
@@ -416,8 +1385,9 @@ impl DataBase {
         let mut offset: usize = 1;
         let database = Rc::new(DataBase {
             tables,
-            num_tables: RefCell::new(num_tables),
-            file_handle: RefCell::new(file),
+            //Counted up again as the stored tables are added below.
+            num_tables: RefCell::new(0),
+            pager: Rc::new(RefCell::new(pager)),
         });
         while i < num_tables {
             let table_name: String =
@@ -438,10 +1408,15 @@ impl DataBase {
             );
             offset += TABLE_DATA_LOCATION_SIZE;
             let mut colums = vec![];
+            let mut clustered_key = None;
 
             while num_columns > 0 {
                 let column_name = &table_meta[offset..offset + COLUMN_NAME_SIZE];
-                let column_type: u8 = table_meta[offset + COLUMN_NAME_SIZE];
+                let mut column_type: u8 = table_meta[offset + COLUMN_NAME_SIZE];
+                if column_type & PRIMARY_KEY_FLAG != 0 {
+                    column_type &= !PRIMARY_KEY_FLAG;
+                    clustered_key = Some(String::from_utf8_lossy(column_name).replace('\0', "").trim().to_string());
+                }
                 let column: Column = match column_type {
                     0 => Column {
                         name: String::from_utf8_lossy(column_name).replace('\0', "").trim().to_string(),
@@ -461,16 +1436,73 @@ impl DataBase {
                         col_type: ColumnType::FIELD,
                         data_type: DataType::INT,
                     },
+                    3 => Column {
+                        name: String::from_utf8_lossy(column_name).replace('\0', "").trim().to_string(),
+                        size: FLOAT_SIZE,
+                        col_type: ColumnType::FIELD,
+                        data_type: DataType::FLOAT,
+                    },
                     _ => panic!("Invalid column type"),
                 };
                 colums.push(column);
                 offset += COLUMN_NAME_SIZE + COLUMN_TYPE_META;
                 num_columns -= 1;
             }
-            database.add_table(table_name, colums, total_rows as usize,Some(start_index as usize), false);
+            database.add_table(table_name, colums, total_rows as usize,Some(start_index as usize), clustered_key, false).unwrap();
             i+=1;
         }
-        database
+        let num_indexes = table_meta[offset];
+        offset += 1;
+        for _ in 0..num_indexes {
+            let index_name: String =
+                String::from_utf8_lossy(&table_meta[offset..offset + INDEX_NAME_SIZE]).replace('\0', "").trim().to_string();
+            offset += INDEX_NAME_SIZE;
+            let table_name: String =
+                String::from_utf8_lossy(&table_meta[offset..offset + TABLE_NAME_SIZE]).replace('\0', "").trim().to_string();
+            offset += TABLE_NAME_SIZE;
+            let tables = database.tables.borrow();
+            let mut table = tables.get(&table_name).expect("Index on unknown table").borrow_mut();
+            let column_name = |position: u8| table.columns[position as usize].name.clone();
+            let mut definition = IndexDefinition { name: index_name, ..Default::default() };
+            let num_columns = table_meta[offset] as usize;
+            offset += TOTAL_INDEX_COLUMNS_SIZE;
+            for _ in 0..num_columns {
+                definition.columns.push(column_name(table_meta[offset]));
+                definition.functions.push(match table_meta[offset + 1] {
+                    1 => Some(KeyFunction::LOWER),
+                    2 => Some(KeyFunction::UPPER),
+                    _ => None,
+                });
+                offset += INDEX_COLUMN_META;
+            }
+            let num_included = table_meta[offset] as usize;
+            offset += TOTAL_INDEX_COLUMNS_SIZE;
+            for _ in 0..num_included {
+                definition.included.push(column_name(table_meta[offset]));
+                offset += 1;
+            }
+            if table_meta[offset] == 1 {
+                let position = table_meta[offset + 1];
+                offset += 2;
+                definition.predicate = Some((column_name(position), read_value(&table_meta, &mut offset)));
+            } else {
+                offset += 1;
+            }
+            definition.method = match table_meta[offset] {
+                1 => IndexMethod::HASH,
+                2 => IndexMethod::FULLTEXT,
+                _ => IndexMethod::BTREE,
+            };
+            offset += 1;
+            let root = u32::from_le_bytes(table_meta[offset..offset + INDEX_ROOT_SIZE].try_into().unwrap()) as usize;
+            offset += INDEX_ROOT_SIZE;
+            //Indexes without pages of their own are built from the rows again.
+            match root {
+                0 => table.create_index(definition)?,
+                root => table.load_index(definition, root)?,
+            }
+        }
+        Ok(database)
     }
 
     pub fn add_table(
@@ -478,61 +1510,72 @@ impl DataBase {
         table_name: String,
         columns: Vec<Column>,
         total_rows:usize,
-        start_offset: Option<usize>,
+        directory_page: Option<usize>,
+        clustered_key: Option<String>,
         flush:bool
-    ) {
+    ) -> Result<(), String> {
         let weak_db: Weak<DataBase> = Rc::downgrade(self);
-        let table_start_offset = match start_offset {
-            Some(offset) => offset,
-            None => {
-                let tables = self.tables.borrow();
-                let mut occupied_page = 0;
-                for table in tables.values() {
-                    let table_borrow = Rc::clone(table);
-                    let table_pages = ((table_borrow.borrow().total_rows / PAGE_SIZE) as f64).ceil() as usize;
-                    occupied_page += table_pages;
-                }
-                occupied_page + META_SIZE
-            }
-        };
         let table: Table = Table::new(
                 table_name.clone(),
                 columns,
                 weak_db,
                 total_rows,
-                table_start_offset,
+                directory_page,
+                clustered_key,
             );
-        self.create_table(table, flush ).unwrap();
+        self.create_table(table, flush)
     }
 
     fn create_table(self: &Rc<Self>, table: Table, flush:bool) -> Result<(), String> {
+        let table_name = table.table_name.clone();
         self.tables
             .borrow_mut()
             .insert(table.table_name.clone(), Rc::new(RefCell::new(table)));
         *self.num_tables.borrow_mut() += 1;
-        if flush {
-            self.flush();
+        if flush && let Err(error) = self.flush() {
+            if let Some(table) = self.tables.borrow_mut().remove(&table_name) {
+                table.borrow_mut().release();
+            }
+            *self.num_tables.borrow_mut() -= 1;
+            return Err(error);
         }
         Ok(())
     }
 
-    pub fn flush(self: &Rc<Self>) {
-        let mut buff = [0; META_SIZE];
-        buff[0] = *self.num_tables.borrow();
-        let mut offset = 1;
+    //Removes the table and its indexes from the catalog and gives their pages back to the pager.
+    pub fn drop_table(self: &Rc<Self>, table_name: &str) -> Result<(), String> {
+        let Some(table) = self.tables.borrow_mut().remove(table_name) else {
+            return Err(String::from("Table not found"));
+        };
+        table.borrow_mut().release();
+        *self.num_tables.borrow_mut() -= 1;
+        self.flush()
+    }
+
+    //Writes the table directories and the catalog, which has to fit in the first page after the header.
+    pub fn flush(self: &Rc<Self>) -> Result<(), String> {
+        let mut buff = vec![*self.num_tables.borrow()];
         println!("Number of tables: {}", *self.num_tables.borrow());
         println!("Tables: {:?}", self.tables.borrow().keys());
         for table in self.tables.borrow_mut().values_mut() {
             let table_borrow = Rc::clone(table);
+            table_borrow.borrow_mut().flush_directory();
             let table_meta: Vec<u8> = table_borrow.borrow_mut().get_table_meta();
-            buff[offset..offset + table_meta.len()].copy_from_slice(&table_meta);
-            offset += table_meta.len();
+            buff.extend(table_meta);
         }
-        self.file_handle
-            .borrow_mut()
-            .seek(io::SeekFrom::Start(0))
-            .unwrap();
-        self.file_handle.borrow_mut().write_all(&buff).unwrap();
-        self.file_handle.borrow_mut().flush().unwrap();
+        let mut num_indexes = 0;
+        let mut index_meta: Vec<u8> = vec![];
+        for table in self.tables.borrow().values() {
+            let table = table.borrow();
+            num_indexes += table.indexes.len();
+            index_meta.extend(table.get_index_meta());
+        }
+        buff.push(num_indexes as u8);
+        buff.extend(index_meta);
+        if buff.len() > CATALOG_SIZE {
+            return Err(format!("Catalog full, the tables and indexes need {} of its {} bytes", buff.len(), CATALOG_SIZE));
+        }
+        self.pager.borrow_mut().write_catalog(&buff);
+        Ok(())
     }
 }
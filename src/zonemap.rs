@@ -0,0 +1,171 @@
+use std::ops::Bound;
+
+use crate::key::EncodedKey;
+use crate::table::{DataType, Value};
+
+//512 bits and 3 probes keep false positives around 1% for a page of ~50 rows.
+const BLOOM_BITS: usize = 512;
+const BLOOM_HASHES: u64 = 3;
+
+fn encode(value: &Value) -> EncodedKey {
+    let mut key = EncodedKey::new();
+    key.push_value(value);
+    key
+}
+
+//FNV-1a, split into two halves for double hashing.
+fn hash(key: &[u8]) -> (u64, u64) {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in key {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    (hash & 0xFFFF_FFFF, (hash >> 32) | 1)
+}
+
+#[derive(Debug, Clone)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+}
+
+impl BloomFilter {
+    pub fn new() -> Self {
+        BloomFilter {
+            bits: vec![0; BLOOM_BITS / 64],
+        }
+    }
+
+    fn probes(key: &[u8]) -> impl Iterator<Item = usize> {
+        let (h1, h2) = hash(key);
+        (0..BLOOM_HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % BLOOM_BITS as u64) as usize)
+    }
+
+    pub fn insert(&mut self, key: &[u8]) {
+        for bit in Self::probes(key) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    //False means the key was never inserted, true only that it may have been.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        Self::probes(key).all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+}
+
+impl Default for BloomFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//Smallest and largest encoded value of a column within one page,
+//plus a Bloom filter of its values for STRING columns.
+#[derive(Debug, Clone, Default)]
+struct ColumnSummary {
+    min: Option<EncodedKey>,
+    max: Option<EncodedKey>,
+    bloom: Option<BloomFilter>,
+}
+
+//Per page summary kept beside the table pages so scans can skip
+//pages that cannot hold a row matching the WHERE clause.
+#[derive(Debug, Clone)]
+pub struct ZoneMap {
+    columns: Vec<ColumnSummary>,
+}
+
+impl ZoneMap {
+    pub fn new(data_types: &[DataType]) -> Self {
+        let columns = data_types
+            .iter()
+            .map(|data_type| ColumnSummary {
+                bloom: matches!(data_type, DataType::STRING).then(BloomFilter::new),
+                ..Default::default()
+            })
+            .collect();
+        ZoneMap { columns }
+    }
+
+    pub fn add_row(&mut self, row: &[Value]) {
+        for (summary, value) in self.columns.iter_mut().zip(row.iter()) {
+            let key = encode(value);
+            if let Some(bloom) = summary.bloom.as_mut() {
+                bloom.insert(key.as_bytes());
+            }
+            if summary.min.as_ref().is_none_or(|min| key < *min) {
+                summary.min = Some(key.clone());
+            }
+            if summary.max.as_ref().is_none_or(|max| key > *max) {
+                summary.max = Some(key);
+            }
+        }
+    }
+
+    //Whether some row of the page may have `value` in the column.
+    pub fn may_contain(&self, position: usize, value: &Value) -> bool {
+        let key = encode(value);
+        let summary = &self.columns[position];
+        if let Some(bloom) = &summary.bloom
+            && !bloom.may_contain(key.as_bytes())
+        {
+            return false;
+        }
+        self.may_overlap(position, Bound::Included(value), Bound::Included(value))
+    }
+
+    //Whether some row of the page may have the column within the bounds.
+    pub fn may_overlap(&self, position: usize, lower: Bound<&Value>, upper: Bound<&Value>) -> bool {
+        let summary = &self.columns[position];
+        let (Some(min), Some(max)) = (&summary.min, &summary.max) else {
+            return false;
+        };
+        let above_lower = match lower {
+            Bound::Included(value) => *max >= encode(value),
+            Bound::Excluded(value) => *max > encode(value),
+            Bound::Unbounded => true,
+        };
+        let below_upper = match upper {
+            Bound::Included(value) => *min <= encode(value),
+            Bound::Excluded(value) => *min < encode(value),
+            Bound::Unbounded => true,
+        };
+        above_lower && below_upper
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter_has_no_false_negatives() {
+        let mut bloom = BloomFilter::new();
+        for n in 0..50 {
+            bloom.insert(format!("user{}", n).as_bytes());
+        }
+        assert!((0..50).all(|n| bloom.may_contain(format!("user{}", n).as_bytes())));
+        let false_positives = (50..1050)
+            .filter(|n| bloom.may_contain(format!("user{}", n).as_bytes()))
+            .count();
+        assert!(false_positives < 50, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn test_zone_map_tracks_min_and_max() {
+        let mut zone = ZoneMap::new(&[DataType::INT, DataType::STRING]);
+        assert!(!zone.may_contain(0, &Value::Int(1)));
+        for (n, name) in [(-5, "bob"), (20, "ann"), (7, "cy")] {
+            zone.add_row(&[Value::Int(n), Value::String(name.to_string())]);
+        }
+        assert!(zone.may_contain(0, &Value::Int(7)));
+        assert!(!zone.may_contain(0, &Value::Int(21)));
+        assert!(!zone.may_contain(0, &Value::Int(-6)));
+        assert!(zone.may_contain(1, &Value::String("ann".to_string())));
+        assert!(!zone.may_contain(1, &Value::String("zed".to_string())));
+        assert!(zone.may_overlap(0, Bound::Excluded(&Value::Int(19)), Bound::Unbounded));
+        assert!(!zone.may_overlap(0, Bound::Excluded(&Value::Int(20)), Bound::Unbounded));
+        assert!(!zone.may_overlap(0, Bound::Unbounded, Bound::Excluded(&Value::Int(-5))));
+        assert!(zone.may_overlap(0, Bound::Unbounded, Bound::Included(&Value::Int(-5))));
+    }
+}